@@ -0,0 +1,22 @@
+# The _cli file describes the cli itself, rather than a command. Its top-level
+# tags set the metadata of the root command.
+
+# @version 1.0.0
+# @about An example cli built by easy-cli
+# @footer See ANNOTATIONS.md for the annotations easy-cli understands.
+# @opt verbose 'v' Print more output
+# @opt env true The environment to run against
+
+# Each @command tag defines a command that is not a script: an alias for another command line,
+# a line of shell code, or an executable. The tags that follow it configure the command.
+
+# @command greet alias multi one
+# @about Greets, via the multi command
+
+# @command today inline date +%F
+# @about Prints the date
+
+# @command listing binary ls -1
+# @about Lists files, one per line
+# @opt all 'a' Include hidden files
+# @vararg paths true <path> The paths to list
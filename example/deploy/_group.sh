@@ -0,0 +1,6 @@
+# The _group file describes the group of commands in its directory. Its options are global: they
+# can be given to every command in the group, and are passed to each script.
+
+# @about Deploys the example application
+# @alias dp
+# @opt stage 's' true The stage to deploy to
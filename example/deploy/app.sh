@@ -0,0 +1,7 @@
+#!/usr/bin/env sh
+
+# @about Deploys the application
+# @arg version The version to deploy
+
+# In executed mode, the options of the group are passed after those of the script
+echo "Deploying version $1 to stage $2"
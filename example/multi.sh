@@ -12,6 +12,7 @@
 # @about Prints a message
 # @opt option 'o' An option for this sub-command
 # @arg Message Will be printed
+# @example multi one -o "Hello"
 # This sub-command will accept one arguments
 one() {
   #Print "option set " if the second argument is 'true', "option not set" otherwise
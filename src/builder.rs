@@ -1,34 +1,99 @@
 use std::ops::{Range, RangeFrom, RangeTo};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 use nom::branch::alt;
 use nom::bytes::complete::tag_no_case;
 use nom::bytes::streaming::is_not;
 use nom::character::complete::anychar;
 use nom::character::streaming::{multispace0, not_line_ending, space0};
-use nom::combinator::{flat_map, iterator, map, opt, rest, value};
+use nom::combinator::{consumed, flat_map, iterator, map, not, opt, rest, value};
 use nom::error::ParseError;
 use nom::sequence::{delimited, pair, terminated, tuple};
 use nom::Err::{Error, Failure, Incomplete};
 use nom::{
-    sequence::preceded, Compare, IResult, InputIter, InputLength, InputTakeAtPosition, Parser,
-    Slice,
+    sequence::preceded, Compare, IResult, InputIter, InputLength, InputTakeAtPosition, Offset,
+    Parser, Slice,
 };
 
 use crate::model::ArgType::Unknown;
-use crate::model::{ArgType, Command, CommandArg, CommandOption, EmbeddedCommand, ScriptCommand};
+use crate::model::{
+    AliasCommand, ArgRelation, ArgType, AuditLog, BinaryCommand, CliMetadata, Command, CommandArg,
+    CommandOption, Confirmation, EmbeddedCommand, EnvVar, GroupCommand, Hook, Hooks, InlineCommand,
+    OptionKind, Retry, ScriptCommand, WithMeta,
+};
+use crate::transform::global_option_names;
 use crate::utils::strip_file_suffix;
 
-const TRUE: &'static str = "true";
-const FALSE: &'static str = "false";
-
-const IGNORE_TAG: &'static str = "ignore";
-const SUB_TAG: &'static str = "sub";
-const NAME_TAG: &'static str = "name";
-const ABOUT_TAG: &'static str = "about";
-const ARG_TAG: &'static str = "arg";
-const VAR_ARG_TAG: &'static str = "vararg";
-const OPT_TAG: &'static str = "opt";
+const TRUE: &str = "true";
+const FALSE: &str = "false";
+
+const IGNORE_TAG: &str = "ignore";
+const SUB_TAG: &str = "sub";
+const NAME_TAG: &str = "name";
+const ABOUT_TAG: &str = "about";
+const ARG_TAG: &str = "arg";
+const VAR_ARG_TAG: &str = "vararg";
+const OPT_TAG: &str = "opt";
+const VERSION_TAG: &str = "version";
+const AUTHOR_TAG: &str = "author";
+const FOOTER_TAG: &str = "footer";
+const AUDIT_TAG: &str = "audit";
+const DETAILS_TAG: &str = "details";
+const EXAMPLE_TAG: &str = "example";
+const COMMAND_TAG: &str = "command";
+const ALIAS_TAG: &str = "alias";
+const HIDDEN_TAG: &str = "hidden";
+const HEADING_TAG: &str = "heading";
+const PROMPT_TAG: &str = "prompt";
+const CONFIRM_TAG: &str = "confirm";
+const PRE_TAG: &str = "pre";
+const POST_TAG: &str = "post";
+const TIMEOUT_TAG: &str = "timeout";
+const RETRY_TAG: &str = "retry";
+const CONFLICTS_TAG: &str = "conflicts";
+const REQUIRES_TAG: &str = "requires";
+const GROUP_TAG: &str = "group";
+
+/// The words setting the kind of an option.
+const COUNT: &str = "count";
+const MANY: &str = "many";
+const NEGATABLE: &str = "negatable";
+
+/// The prefix of the name of the value of an option or argument, shown in help.
+const VALUE_NAME_PREFIX: &str = "value=";
+
+/// The prefix of the environment variable from which the value of an option or argument is taken.
+const ENV_PREFIX: &str = "env=";
+
+/// The word marking the value of an environment variable as secret.
+const SECRET: &str = "secret";
+
+/// The word turning the audit log off.
+const AUDIT_OFF: &str = "off";
+
+/// The word marking a group of options and arguments of which one must be given.
+const REQUIRED: &str = "required";
+
+/// The options clap adds to every command, and to the cli itself.
+const HELP_OPTION: &str = "help";
+const VERSION_OPTION: &str = "version";
+
+/// The stem of the file in the source directory that holds the metadata of the CLI itself.
+pub const CLI_METADATA_FILE: &str = "_cli";
+
+/// The stem of the file in a sub-directory of the source directory that describes the group of
+/// commands in it.
+pub const GROUP_FILE: &str = "_group";
+
+/// The name of the directory in the source directory holding the scripts run before and after
+/// every command.
+pub const HOOKS_DIR: &str = "_hooks";
+
+/// The stems of the hook scripts run before and after every command.
+const PRE_HOOK_FILE: &str = "pre";
+const POST_HOOK_FILE: &str = "post";
 
 #[derive(Debug, Clone, PartialEq)]
 struct NameTag {
@@ -45,11 +110,68 @@ impl NameTag {
 struct SubTag {
     name: String,
     path: Option<String>,
+    line: Option<usize>,
 }
 
 impl SubTag {
     fn new(name: String, path: Option<String>) -> Self {
-        SubTag { name, path }
+        SubTag {
+            name,
+            path,
+            line: None,
+        }
+    }
+
+    /// Records the line of the file on which the tag was found.
+    fn at_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+}
+
+/// The kinds of command that are defined by a `@command` tag, rather than by a script.
+#[derive(Debug, Clone, PartialEq)]
+enum CommandKind {
+    Alias,
+    Inline,
+    Binary,
+}
+
+impl FromStr for CommandKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alias" => Ok(CommandKind::Alias),
+            "inline" => Ok(CommandKind::Inline),
+            "binary" => Ok(CommandKind::Binary),
+            _ => Err(format!("Unknown command kind '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CommandTag {
+    name: String,
+    kind: CommandKind,
+    definition: String,
+    line: Option<usize>,
+}
+
+impl CommandTag {
+    fn new(name: String, kind: CommandKind, definition: String) -> Self {
+        CommandTag {
+            name,
+            kind,
+            definition,
+            line: None,
+        }
+    }
+
+    /// Records the line of the file on which the tag was found.
+    fn at_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
     }
 }
 
@@ -64,6 +186,18 @@ impl AboutTag {
     }
 }
 
+/// A tag whose value is simply the rest of the line.
+#[derive(Debug, Clone, PartialEq)]
+struct TextTag {
+    text: String,
+}
+
+impl TextTag {
+    fn new(text: String) -> Self {
+        TextTag { text }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum DocTag {
     Ignore,
@@ -72,6 +206,226 @@ enum DocTag {
     About(AboutTag),
     Arg(CommandArg),
     Opt(CommandOption),
+    Version(TextTag),
+    Author(TextTag),
+    Footer(TextTag),
+    Audit(TextTag),
+    Details(TextTag),
+    Example(TextTag),
+    Command(CommandTag),
+    Alias(NameTag),
+    Hidden,
+    Relation(ArgRelation),
+    Heading(TextTag),
+    Prompt(Vec<String>),
+    Confirm(Confirmation),
+    Pre(NameTag),
+    Post(NameTag),
+    Timeout(Duration),
+    Retry(Retry),
+}
+
+/// The tags configuring a single command, be it the script itself or one of its sub-commands.
+#[derive(Debug, Default)]
+struct CommandTags {
+    name: Option<String>,
+    description: Option<String>,
+    details: Option<String>,
+    examples: Vec<String>,
+    aliases: Vec<String>,
+    hidden: bool,
+    opts: Vec<CommandOption>,
+    args: Vec<CommandArg>,
+    relations: Vec<ArgRelation>,
+    prompts: Vec<String>,
+    confirmation: Option<Confirmation>,
+    pre: Option<String>,
+    post: Option<String>,
+    timeout: Option<Duration>,
+    retry: Option<Retry>,
+}
+
+impl FromIterator<DocTag> for CommandTags {
+    fn from_iter<I: IntoIterator<Item = DocTag>>(tags: I) -> Self {
+        let mut command_tags = CommandTags::default();
+
+        // The heading applies to the options and arguments that follow it
+        let mut heading: Option<String> = None;
+
+        tags.into_iter().for_each(|tag| match tag {
+            DocTag::Arg(arg) => command_tags.args.push(arg.with_heading(heading.clone())),
+            DocTag::Opt(opt) => command_tags.opts.push(opt.with_heading(heading.clone())),
+            DocTag::Heading(text) => heading = none_if_empty(&text.text),
+            DocTag::About(about) => command_tags.description = Some(about.text),
+            DocTag::Name(name_tag) => command_tags.name = Some(name_tag.name),
+            // Each details tag adds a line to the details
+            DocTag::Details(details) => {
+                command_tags.details = Some(match command_tags.details.take() {
+                    Some(previous) => format!("{}\n{}", previous, details.text),
+                    None => details.text,
+                })
+            }
+            DocTag::Example(example) => command_tags.examples.push(example.text),
+            DocTag::Alias(alias) => command_tags.aliases.push(alias.name),
+            DocTag::Hidden => command_tags.hidden = true,
+            DocTag::Relation(relation) => command_tags.relations.push(relation),
+            DocTag::Prompt(names) => command_tags.prompts.extend(names),
+            DocTag::Confirm(confirmation) => command_tags.confirmation = Some(confirmation),
+            DocTag::Pre(function) => command_tags.pre = Some(function.name),
+            DocTag::Post(function) => command_tags.post = Some(function.name),
+            DocTag::Timeout(timeout) => command_tags.timeout = Some(timeout),
+            DocTag::Retry(retry) => command_tags.retry = Some(retry),
+            _ => {}
+        });
+
+        // The prompt tags may come before or after the args they name
+        command_tags.args = command_tags
+            .args
+            .into_iter()
+            .map(|arg| {
+                let prompt = command_tags.prompts.contains(&arg.name);
+                arg.with_prompt(prompt)
+            })
+            .collect();
+
+        command_tags
+    }
+}
+
+impl CommandTags {
+    /// The hooks named by the tags, which are functions in the script file at the given path.
+    fn hooks(&self, path: &Path) -> Hooks {
+        let hook = |function: &Option<String>| {
+            function
+                .clone()
+                .map(|function| Hook::new(path.to_path_buf(), Some(function)))
+        };
+
+        Hooks::new(hook(&self.pre), hook(&self.post))
+    }
+
+    /// Checks that the relations only refer to the options and arguments of the command, that the
+    /// names of groups do not clash with them, and that the prompts only refer to its arguments.
+    fn check_references(&self) -> Result<(), String> {
+        check_option_names(&self.opts, &[HELP_OPTION])?;
+
+        if let Some(name) = self
+            .prompts
+            .iter()
+            .find(|name| !self.args.iter().any(|arg| &arg.name == *name))
+        {
+            return Err(format!("Unknown argument '{}'", name));
+        }
+
+        let is_defined = |name: &str| {
+            self.opts.iter().any(|opt| opt.name == name)
+                || self.args.iter().any(|arg| arg.name == name)
+        };
+
+        self.relations.iter().try_for_each(|relation| {
+            if let Some(name) = relation.names().into_iter().find(|name| !is_defined(name)) {
+                return Err(format!("Unknown option or argument '{}'", name));
+            }
+
+            match relation {
+                ArgRelation::Group { name, .. } if is_defined(name) => Err(format!(
+                    "The group '{}' has the name of an option or argument",
+                    name
+                )),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// Checks that the names of the options do not start with `-`, that no two of them are set under the
+/// same variable name for scripts, and that none is among the given names reserved for options clap
+/// adds itself.
+fn check_option_names(opts: &[CommandOption], reserved: &[&str]) -> Result<(), String> {
+    opts.iter().enumerate().try_for_each(|(index, opt)| {
+        if opt.name.starts_with('-') {
+            Err(format!(
+                "Invalid option name '{}', as names cannot start with '-'",
+                opt.name
+            ))
+        } else if let Some(other) = opts[..index]
+            .iter()
+            .find(|other| other.var_name() == opt.var_name())
+        {
+            Err(format!(
+                "The options '{}' and '{}' are both set as '{}'",
+                other.name,
+                opt.name,
+                opt.var_name()
+            ))
+        } else if reserved.contains(&opt.name.as_str()) {
+            Err(format!("The option name '{}' is reserved", opt.name))
+        } else {
+            Ok(())
+        }
+    })
+}
+
+/// Checks that none of the options and arguments of the command, or of its sub-commands, has the
+/// name of an option every command of the cli has already, and that none of the options has the
+/// short name of a root option.
+pub(crate) fn check_global_option_clashes(
+    metadata: &CliMetadata,
+    command: &dyn Command,
+) -> Result<(), String> {
+    let names = global_option_names(metadata);
+    let shorts: Vec<char> = metadata
+        .options
+        .iter()
+        .filter_map(|option| option.short)
+        .collect();
+
+    check_option_clashes(&names, &shorts, command, command.name())
+}
+
+fn check_option_clashes(
+    names: &[String],
+    shorts: &[char],
+    command: &dyn Command,
+    path: &str,
+) -> Result<(), String> {
+    command.options().iter().try_for_each(|option| {
+        let negation = (option.kind == OptionKind::Negatable).then(|| option.negation());
+
+        if names.contains(&option.name) || negation.is_some_and(|name| names.contains(&name)) {
+            Err(format!(
+                "The option '{}' of '{}' is an option of the cli already",
+                option.name, path
+            ))
+        } else if let Some(short) = option.short.filter(|short| shorts.contains(short)) {
+            Err(format!(
+                "The short name '-{}' of the option '{}' of '{}' is that of an option of the cli",
+                short, option.name, path
+            ))
+        } else {
+            Ok(())
+        }
+    })?;
+
+    command.args().iter().try_for_each(|arg| {
+        if names.contains(&arg.name) {
+            Err(format!(
+                "The argument '{}' of '{}' has the name of an option of the cli",
+                arg.name, path
+            ))
+        } else {
+            Ok(())
+        }
+    })?;
+
+    command.sub_commands().iter().try_for_each(|sub| {
+        check_option_clashes(
+            names,
+            shorts,
+            sub.as_ref(),
+            &format!("{} {}", path, sub.name()),
+        )
+    })
 }
 
 trait FinishIncomplete<T, O, E> {
@@ -89,6 +443,7 @@ impl<T, O1, O2, E> FinishIncomplete<T, O1, E> for IResult<T, O2, E> {
     }
 }
 
+/// The input types the annotation parsers accept.
 pub trait InputType:
     InputTakeAtPosition<Item = char>
     + Slice<Range<usize>>
@@ -96,6 +451,7 @@ pub trait InputType:
     + Slice<RangeTo<usize>>
     + InputIter<Item = char>
     + InputLength
+    + Offset
     + Compare<&'static str>
     + ToString
 {
@@ -129,6 +485,19 @@ fn name_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
         .map(|(i, o)| (i, Some(DocTag::Name(NameTag::new(o.to_string())))))
 }
 
+fn alias_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    terminated(preceded(multispace0, identifier), not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Alias(NameTag::new(o.to_string())))))
+}
+
+fn hidden_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    value(Some(DocTag::Hidden), not_line_ending)(input)
+}
+
 fn sub_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
     input: T,
 ) -> IResult<T, Option<DocTag>, E> {
@@ -143,6 +512,219 @@ fn about_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
         .map(|(i, o)| (i, Some(DocTag::About(AboutTag::new(o.to_string())))))
 }
 
+fn version_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Version(TextTag::new(o.to_string())))))
+}
+
+fn author_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Author(TextTag::new(o.to_string())))))
+}
+
+fn footer_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Footer(TextTag::new(o.to_string())))))
+}
+
+/// Parses the optional path of the audit log, or the word turning it off.
+fn audit_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input).map(|(i, o)| {
+        (
+            i,
+            Some(DocTag::Audit(TextTag::new(o.to_string().trim().to_owned()))),
+        )
+    })
+}
+
+fn details_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Details(TextTag::new(o.to_string())))))
+}
+
+fn heading_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Heading(TextTag::new(o.to_string())))))
+}
+
+fn confirm_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input).map(|(i, o)| {
+        let message = none_if_empty(&o.to_string());
+        (i, Some(DocTag::Confirm(Confirmation::new(message))))
+    })
+}
+
+fn pre_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    terminated(preceded(multispace0, identifier), not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Pre(NameTag::new(o.to_string())))))
+}
+
+fn post_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    terminated(preceded(multispace0, identifier), not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Post(NameTag::new(o.to_string())))))
+}
+
+/// Parses a duration such as `30s` or `1m 30s`; an invalid duration makes the tag unknown.
+fn timeout_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input).map(|(i, o)| {
+        let timeout = humantime::parse_duration(o.to_string().trim())
+            .ok()
+            .filter(|timeout| !timeout.is_zero());
+
+        (i, timeout.map(DocTag::Timeout))
+    })
+}
+
+/// Parses the number of retries, followed by the optional duration of the first wait.
+fn retry_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input).map(|(i, o)| {
+        let text = o.to_string();
+        let (count, backoff) = text
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((text.trim(), ""));
+
+        let backoff = match backoff.trim() {
+            "" => Ok(None),
+            backoff => humantime::parse_duration(backoff).map(Some),
+        };
+
+        let retry = count
+            .parse::<u32>()
+            .ok()
+            .zip(backoff.ok())
+            .map(|(count, backoff)| DocTag::Retry(Retry::new(count, backoff)));
+
+        (i, retry)
+    })
+}
+
+fn example_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    padded(not_line_ending)(input)
+        .map(|(i, o)| (i, Some(DocTag::Example(TextTag::new(o.to_string())))))
+}
+
+fn command_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    preceded(
+        multispace0,
+        pair(
+            identifier,
+            pair(padded(identifier), padded(not_line_ending)),
+        ),
+    )(input)
+    .map(|(i, (name, (kind, definition)))| {
+        let tag = CommandKind::from_str(&kind.to_string()).ok().map(|kind| {
+            DocTag::Command(CommandTag::new(
+                name.to_string(),
+                kind,
+                definition.to_string(),
+            ))
+        });
+
+        (i, tag)
+    })
+}
+
+/// Parses the rest of the line as a list of names, of which there must be at least `min`.
+fn names<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    min: usize,
+    input: T,
+) -> IResult<T, Option<Vec<String>>, E> {
+    padded(not_line_ending)(input).map(|(i, o)| {
+        let names: Vec<String> = o
+            .to_string()
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect();
+
+        (i, Some(names).filter(|names| names.len() >= min))
+    })
+}
+
+fn prompt_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    names(1, input).map(|(i, names)| (i, names.map(DocTag::Prompt)))
+}
+
+fn conflicts_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    names(2, input).map(|(i, names)| {
+        (
+            i,
+            names.map(|names| DocTag::Relation(ArgRelation::Conflicts(names))),
+        )
+    })
+}
+
+fn requires_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    names(2, input).map(|(i, names)| {
+        let tag = names.map(|mut names| {
+            let name = names.remove(0);
+            DocTag::Relation(ArgRelation::Requires(name, names))
+        });
+
+        (i, tag)
+    })
+}
+
+fn group_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
+    input: T,
+) -> IResult<T, Option<DocTag>, E> {
+    names(2, input).map(|(i, names)| {
+        let tag = names
+            .map(|mut names| {
+                let name = names.remove(0);
+                let required = names[0] == REQUIRED;
+
+                if required {
+                    names.remove(0);
+                }
+
+                (name, required, names)
+            })
+            .filter(|(_, _, members)| !members.is_empty())
+            .map(|(name, required, members)| {
+                DocTag::Relation(ArgRelation::Group {
+                    name,
+                    required,
+                    members,
+                })
+            });
+
+        (i, tag)
+    })
+}
+
 fn arg_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
     input: T,
 ) -> IResult<T, Option<DocTag>, E> {
@@ -153,7 +735,12 @@ fn arg_var_arg<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
     var_arg: bool,
     input: T,
 ) -> IResult<T, Option<DocTag>, E> {
-    preceded(multispace0, pair(identifier, padded(not_line_ending)))(input).map(|(i, o)| {
+    // As option names, argument names may contain `-`
+    preceded(
+        multispace0,
+        pair(is_not(" \t\r\n"), padded(not_line_ending)),
+    )(input)
+    .map(|(i, o)| {
         let name = o.0.to_string();
         let details = o.1.to_string();
         let inner_res =
@@ -189,16 +776,22 @@ fn arg_details<'a, E: ParseError<&'a str> + 'a>(
         tuple((
             opt(padded_bool),
             opt(arg_type),
+            opt(prefixed_word(VALUE_NAME_PREFIX)),
+            opt(env_var),
             preceded(nom::character::complete::space0, rest),
         )),
-        |(optional, arg_type, rest)| {
-            Some(DocTag::Arg(CommandArg::new(
-                name.to_string(),
-                optional.unwrap_or(false),
-                var_arg,
-                arg_type.unwrap_or(Unknown),
-                none_if_empty(rest),
-            )))
+        |(optional, arg_type, value_name, env, rest)| {
+            Some(DocTag::Arg(
+                CommandArg::new(
+                    name.to_string(),
+                    optional.unwrap_or(false),
+                    var_arg,
+                    arg_type.unwrap_or(Unknown),
+                    none_if_empty(rest),
+                )
+                .with_value_name(value_name.map(str::to_owned))
+                .with_env(env),
+            ))
         },
     )(input)
 }
@@ -214,7 +807,12 @@ fn none_if_empty(rest: &str) -> Option<String> {
 fn opt_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
     input: T,
 ) -> IResult<T, Option<DocTag>, E> {
-    preceded(multispace0, pair(identifier, padded(not_line_ending)))(input).map(|(i, o)| {
+    // Unlike other names, option names may contain `-`, e.g. `dry-run`
+    preceded(
+        multispace0,
+        pair(is_not(" \t\r\n"), padded(not_line_ending)),
+    )(input)
+    .map(|(i, o)| {
         let name = o.0.to_string();
         let details = o.1.to_string();
 
@@ -237,25 +835,78 @@ fn opt_details<'a, E: ParseError<&'a str> + 'a>(
                     anychar::<&'a str, _>,
                     nom::character::complete::char('\''),
                 )),
-                padded_bool_default_false,
+                opt(padded_bool),
+                opt(option_kind),
+                opt(prefixed_word(VALUE_NAME_PREFIX)),
+                opt(env_var),
                 preceded(nom::character::complete::space0, rest),
             )),
         ),
-        |(short, has_param, rest)| {
-            Some(DocTag::Opt(CommandOption::new(
-                name.to_string(),
-                short,
-                has_param,
-                none_if_empty(rest),
-            )))
+        |(short, has_param, kind, value_name, env, rest)| {
+            let kind = kind.unwrap_or_default();
+
+            // Only options with a parameter can have many values, and only flags can be counted or
+            // negated
+            let has_param = match (kind, has_param) {
+                (OptionKind::Single, has_param) => has_param.unwrap_or(false),
+                (OptionKind::Many, None | Some(true)) => true,
+                (OptionKind::Count | OptionKind::Negatable, None | Some(false)) => false,
+                _ => return None,
+            };
+
+            Some(DocTag::Opt(
+                CommandOption::new(name.to_string(), short, has_param, none_if_empty(rest))
+                    .with_kind(kind)
+                    .with_value_name(value_name.map(str::to_owned))
+                    .with_env(env),
+            ))
         },
     )(input)
 }
 
-fn padded_bool_default_false<'a, E: ParseError<&'a str> + 'a>(
-    input: &'a str,
-) -> IResult<&'a str, bool, E> {
-    map(opt(padded_bool), |x| x.unwrap_or(false))(input)
+/// Parses the word setting the kind of an option: `count`, `many` or `negatable`.
+fn option_kind<'a, E: ParseError<&'a str> + 'a>(input: &'a str) -> IResult<&'a str, OptionKind, E> {
+    preceded(
+        nom::character::complete::space0,
+        terminated(
+            alt((
+                value(OptionKind::Count, nom::bytes::complete::tag(COUNT)),
+                value(OptionKind::Many, nom::bytes::complete::tag(MANY)),
+                value(OptionKind::Negatable, nom::bytes::complete::tag(NEGATABLE)),
+            )),
+            not(nom::bytes::complete::is_not(" \t\r\n")),
+        ),
+    )(input)
+}
+
+/// Parses a word starting with the given prefix, e.g. `env=VAR`, returning the rest of the word.
+fn prefixed_word<'a, E: ParseError<&'a str> + 'a>(
+    prefix: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, E> {
+    preceded(
+        nom::character::complete::space0,
+        preceded(
+            nom::bytes::complete::tag(prefix),
+            nom::bytes::complete::is_not(" \t\r\n"),
+        ),
+    )
+}
+
+/// Parses `env=<VAR>`, optionally followed by `secret`.
+fn env_var<'a, E: ParseError<&'a str> + 'a>(input: &'a str) -> IResult<&'a str, EnvVar, E> {
+    map(
+        pair(
+            prefixed_word(ENV_PREFIX),
+            opt(preceded(
+                nom::character::complete::space1,
+                terminated(
+                    nom::bytes::complete::tag(SECRET),
+                    not(nom::bytes::complete::is_not(" \t\r\n")),
+                ),
+            )),
+        ),
+        |(name, secret)| EnvVar::new(name, secret.is_some()),
+    )(input)
 }
 
 fn padded_bool<'a, E: ParseError<&'a str> + 'a>(input: &'a str) -> IResult<&'a str, bool, E> {
@@ -285,6 +936,25 @@ fn parser_for_tag<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
         ARG_TAG => Box::new(arg_tag),
         VAR_ARG_TAG => Box::new(var_arg_tag),
         OPT_TAG => Box::new(opt_tag),
+        VERSION_TAG => Box::new(version_tag),
+        AUTHOR_TAG => Box::new(author_tag),
+        FOOTER_TAG => Box::new(footer_tag),
+        AUDIT_TAG => Box::new(audit_tag),
+        DETAILS_TAG => Box::new(details_tag),
+        EXAMPLE_TAG => Box::new(example_tag),
+        COMMAND_TAG => Box::new(command_tag),
+        ALIAS_TAG => Box::new(alias_tag),
+        HIDDEN_TAG => Box::new(hidden_tag),
+        HEADING_TAG => Box::new(heading_tag),
+        PROMPT_TAG => Box::new(prompt_tag),
+        CONFIRM_TAG => Box::new(confirm_tag),
+        PRE_TAG => Box::new(pre_tag),
+        POST_TAG => Box::new(post_tag),
+        TIMEOUT_TAG => Box::new(timeout_tag),
+        RETRY_TAG => Box::new(retry_tag),
+        CONFLICTS_TAG => Box::new(conflicts_tag),
+        REQUIRES_TAG => Box::new(requires_tag),
+        GROUP_TAG => Box::new(group_tag),
         _ => Box::new(unknown_tag),
     }
 }
@@ -322,125 +992,448 @@ fn comment_or_not<'a, T: InputType + 'a, E: ParseError<T> + 'a>(
 fn collect<'a, T: InputType + Clone + 'a, E: ParseError<T> + 'a>(
     input: T,
 ) -> Result<Vec<Vec<DocTag>>, E> {
-    // create an iterator over all tags in the input
-    let mut iter = iterator(input, comment_or_not);
-
-    // fold the tags into groups of tags, starting a new group when a sub tag is found
-    let groups = iter
-        .filter_map(|a| a)
-        .fold(vec![vec![]], |mut groups, tag| {
-            match tag {
-                DocTag::Sub(_) => groups.push(vec![tag]),
-                _ => groups.last_mut().unwrap().push(tag),
+    // create an iterator over all tags in the input, along with the input consumed for each
+    let mut iter = iterator(input, consumed(comment_or_not));
+
+    let mut line = 1;
+
+    // fold the tags into groups of tags, starting a new group when a sub or command tag is found
+    let groups = iter.fold(vec![vec![]], |mut groups, (consumed, tag)| {
+        // The consumed input ends with the line the tag is on
+        line += consumed.iter_elements().filter(|c| *c == '\n').count();
+
+        match tag {
+            Some(DocTag::Sub(sub)) => groups.push(vec![DocTag::Sub(sub.at_line(line))]),
+            Some(DocTag::Command(command)) => {
+                groups.push(vec![DocTag::Command(command.at_line(line))])
             }
-            groups
-        });
+            Some(tag) => groups.last_mut().unwrap().push(tag),
+            None => {}
+        }
+        groups
+    });
 
     iter.finish().finish_with_val(groups)
 }
 
-fn default_name(path: &PathBuf) -> String {
+fn default_name(path: &Path) -> String {
     path.file_name()
         .map(|name| name.to_string_lossy().to_string())
         .map(|file_name| strip_file_suffix(&file_name))
         .unwrap()
 }
 
-pub fn build_script_command(path: PathBuf) -> Result<Option<ScriptCommand>, String> {
-    let mut file_content = std::fs::read_to_string(&path).unwrap();
+/// Returns true if the given path is the file holding the metadata of the CLI itself.
+pub fn is_cli_metadata_file(path: &Path) -> bool {
+    default_name(path) == CLI_METADATA_FILE
+}
+
+/// Returns true if the given path is the file describing a group of commands.
+pub fn is_group_file(path: &Path) -> bool {
+    default_name(path) == GROUP_FILE
+}
+
+/// Reads the hook scripts in the hooks directory of the given source directory: the files named
+/// `pre` and `post`, with any extension.
+pub fn build_dir_hooks(dir: &Path) -> Hooks {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir.join(HOOKS_DIR))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+
+    // Of several files with the same stem, the first by name is taken
+    files.sort();
+
+    let hook = |stem: &str| {
+        files
+            .iter()
+            .find(|path| default_name(path) == stem)
+            .map(|path| Hook::new(path.clone(), None))
+    };
+
+    Hooks::new(hook(PRE_HOOK_FILE), hook(POST_HOOK_FILE))
+}
+
+/// Reads the given file, ensuring it ends with a newline.
+fn read_annotated_file(path: &Path) -> Result<String, String> {
+    let mut file_content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
 
     // Until streaming is implemented properly and we can handle incomplete, make sure the file
     // ends with a newline, otherwise we may miss the last tag
-    if !file_content.ends_with("\n") {
+    if !file_content.ends_with('\n') {
         file_content.push('\n')
     }
 
-    let res = collect::<&str, nom::error::Error<&str>>(&file_content)
-        .map_err(|e| e.to_string())
-        .map(|groups| {
-            if groups.len() == 0 || groups.len() == 1 && groups[0].len() == 0 {
-                // There are no doc-tags. Assume the file is a script
-                // and let it accept any args
-                Ok(Some(ScriptCommand::new(
-                    default_name(&path),
-                    None,
-                    path,
-                    vec![],
-                    vec![CommandArg::new(
-                        "args",
-                        true,
-                        true,
-                        ArgType::Unknown,
-                        Some("Any arguments are passed to the script"),
-                    )],
-                    vec![],
-                )))
-            } else if groups[0].len() > 0 && groups[0][0] == DocTag::Ignore {
-                Ok(None)
-            } else {
-                let mut iter = groups.into_iter();
+    Ok(file_content)
+}
 
-                let main_tags = iter.next().unwrap();
+/// Builds the metadata of the CLI from the tags in the given file. Only the tags before the first
+/// `@sub` are considered.
+pub fn build_cli_metadata(path: &Path) -> Result<CliMetadata, String> {
+    let file_content = read_annotated_file(path)?;
+
+    let groups =
+        collect::<&str, nom::error::Error<&str>>(&file_content).map_err(|e| e.to_string())?;
+
+    let mut metadata = CliMetadata::default();
+    let mut heading: Option<String> = None;
+
+    groups
+        .into_iter()
+        .next()
+        .into_iter()
+        .flatten()
+        .for_each(|tag| match tag {
+            DocTag::Name(name_tag) => metadata.name = Some(name_tag.name),
+            DocTag::About(about) => metadata.description = Some(about.text),
+            DocTag::Version(version) => metadata.version = Some(version.text),
+            DocTag::Author(author) => metadata.author = Some(author.text),
+            DocTag::Footer(footer) => metadata.after_help = Some(footer.text),
+            DocTag::Audit(path) => {
+                metadata.audit_log = match path.text.as_str() {
+                    "" => AuditLog::InHome,
+                    AUDIT_OFF => AuditLog::Off,
+                    path => AuditLog::File(PathBuf::from(path)),
+                }
+            }
+            DocTag::Opt(opt) => metadata.options.push(opt.with_heading(heading.clone())),
+            DocTag::Heading(text) => heading = none_if_empty(&text.text),
+            _ => {}
+        });
 
-                let mut opts = Vec::new();
-                let mut args = Vec::new();
+    check_option_names(&metadata.options, &[HELP_OPTION, VERSION_OPTION])?;
 
-                let mut description = None;
-                let mut name = None;
+    Ok(metadata)
+}
 
-                main_tags.into_iter().for_each(|tag| match tag {
-                    DocTag::Arg(arg) => args.push(arg),
-                    DocTag::Opt(opt) => opts.push(opt),
-                    DocTag::About(about) => description = Some(about.text),
-                    DocTag::Name(name_tag) => name = Some(name_tag.name),
-                    _ => {}
-                });
+/// Builds the commands defined by the `@command` tags in the given file, each configured by the
+/// tags that follow it. The tags before the first `@command` are ignored.
+pub fn build_config_commands(path: &Path) -> Result<Vec<Box<dyn Command>>, String> {
+    let file_content = read_annotated_file(path)?;
 
-                let sub_commands = iter
-                    .map(|group| {
-                        let mut opts = Vec::new();
-                        let mut args = Vec::new();
+    let groups =
+        collect::<&str, nom::error::Error<&str>>(&file_content).map_err(|e| e.to_string())?;
 
-                        let mut group_iter = group.into_iter();
+    groups
+        .into_iter()
+        .skip(1)
+        .map(|group| {
+            let mut group_iter = group.into_iter();
 
-                        let sub_tag = match group_iter.next() {
-                            Some(DocTag::Sub(sub)) => sub,
-                            _ => return Err("No sub tag found".to_string()),
-                        };
+            let command_tag = match group_iter.next() {
+                Some(DocTag::Command(command)) => command,
+                _ => return Err("No command tag found".to_string()),
+            };
 
-                        let mut description = None;
+            build_defined_command(path, command_tag, group_iter.collect())
+        })
+        .collect()
+}
 
-                        group_iter.for_each(|tag| match tag {
-                            DocTag::Arg(arg) => args.push(arg),
-                            DocTag::Opt(opt) => opts.push(opt),
-                            DocTag::About(about) => description = Some(about.text),
-                            _ => {}
-                        });
-                        Ok(EmbeddedCommand::new(sub_tag.name, description, opts, args))
+/// Builds the command defined by a `@command` tag in the cli file at the given path. The hooks of
+/// an alias are ignored, as are its confirmation, since the command it expands to is what is run.
+fn build_defined_command(
+    cli_file: &Path,
+    command_tag: CommandTag,
+    tags: CommandTags,
+) -> Result<Box<dyn Command>, String> {
+    let CommandTag {
+        name,
+        kind,
+        definition,
+        line,
+    } = command_tag;
+
+    let mut words = definition.split_whitespace().map(str::to_owned);
+
+    if definition.trim().is_empty() {
+        return Err(format!("The command '{}' has no definition", name));
+    }
+
+    tags.check_references()?;
+
+    let hooks = tags.hooks(cli_file);
+
+    Ok(match kind {
+        CommandKind::Alias => {
+            let description = tags
+                .description
+                .unwrap_or(format!("Alias for `{}`", definition));
+
+            Box::new(
+                AliasCommand::new(name, words.collect(), Some(description))
+                    .with_line(line)
+                    .with_details(tags.details)
+                    .with_examples(tags.examples),
+            )
+        }
+        CommandKind::Inline => {
+            let description = tags.description.unwrap_or(format!("Runs `{}`", definition));
+
+            Box::new(
+                InlineCommand::new(name, definition, Some(description), tags.opts, tags.args)
+                    .with_line(line)
+                    .with_details(tags.details)
+                    .with_examples(tags.examples)
+                    .with_relations(tags.relations)
+                    .with_confirmation(tags.confirmation)
+                    .with_hooks(hooks),
+            )
+        }
+        CommandKind::Binary => {
+            let program = words.next().unwrap(/* The definition is not empty */);
+
+            let description = tags.description.unwrap_or(format!("Runs {}", program));
+
+            // Without annotations, accept any args, as for a script without tags
+            let args = if tags.opts.is_empty() && tags.args.is_empty() {
+                vec![CommandArg::new(
+                    "args",
+                    true,
+                    true,
+                    ArgType::Unknown,
+                    Some(format!("Any arguments are passed to {}", program)),
+                )]
+            } else {
+                tags.args
+            };
+
+            Box::new(
+                BinaryCommand::new(
+                    name,
+                    program,
+                    words.collect(),
+                    Some(description),
+                    tags.opts,
+                    args,
+                )
+                .with_line(line)
+                .with_details(tags.details)
+                .with_examples(tags.examples)
+                .with_relations(tags.relations)
+                .with_confirmation(tags.confirmation)
+                .with_hooks(hooks),
+            )
+        }
+    })
+}
+
+/// Builds the group of the given commands in the given directory, from the tags in its group file
+/// before the first `@sub`. Without a group file, or an `@name` tag in it, the group is named after
+/// the directory.
+pub fn build_group_command(
+    dir: &Path,
+    group_file: Option<PathBuf>,
+    commands: Vec<Box<dyn Command>>,
+) -> Result<GroupCommand, String> {
+    let tags: CommandTags = match group_file.as_ref() {
+        Some(group_file) => {
+            let file_content = read_annotated_file(group_file)?;
+
+            collect::<&str, nom::error::Error<&str>>(&file_content)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .next()
+                .into_iter()
+                .flatten()
+                .collect()
+        }
+        None => CommandTags::default(),
+    };
+
+    tags.check_references()?;
+
+    let hooks = group_file
+        .as_deref()
+        .map(|group_file| tags.hooks(group_file))
+        .unwrap_or_default();
+
+    let name = tags.name.unwrap_or(
+        dir.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or(format!("Not a directory: {}", dir.to_string_lossy()))?,
+    );
+
+    let description = tags.description.unwrap_or(format!("The {} commands", name));
+
+    Ok(
+        GroupCommand::new(name, Some(description), tags.opts, commands)
+            .with_path(group_file)
+            .with_confirmation(tags.confirmation)
+            .with_hooks(hooks)
+            .with_details(tags.details)
+            .with_aliases(tags.aliases)
+            .with_hidden(tags.hidden)
+            .with_relations(tags.relations),
+    )
+}
+
+/// A sub-command of a script, while the tree of sub-commands is built.
+struct SubCommandNode {
+    command: EmbeddedCommand,
+    children: Vec<SubCommandNode>,
+}
+
+impl SubCommandNode {
+    fn new(command: EmbeddedCommand) -> Self {
+        SubCommandNode {
+            command,
+            children: vec![],
+        }
+    }
+
+    fn into_command(self) -> Box<dyn Command> {
+        let children = self
+            .children
+            .into_iter()
+            .map(SubCommandNode::into_command)
+            .collect();
+
+        Box::new(self.command.with_sub_commands(children))
+    }
+}
+
+/// Arranges the sub-commands of a script into a tree by their dotted names, e.g. `cluster.create`
+/// is the `create` sub-command of `cluster`. Parents that are not declared are added without any
+/// tags.
+fn nest_sub_commands(
+    sub_commands: Vec<(String, EmbeddedCommand)>,
+) -> Result<Vec<Box<dyn Command>>, String> {
+    let mut roots: Vec<SubCommandNode> = vec![];
+
+    for (dotted_name, command) in sub_commands {
+        let path: Vec<&str> = dotted_name.split('.').collect();
+
+        if path.iter().any(|part| part.is_empty()) {
+            return Err(format!("Invalid sub-command name '{}'", dotted_name));
+        }
+
+        let mut siblings = &mut roots;
+
+        for parent in &path[..path.len() - 1] {
+            let index = match siblings
+                .iter()
+                .position(|node| node.command.name() == *parent)
+            {
+                Some(index) => index,
+                None => {
+                    siblings.push(SubCommandNode::new(EmbeddedCommand::new(
+                        *parent,
+                        None::<String>,
+                        vec![],
+                        vec![],
+                    )));
+                    siblings.len() - 1
+                }
+            };
+
+            siblings = &mut siblings[index].children;
+        }
+
+        match siblings
+            .iter_mut()
+            .find(|node| node.command.name() == command.name())
+        {
+            // The command was added as a parent before it was declared
+            Some(node) => node.command = command,
+            None => siblings.push(SubCommandNode::new(command)),
+        }
+    }
+
+    Ok(roots
+        .into_iter()
+        .map(SubCommandNode::into_command)
+        .collect())
+}
+
+/// Builds a command from the annotations in the script at the given path. Returns `None` if the
+/// script is to be ignored.
+pub fn build_script_command(path: PathBuf) -> Result<Option<ScriptCommand>, String> {
+    let file_content = read_annotated_file(&path)?;
+
+    let res = collect::<&str, nom::error::Error<&str>>(&file_content)
+        .map_err(|e| e.to_string())
+        .map(|groups| {
+            if groups.is_empty() || groups.len() == 1 && groups[0].is_empty() {
+                // There are no doc-tags. Assume the file is a script
+                // and let it accept any args
+                Ok(Some(ScriptCommand::new(
+                    default_name(&path),
+                    None,
+                    path,
+                    vec![],
+                    vec![CommandArg::new(
+                        "args",
+                        true,
+                        true,
+                        ArgType::Unknown,
+                        Some("Any arguments are passed to the script"),
+                    )],
+                    vec![],
+                )))
+            } else if !groups[0].is_empty() && groups[0][0] == DocTag::Ignore {
+                Ok(None)
+            } else {
+                let mut iter = groups.into_iter();
+
+                let main_tags: CommandTags = iter.next().unwrap().into_iter().collect();
+
+                main_tags.check_references()?;
+
+                let main_hooks = main_tags.hooks(&path);
+
+                let sub_commands = iter
+                    .map(|group| {
+                        let mut group_iter = group.into_iter();
+
+                        let sub_tag = match group_iter.next() {
+                            Some(DocTag::Sub(sub)) => sub,
+                            _ => return Err("No sub tag found".to_string()),
+                        };
+
+                        let tags: CommandTags = group_iter.collect();
+                        tags.check_references()?;
+
+                        let hooks = tags.hooks(&path);
+
+                        // The name of the command is the last part of a dotted name
+                        let name = sub_tag.name.rsplit('.').next().unwrap().to_owned();
+
+                        Ok((
+                            sub_tag.name,
+                            EmbeddedCommand::new(name, tags.description, tags.opts, tags.args)
+                                .with_line(sub_tag.line)
+                                .with_details(tags.details)
+                                .with_examples(tags.examples)
+                                .with_relations(tags.relations)
+                                .with_confirmation(tags.confirmation)
+                                .with_hooks(hooks),
+                        ))
                     })
-                    .fold(
-                        Ok::<Vec<Box<dyn Command>>, String>(vec![]),
-                        |acc, res| match acc {
-                            Ok(mut vec) => match res {
-                                Ok(val) => {
-                                    vec.push(Box::new(val));
-                                    Ok(vec)
-                                }
-                                Err(e) => Err(e),
-                            },
-                            Err(e) => Err(e),
-                        },
-                    );
+                    .collect::<Result<Vec<(String, EmbeddedCommand)>, String>>()
+                    .and_then(nest_sub_commands);
 
                 sub_commands.map(|sub_commands| {
-                    Some(ScriptCommand::new(
-                        name.unwrap_or(default_name(&path)),
-                        description,
-                        path,
-                        opts,
-                        args,
-                        sub_commands,
-                    ))
+                    Some(
+                        ScriptCommand::new(
+                            main_tags.name.unwrap_or(default_name(&path)),
+                            main_tags.description,
+                            path,
+                            main_tags.opts,
+                            main_tags.args,
+                            sub_commands,
+                        )
+                        .with_details(main_tags.details)
+                        .with_examples(main_tags.examples)
+                        .with_relations(main_tags.relations)
+                        .with_confirmation(main_tags.confirmation)
+                        .with_hooks(main_hooks)
+                        .with_timeout(main_tags.timeout)
+                        .with_retry(main_tags.retry),
+                    )
                 })
             }
         });
@@ -456,15 +1449,23 @@ pub fn build_script_command(path: PathBuf) -> Result<Option<ScriptCommand>, Stri
 mod test {
     use std::fs::File;
     use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::Duration;
 
     use indoc::indoc;
 
     use crate::builder::{
-        arg_tag, build_script_command, collect, comment_or_not, doc_tag, doc_tag_or_not, opt_tag,
-        sub_tag, var_arg_tag, AboutTag, DocTag, SubTag,
+        arg_tag, audit_tag, build_cli_metadata, build_config_commands, build_dir_hooks,
+        build_group_command, build_script_command, check_global_option_clashes, collect,
+        command_tag, comment_or_not, conflicts_tag, doc_tag, doc_tag_or_not, group_tag, opt_tag,
+        requires_tag, sub_tag, var_arg_tag, AboutTag, CommandKind, CommandTag, CommandTags, DocTag,
+        SubTag, TextTag, HOOKS_DIR,
     };
     use crate::model::test::NO_DESCRIPTION;
-    use crate::model::{ArgType, Command, CommandArg, CommandOption};
+    use crate::model::{
+        ArgRelation, ArgType, AuditLog, CliMetadata, Command, CommandArg, CommandOption,
+        Confirmation, EnvVar, Hook, Hooks, OptionKind, Retry,
+    };
 
     #[test]
     fn sub_tag_finds_name() {
@@ -739,6 +1740,22 @@ mod test {
         assert!(sub.is_none());
     }
 
+    #[test]
+    fn opt_tag_finds_dashed_name() {
+        let (_, opt) =
+            opt_tag::<&str, nom::error::Error<&str>>("dry-run Show what is run\n").unwrap();
+
+        assert_eq!(
+            opt.unwrap(),
+            DocTag::Opt(CommandOption::new(
+                "dry-run",
+                None,
+                false,
+                Some("Show what is run")
+            ))
+        );
+    }
+
     #[test]
     fn opt_tag_finds_name() {
         let input = indoc! {"
@@ -886,6 +1903,138 @@ mod test {
         );
     }
 
+    #[test]
+    fn opt_tag_finds_env() {
+        let input = indoc! {"
+            token 't' true env=GITHUB_TOKEN secret The token to use
+            "};
+
+        let res = opt_tag::<&str, nom::error::Error<&str>>(input);
+
+        let (_, sub) = res.unwrap();
+
+        assert_eq!(
+            sub.unwrap(),
+            DocTag::Opt(
+                CommandOption::new(
+                    "token".to_string(),
+                    Some('t'),
+                    true,
+                    Some("The token to use".to_string()),
+                )
+                .with_env(Some(EnvVar::new("GITHUB_TOKEN", true)))
+            )
+        );
+    }
+
+    #[test]
+    fn opt_tag_finds_kind() {
+        let parse = |input: &str| {
+            opt_tag::<&str, nom::error::Error<&str>>(input)
+                .unwrap()
+                .1
+                .map(|tag| match tag {
+                    DocTag::Opt(option) => (option.has_param, option.kind),
+                    _ => panic!("Not an option"),
+                })
+        };
+
+        assert_eq!(
+            parse("verbose 'v' count\n"),
+            Some((false, OptionKind::Count))
+        );
+        assert_eq!(parse("tag many A tag\n"), Some((true, OptionKind::Many)));
+        assert_eq!(parse("tag true many\n"), Some((true, OptionKind::Many)));
+        assert_eq!(
+            parse("color negatable Colour\n"),
+            Some((false, OptionKind::Negatable))
+        );
+        assert_eq!(
+            parse("color manyfold colours\n"),
+            Some((false, OptionKind::Single))
+        );
+
+        // Only flags can be counted
+        assert_eq!(parse("verbose true count\n"), None);
+        assert_eq!(parse("tag false many\n"), None);
+    }
+
+    #[test]
+    fn opt_and_arg_tags_find_value_name() {
+        let (_, tag) =
+            opt_tag::<&str, nom::error::Error<&str>>("output 'o' true value=FILE Write to FILE\n")
+                .unwrap();
+
+        assert_eq!(
+            tag.unwrap(),
+            DocTag::Opt(
+                CommandOption::new("output", Some('o'), true, Some("Write to FILE"))
+                    .with_value_name(Some("FILE".to_string()))
+            )
+        );
+
+        let (_, tag) =
+            arg_tag::<&str, nom::error::Error<&str>>("query value=SQL env=QUERY The query\n")
+                .unwrap();
+
+        assert_eq!(
+            tag.unwrap(),
+            DocTag::Arg(
+                CommandArg::new("query", false, false, ArgType::Unknown, Some("The query"))
+                    .with_value_name(Some("SQL".to_string()))
+                    .with_env(Some(EnvVar::new("QUERY", false)))
+            )
+        );
+    }
+
+    #[test]
+    fn heading_applies_to_following_opts_and_args() {
+        let input = indoc! {"
+            # @opt output true
+            # @heading Connection
+            # @opt host true
+            # @arg db
+            # @heading
+            # @arg query
+            "};
+
+        let tags: CommandTags = collect::<&str, nom::error::Error<&str>>(input)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+
+        assert_eq!(tags.opts[0].heading, None);
+        assert_eq!(tags.opts[1].heading, Some("Connection".to_string()));
+        assert_eq!(tags.args[0].heading, Some("Connection".to_string()));
+        assert_eq!(tags.args[1].heading, None);
+    }
+
+    #[test]
+    fn arg_tag_finds_env() {
+        let input = indoc! {"
+            file true <file> env=INPUT_FILE secretive files are not secret
+            "};
+
+        let res = arg_tag::<&str, nom::error::Error<&str>>(input);
+
+        let (_, sub) = res.unwrap();
+
+        assert_eq!(
+            sub.unwrap(),
+            DocTag::Arg(
+                CommandArg::new(
+                    "file".to_string(),
+                    true,
+                    false,
+                    ArgType::File,
+                    Some("secretive files are not secret".to_string()),
+                )
+                .with_env(Some(EnvVar::new("INPUT_FILE", false)))
+            )
+        );
+    }
+
     #[test]
     fn collect_groups_each_subtag() {
         let input = indoc! {"
@@ -911,6 +2060,28 @@ mod test {
         assert_eq!(sub.len(), 3);
     }
 
+    #[test]
+    fn collect_records_line_of_subtag() {
+        let input = indoc! {"
+            # @about This is a description
+
+            # @sub fooBar
+            function fooBar(){}
+            # @sub barFoo
+            "};
+
+        let groups = collect::<&str, nom::error::Error<&str>>(input).unwrap();
+
+        assert_eq!(
+            groups[1][0],
+            DocTag::Sub(SubTag::new("fooBar".to_string(), None).at_line(3))
+        );
+        assert_eq!(
+            groups[2][0],
+            DocTag::Sub(SubTag::new("barFoo".to_string(), None).at_line(5))
+        );
+    }
+
     #[test]
     fn build_script_command_includes_sub_commands() {
         let test_dir = tempfile::tempdir().unwrap();
@@ -919,7 +2090,7 @@ mod test {
 
         File::create(&script1_path)
             .unwrap()
-            .write(
+            .write_all(
                 indoc! {"\
             # @name CommandName blah blah
             # @about foo bar
@@ -932,23 +2103,24 @@ mod test {
             "}
                 .as_bytes(),
             )
-            .expect(format!("Unable to create file {}", script1_path.to_str().unwrap()).as_str());
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
 
         let command = build_script_command(script1_path).unwrap().unwrap();
 
-        assert_eq!(command.name, "CommandName");
-        assert_eq!(command.description, Some("foo bar".to_string()));
+        assert_eq!(command.name(), "CommandName");
+        assert_eq!(command.description(), Some("foo bar"));
 
         let sub_commands = command.sub_commands();
         assert_eq!(sub_commands.len(), 2);
         assert_eq!(sub_commands[0].name(), "sub1");
+        assert_eq!(sub_commands[0].line(), Some(3));
         assert_eq!(sub_commands[0].options().len(), 1);
 
         let option = &sub_commands[0].options()[0];
 
         assert_eq!(option.name, "longname");
         assert_eq!(option.short, Some('l'));
-        assert_eq!(option.has_param, true);
+        assert!(option.has_param);
         assert_eq!(
             option.description,
             Some("The description of longname".to_string())
@@ -960,11 +2132,195 @@ mod test {
         let arg = &sub_commands[1].args()[0];
 
         assert_eq!(arg.name, "arg1");
-        assert_eq!(arg.optional, true);
-        assert_eq!(arg.var_arg, false);
+        assert!(arg.optional);
+        assert!(!arg.var_arg);
         assert_eq!(arg.description, Some("The description of arg1".to_string()));
     }
 
+    #[test]
+    fn build_script_command_nests_dotted_sub_commands() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("foo.sh");
+
+        File::create(&script1_path)
+            .unwrap()
+            .write_all(
+                indoc! {"\
+            # @sub cluster.create
+            # @arg name The name of the cluster
+            function cluster.create(){}
+            # @sub cluster
+            # @about Manages clusters
+            # @opt region true The region of the cluster
+            # @sub node.pool.list
+            function node.pool.list(){}
+            "}
+                .as_bytes(),
+            )
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        let command = build_script_command(script1_path).unwrap().unwrap();
+
+        let sub_commands = command.sub_commands();
+        assert_eq!(sub_commands.len(), 2);
+
+        let cluster = &sub_commands[0];
+        assert_eq!(cluster.name(), "cluster");
+        assert_eq!(cluster.description(), Some("Manages clusters"));
+        assert_eq!(cluster.options().len(), 1);
+        assert!(cluster.sub_command_required());
+        assert_eq!(cluster.sub_commands().len(), 1);
+        assert_eq!(cluster.sub_commands()[0].name(), "create");
+        assert_eq!(cluster.sub_commands()[0].line(), Some(1));
+        assert_eq!(cluster.sub_commands()[0].args().len(), 1);
+        assert!(!cluster.sub_commands()[0].sub_command_required());
+
+        let node = &sub_commands[1];
+        assert_eq!(node.name(), "node");
+        assert_eq!(node.description(), None);
+        assert_eq!(node.sub_commands()[0].name(), "pool");
+        assert_eq!(node.sub_commands()[0].sub_commands()[0].name(), "list");
+    }
+
+    #[test]
+    fn build_script_command_reads_confirmations() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("foo.sh");
+
+        File::create(&script1_path)
+            .unwrap()
+            .write_all(
+                indoc! {"\
+            # @confirm
+            # @sub prod
+            # @confirm Deploy to production?
+            function prod(){}
+            "}
+                .as_bytes(),
+            )
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        let command = build_script_command(script1_path).unwrap().unwrap();
+
+        assert_eq!(command.confirmation(), Some(&Confirmation::new(None)));
+        assert_eq!(
+            command.sub_commands()[0]
+                .confirmation()
+                .map(Confirmation::text),
+            Some("Deploy to production?")
+        );
+    }
+
+    #[test]
+    fn build_script_command_rejects_reserved_option_names() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script_path = test_dir.path().join("foo.sh");
+
+        std::fs::write(&script_path, "# @sub bar\n# @opt help Show help\n").unwrap();
+
+        assert_eq!(
+            build_script_command(script_path).err(),
+            Some("The option name 'help' is reserved".to_string())
+        );
+    }
+
+    #[test]
+    fn build_script_command_reads_hooks() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("foo.sh");
+
+        File::create(&script1_path)
+            .unwrap()
+            .write_all(
+                indoc! {"\
+            # @pre login
+            # @sub deploy
+            # @post notify
+            function deploy(){}
+            "}
+                .as_bytes(),
+            )
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        let command = build_script_command(script1_path.clone()).unwrap().unwrap();
+
+        assert_eq!(
+            command.hooks(),
+            &Hooks::new(
+                Some(Hook::new(script1_path.clone(), Some("login".to_owned()))),
+                None
+            )
+        );
+        assert_eq!(
+            command.sub_commands()[0].hooks(),
+            &Hooks::new(
+                None,
+                Some(Hook::new(script1_path, Some("notify".to_owned())))
+            )
+        );
+    }
+
+    #[test]
+    fn build_script_command_reads_timeout_and_retry() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("foo.sh");
+
+        std::fs::write(&script1_path, "# @timeout 1m 30s\n# @retry 3 500ms\n").unwrap();
+
+        let command = build_script_command(script1_path.clone()).unwrap().unwrap();
+
+        assert_eq!(command.timeout(), Some(Duration::from_secs(90)));
+        assert_eq!(
+            command.retry(),
+            Some(&Retry::new(3, Some(Duration::from_millis(500))))
+        );
+
+        // Invalid values are ignored, and the wait defaults to a second
+        std::fs::write(&script1_path, "# @timeout soon\n# @retry 2\n").unwrap();
+
+        let command = build_script_command(script1_path).unwrap().unwrap();
+
+        assert_eq!(command.timeout(), None);
+        assert_eq!(command.retry(), Some(&Retry::new(2, None)));
+        assert_eq!(command.retry().unwrap().backoff, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn build_dir_hooks_reads_hooks_directory() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(build_dir_hooks(test_dir.path()), Hooks::default());
+
+        let hooks_dir = test_dir.path().join(HOOKS_DIR);
+        std::fs::create_dir(&hooks_dir).unwrap();
+        std::fs::write(hooks_dir.join("pre.sh"), "#!/bin/sh\n").unwrap();
+        std::fs::write(hooks_dir.join("helpers.sh"), "#!/bin/sh\n").unwrap();
+
+        assert_eq!(
+            build_dir_hooks(test_dir.path()),
+            Hooks::new(Some(Hook::new(hooks_dir.join("pre.sh"), None)), None)
+        );
+    }
+
+    #[test]
+    fn build_script_command_rejects_empty_sub_command_name() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("foo.sh");
+
+        File::create(&script1_path)
+            .unwrap()
+            .write_all(b"# @sub cluster..create\n")
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        assert!(build_script_command(script1_path).is_err());
+    }
+
     #[test]
     fn build_script_command_finds_tag_on_last_line() {
         let test_dir = tempfile::tempdir().unwrap();
@@ -973,19 +2329,437 @@ mod test {
 
         File::create(&script1_path)
             .unwrap()
-            .write(
+            .write_all(
                 indoc! {"\
                 # @about The description of this file"}
                 .as_bytes(),
             )
-            .expect(format!("Unable to create file {}", script1_path.to_str().unwrap()).as_str());
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
 
         let command = build_script_command(script1_path).unwrap().unwrap();
 
-        assert_eq!(command.name, "foo");
+        assert_eq!(command.name(), "foo");
         assert_eq!(
-            command.description.unwrap().as_str(),
+            command.description().unwrap(),
             "The description of this file"
         );
     }
+
+    #[test]
+    fn build_cli_metadata_reads_top_level_tags() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let metadata_path = test_dir.path().join("_cli.sh");
+
+        File::create(&metadata_path)
+            .unwrap()
+            .write_all(
+                indoc! {"\
+                # @name mycli
+                # @version 1.2.3
+                # @author Jane Doe
+                # @about The team cli
+                # @footer See the wiki for more
+                # @audit logs/mycli.log
+                # @opt verbose 'v' Print more
+                # @sub ignored
+                # @about Not the cli description
+                "}
+                .as_bytes(),
+            )
+            .unwrap_or_else(|_| {
+                panic!("Unable to create file {}", metadata_path.to_str().unwrap())
+            });
+
+        let metadata = build_cli_metadata(&metadata_path).unwrap();
+
+        assert_eq!(metadata.name, Some("mycli".to_string()));
+        assert_eq!(metadata.version, Some("1.2.3".to_string()));
+        assert_eq!(metadata.author, Some("Jane Doe".to_string()));
+        assert_eq!(metadata.description, Some("The team cli".to_string()));
+        assert_eq!(
+            metadata.after_help,
+            Some("See the wiki for more".to_string())
+        );
+        assert_eq!(
+            metadata.audit_log,
+            AuditLog::File(PathBuf::from("logs/mycli.log"))
+        );
+        assert_eq!(
+            metadata.options,
+            vec![CommandOption::new(
+                "verbose",
+                Some('v'),
+                false,
+                Some("Print more")
+            )]
+        );
+    }
+
+    #[test]
+    fn audit_tag_reads_optional_path() {
+        assert_eq!(
+            audit_tag::<&str, nom::error::Error<&str>>("\n").unwrap().1,
+            Some(DocTag::Audit(TextTag::new(String::new())))
+        );
+        assert_eq!(
+            audit_tag::<&str, nom::error::Error<&str>>(" /var/log/mycli.log \n")
+                .unwrap()
+                .1,
+            Some(DocTag::Audit(TextTag::new("/var/log/mycli.log".to_owned())))
+        );
+    }
+
+    #[test]
+    fn command_tag_finds_kind_and_definition() {
+        let input = indoc! {"
+            deployProd alias deploy --env prod
+            "};
+
+        let res = command_tag::<&str, nom::error::Error<&str>>(input);
+
+        let (_, tag) = res.unwrap();
+
+        assert_eq!(
+            tag.unwrap(),
+            DocTag::Command(CommandTag::new(
+                "deployProd".to_string(),
+                CommandKind::Alias,
+                "deploy --env prod".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn command_tag_ignores_unknown_kind() {
+        let input = indoc! {"
+            deployProd macro deploy --env prod
+            "};
+
+        let res = command_tag::<&str, nom::error::Error<&str>>(input);
+
+        let (_, tag) = res.unwrap();
+
+        assert_eq!(tag, None);
+    }
+
+    #[test]
+    fn relation_tags_find_names() {
+        let to_names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        let (_, tag) = conflicts_tag::<&str, nom::error::Error<&str>>(" json yaml  csv\n").unwrap();
+        assert_eq!(
+            tag.unwrap(),
+            DocTag::Relation(ArgRelation::Conflicts(to_names(&["json", "yaml", "csv"])))
+        );
+
+        let (_, tag) = requires_tag::<&str, nom::error::Error<&str>>(" user password\n").unwrap();
+        assert_eq!(
+            tag.unwrap(),
+            DocTag::Relation(ArgRelation::Requires(
+                "user".to_string(),
+                to_names(&["password"])
+            ))
+        );
+
+        let (_, tag) =
+            group_tag::<&str, nom::error::Error<&str>>(" source required file url\n").unwrap();
+        assert_eq!(
+            tag.unwrap(),
+            DocTag::Relation(ArgRelation::Group {
+                name: "source".to_string(),
+                required: true,
+                members: to_names(&["file", "url"]),
+            })
+        );
+
+        let (_, tag) = group_tag::<&str, nom::error::Error<&str>>(" source file url\n").unwrap();
+        assert_eq!(
+            tag.unwrap(),
+            DocTag::Relation(ArgRelation::Group {
+                name: "source".to_string(),
+                required: false,
+                members: to_names(&["file", "url"]),
+            })
+        );
+
+        // Too few names to relate
+        let (_, tag) = conflicts_tag::<&str, nom::error::Error<&str>>(" json\n").unwrap();
+        assert_eq!(tag, None);
+
+        let (_, tag) = group_tag::<&str, nom::error::Error<&str>>(" source required\n").unwrap();
+        assert_eq!(tag, None);
+    }
+
+    #[test]
+    fn check_references_rejects_unknown_names() {
+        let tags = |relation: ArgRelation| CommandTags {
+            opts: vec![CommandOption::new("json", None, false, NO_DESCRIPTION)],
+            args: vec![CommandArg::new(
+                "file",
+                true,
+                false,
+                ArgType::Unknown,
+                NO_DESCRIPTION,
+            )],
+            relations: vec![relation],
+            ..CommandTags::default()
+        };
+
+        assert!(tags(ArgRelation::Conflicts(vec![
+            "json".to_string(),
+            "file".to_string()
+        ]))
+        .check_references()
+        .is_ok());
+
+        assert_eq!(
+            tags(ArgRelation::Requires(
+                "json".to_string(),
+                vec!["yaml".to_string()]
+            ))
+            .check_references(),
+            Err("Unknown option or argument 'yaml'".to_string())
+        );
+
+        assert!(tags(ArgRelation::Group {
+            name: "json".to_string(),
+            required: false,
+            members: vec!["file".to_string()],
+        })
+        .check_references()
+        .is_err());
+    }
+
+    #[test]
+    fn check_references_rejects_invalid_option_names() {
+        let tags = |name: &str| CommandTags {
+            opts: vec![CommandOption::new(name, None, false, NO_DESCRIPTION)],
+            ..CommandTags::default()
+        };
+
+        assert!(tags("dry-run").check_references().is_ok());
+        assert_eq!(
+            tags("-dry").check_references(),
+            Err("Invalid option name '-dry', as names cannot start with '-'".to_string())
+        );
+        assert_eq!(
+            tags("help").check_references(),
+            Err("The option name 'help' is reserved".to_string())
+        );
+    }
+
+    #[test]
+    fn build_cli_metadata_rejects_invalid_option_names() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let metadata_path = test_dir.path().join("_cli.sh");
+
+        std::fs::write(
+            &metadata_path,
+            "# @opt dry-run Show what is run\n# @opt dry_run Show it too\n",
+        )
+        .unwrap();
+        assert_eq!(
+            build_cli_metadata(&metadata_path),
+            Err("The options 'dry-run' and 'dry_run' are both set as 'dry_run'".to_string())
+        );
+
+        std::fs::write(&metadata_path, "# @opt version Show the version\n").unwrap();
+        assert_eq!(
+            build_cli_metadata(&metadata_path),
+            Err("The option name 'version' is reserved".to_string())
+        );
+    }
+
+    #[test]
+    fn build_cli_metadata_logs_unless_turned_off() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let metadata_path = test_dir.path().join("_cli.sh");
+
+        std::fs::write(&metadata_path, "# @name mycli\n").unwrap();
+        assert_eq!(
+            build_cli_metadata(&metadata_path).unwrap().audit_log,
+            AuditLog::InHome
+        );
+
+        std::fs::write(&metadata_path, "# @name mycli\n# @audit off\n").unwrap();
+        assert_eq!(
+            build_cli_metadata(&metadata_path).unwrap().audit_log,
+            AuditLog::Off
+        );
+    }
+
+    #[test]
+    fn check_global_option_clashes_rejects_names_of_global_options() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script_path = test_dir.path().join("cloud.sh");
+
+        let metadata = CliMetadata {
+            options: vec![
+                CommandOption::new("verbose", Some('v'), false, NO_DESCRIPTION),
+                CommandOption::new("color", None, false, NO_DESCRIPTION)
+                    .with_kind(OptionKind::Negatable),
+            ],
+            ..CliMetadata::default()
+        };
+
+        let check = |script: &str| {
+            std::fs::write(&script_path, script).unwrap();
+
+            let command = build_script_command(script_path.clone()).unwrap().unwrap();
+
+            check_global_option_clashes(&metadata, &command)
+        };
+
+        assert_eq!(
+            check("# @opt quiet 'q' Print less\n# @arg region\n"),
+            Ok(())
+        );
+        assert_eq!(
+            check("# @sub login\n# @opt verbose Print more\n"),
+            Err(
+                "The option 'verbose' of 'cloud login' is an option of the cli already".to_string()
+            )
+        );
+        assert_eq!(
+            check("# @opt loud 'v' Print more\n"),
+            Err(
+                "The short name '-v' of the option 'loud' of 'cloud' is that of an option of the cli"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check("# @arg no-color\n"),
+            Err(
+                "The argument 'no-color' of 'cloud' has the name of an option of the cli"
+                    .to_string()
+            )
+        );
+
+        // Every command has the built-in global options too
+        assert_eq!(
+            check("# @opt explain Tell why\n"),
+            Err("The option 'explain' of 'cloud' is an option of the cli already".to_string())
+        );
+    }
+
+    #[test]
+    fn build_config_commands_builds_each_kind() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let metadata_path = test_dir.path().join("_cli.sh");
+
+        File::create(&metadata_path)
+            .unwrap()
+            .write_all(
+                indoc! {"\
+                # @about The team cli
+
+                # @command deployProd alias deploy --env prod
+
+                # @command today inline date +%F
+                # @about Prints the date
+                # @pre log_start
+
+                # @command k binary kubectl --context prod
+                # @opt namespace 'n' true The namespace
+                "}
+                .as_bytes(),
+            )
+            .unwrap_or_else(|_| {
+                panic!("Unable to create file {}", metadata_path.to_str().unwrap())
+            });
+
+        let commands = build_config_commands(&metadata_path).unwrap();
+
+        assert_eq!(commands.len(), 3);
+
+        let alias = &commands[0];
+        assert_eq!(alias.name(), "deployProd");
+        assert_eq!(alias.description(), Some("Alias for `deploy --env prod`"));
+        assert_eq!(
+            alias.expansion(),
+            Some(
+                &[
+                    "deploy".to_string(),
+                    "--env".to_string(),
+                    "prod".to_string()
+                ][..]
+            )
+        );
+        assert_eq!(alias.line(), Some(3));
+
+        let inline = &commands[1];
+        assert_eq!(inline.description(), Some("Prints the date"));
+        assert_eq!(
+            inline.hooks(),
+            &Hooks::new(
+                Some(Hook::new(
+                    metadata_path.clone(),
+                    Some("log_start".to_owned())
+                )),
+                None
+            )
+        );
+        assert_eq!(
+            inline.eval_code(&["x".to_string()]),
+            Some("cli_inline() {\ndate +%F\n}\ncli_inline 'x'\neval \"unset -f cli_inline; (exit $?)\"".to_string())
+        );
+
+        let binary = &commands[2];
+        assert!(binary.forwards_command_line());
+        assert_eq!(binary.options()[0].name, "namespace");
+        // With an option annotated, the binary does not accept any args
+        assert!(binary.args().is_empty());
+        assert_eq!(
+            binary.eval_code(&["--namespace".to_string(), "kube-system".to_string()]),
+            Some("command 'kubectl' '--context' 'prod' '--namespace' 'kube-system'".to_string())
+        );
+    }
+
+    #[test]
+    fn build_group_command_reads_group_file() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let group_path = test_dir.path().join("_group.sh");
+
+        File::create(&group_path)
+            .unwrap()
+            .write_all(
+                indoc! {"\
+                # @about Deployment commands
+                # @alias dp
+                # @alias ship
+                # @hidden
+                # @opt stage 's' true The stage
+                # @confirm Deploy?
+                # @post notify
+                "}
+                .as_bytes(),
+            )
+            .unwrap_or_else(|_| panic!("Unable to create file {}", group_path.to_str().unwrap()));
+
+        let group = build_group_command(test_dir.path(), Some(group_path.clone()), vec![]).unwrap();
+
+        assert_eq!(
+            group.name(),
+            test_dir.path().file_name().unwrap().to_str().unwrap()
+        );
+        assert_eq!(group.description(), Some("Deployment commands"));
+        assert_eq!(group.aliases(), ["dp".to_string(), "ship".to_string()]);
+        assert!(group.hidden());
+        assert_eq!(group.options()[0].name, "stage");
+        assert_eq!(group.confirmation().unwrap().text(), "Deploy?");
+        assert_eq!(
+            group.hooks(),
+            &Hooks::new(
+                None,
+                Some(Hook::new(group_path.clone(), Some("notify".to_owned())))
+            )
+        );
+        assert_eq!(group.get_path(), Some(&group_path));
+    }
 }
@@ -0,0 +1,615 @@
+//! Rendering of a reference of the whole CLI, for publishing e.g. in a wiki
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::model::{
+    Command, CommandArg, CommandOption, EnvVar, Model, OptionKind, NEGATION_PREFIX,
+};
+
+/// The formats in which the reference documentation can be rendered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocsFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for DocsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("markdown") || s.eq_ignore_ascii_case("md") {
+            Ok(DocsFormat::Markdown)
+        } else if s.eq_ignore_ascii_case("html") {
+            Ok(DocsFormat::Html)
+        } else {
+            Err(format!("Unknown docs format '{}'", s))
+        }
+    }
+}
+
+/// A command together with the names of the commands leading to it, starting with the cli name.
+struct Entry<'a> {
+    path: Vec<&'a str>,
+    command: &'a dyn Command,
+}
+
+impl Entry<'_> {
+    fn title(&self) -> String {
+        self.path.join(" ")
+    }
+
+    /// The id of the section of the command, which is to be escaped in HTML like the title.
+    fn anchor(&self) -> String {
+        self.path
+            .join("-")
+            .to_lowercase()
+            .replace(char::is_whitespace, "-")
+    }
+
+    /// The id of the section of the given sub-command of the command.
+    fn sub_anchor(&self, sub: &dyn Command) -> String {
+        format!("{}-{}", self.anchor(), sub.name().to_lowercase()).replace(char::is_whitespace, "-")
+    }
+
+    fn depth(&self) -> usize {
+        self.path.len() - 2
+    }
+
+    fn usage(&self) -> String {
+        let mut usage = self.title();
+
+        if !self.command.options().is_empty() {
+            usage.push_str(" [OPTIONS]");
+        }
+
+        self.command.args().iter().for_each(|arg| {
+            let value_name = arg.value_name.as_ref().unwrap_or(&arg.name);
+
+            let mut arg_usage = if arg.optional {
+                format!("[{}]", value_name)
+            } else {
+                format!("<{}>", value_name)
+            };
+
+            if arg.var_arg {
+                arg_usage.push_str("...");
+            }
+
+            usage.push(' ');
+            usage.push_str(&arg_usage);
+        });
+
+        if self.command.has_sub_commands() {
+            usage.push_str(" <COMMAND>");
+        }
+
+        usage
+    }
+}
+
+/// Collects the given commands and all their (transitive) sub-commands, depth first.
+fn collect_entries<'a>(
+    parent: &[&'a str],
+    commands: &'a [Box<dyn Command>],
+    entries: &mut Vec<Entry<'a>>,
+) {
+    commands
+        .iter()
+        .filter(|command| !command.hidden())
+        .for_each(|command| {
+            let mut path = parent.to_vec();
+            path.push(command.name());
+
+            entries.push(Entry {
+                path: path.clone(),
+                command: command.as_ref(),
+            });
+
+            collect_entries(&path, command.sub_commands(), entries);
+        });
+}
+
+fn option_usage(option: &CommandOption) -> String {
+    let mut usage = String::new();
+
+    if let Some(short) = option.short {
+        write!(&mut usage, "-{}, ", short).unwrap();
+    }
+
+    if option.kind == OptionKind::Negatable {
+        write!(&mut usage, "--[{}]{}", NEGATION_PREFIX, option.name).unwrap();
+    } else {
+        write!(&mut usage, "--{}", option.name).unwrap();
+    }
+
+    if option.has_param {
+        let value_name = option.value_name.as_ref().unwrap_or(&option.name);
+        write!(&mut usage, " <{}>", value_name).unwrap();
+    }
+
+    // Options that may be repeated are marked as for variadic arguments
+    if matches!(option.kind, OptionKind::Count | OptionKind::Many) {
+        usage.push_str("...");
+    }
+
+    usage
+}
+
+/// The description of an option or argument, noting the environment variable its value can be
+/// taken from, as in help.
+fn described(description: Option<&str>, env: Option<&EnvVar>) -> String {
+    match (description, env) {
+        (Some(description), Some(env)) => format!("{} [env: {}]", description, env.name),
+        (None, Some(env)) => format!("[env: {}]", env.name),
+        (description, None) => description.unwrap_or("").to_owned(),
+    }
+}
+
+fn arg_required(arg: &CommandArg) -> &'static str {
+    if arg.optional {
+        "no"
+    } else {
+        "yes"
+    }
+}
+
+/// Renders a reference of all commands in the model in the given format.
+pub fn render_docs(model: &Model, format: DocsFormat) -> String {
+    let name = model.metadata.name.as_deref().unwrap_or("cli");
+
+    let mut entries = vec![];
+    collect_entries(&[name], &model.commands, &mut entries);
+
+    match format {
+        DocsFormat::Markdown => render_markdown(model, name, &entries),
+        DocsFormat::Html => render_html(model, name, &entries),
+    }
+}
+
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn render_markdown(model: &Model, name: &str, entries: &[Entry]) -> String {
+    let mut out = String::new();
+
+    writeln!(&mut out, "# {}\n", name).unwrap();
+
+    if let Some(version) = model.metadata.version.as_ref() {
+        writeln!(&mut out, "Version {}\n", version).unwrap();
+    }
+
+    if let Some(description) = model.metadata.description.as_ref() {
+        writeln!(&mut out, "{}\n", description).unwrap();
+    }
+
+    if !model.metadata.options.is_empty() {
+        writeln!(&mut out, "## Global options\n").unwrap();
+        writeln!(&mut out, "| Option | Description |").unwrap();
+        writeln!(&mut out, "| --- | --- |").unwrap();
+
+        model.metadata.options.iter().for_each(|option| {
+            writeln!(
+                &mut out,
+                "| `{}` | {} |",
+                option_usage(option),
+                escape_markdown_cell(&described(
+                    option.description.as_deref(),
+                    option.env.as_ref()
+                ))
+            )
+            .unwrap();
+        });
+
+        writeln!(&mut out).unwrap();
+    }
+
+    writeln!(&mut out, "## Commands\n").unwrap();
+
+    entries.iter().for_each(|entry| {
+        writeln!(
+            &mut out,
+            "{}- [{}](#{})",
+            "  ".repeat(entry.depth()),
+            entry.title(),
+            entry.anchor()
+        )
+        .unwrap();
+    });
+
+    entries.iter().for_each(|entry| {
+        let command = entry.command;
+
+        writeln!(
+            &mut out,
+            "\n<a id=\"{}\"></a>",
+            escape_html(&entry.anchor())
+        )
+        .unwrap();
+        writeln!(&mut out, "## {}\n", entry.title()).unwrap();
+
+        if let Some(description) = command.description() {
+            writeln!(&mut out, "{}\n", description).unwrap();
+        }
+
+        if let Some(details) = command.details() {
+            writeln!(&mut out, "{}\n", details).unwrap();
+        }
+
+        writeln!(&mut out, "**Usage:** `{}`", entry.usage()).unwrap();
+
+        if !command.aliases().is_empty() {
+            writeln!(
+                &mut out,
+                "\n**Aliases:** {}",
+                command
+                    .aliases()
+                    .iter()
+                    .map(|alias| format!("`{}`", alias))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .unwrap();
+        }
+
+        if !command.options().is_empty() {
+            writeln!(&mut out, "\n### Options\n").unwrap();
+            writeln!(&mut out, "| Option | Description |").unwrap();
+            writeln!(&mut out, "| --- | --- |").unwrap();
+
+            command.options().iter().for_each(|option| {
+                writeln!(
+                    &mut out,
+                    "| `{}` | {} |",
+                    option_usage(option),
+                    escape_markdown_cell(&described(
+                        option.description.as_deref(),
+                        option.env.as_ref()
+                    ))
+                )
+                .unwrap();
+            });
+        }
+
+        if !command.args().is_empty() {
+            writeln!(&mut out, "\n### Arguments\n").unwrap();
+            writeln!(&mut out, "| Argument | Type | Required | Description |").unwrap();
+            writeln!(&mut out, "| --- | --- | --- | --- |").unwrap();
+
+            command.args().iter().for_each(|arg| {
+                writeln!(
+                    &mut out,
+                    "| `{}` | {} | {} | {} |",
+                    arg.name,
+                    arg.arg_type,
+                    arg_required(arg),
+                    escape_markdown_cell(&described(arg.description.as_deref(), arg.env.as_ref()))
+                )
+                .unwrap();
+            });
+        }
+
+        if !command.sub_commands().is_empty() {
+            writeln!(&mut out, "\n### Commands\n").unwrap();
+
+            command
+                .sub_commands()
+                .iter()
+                .filter(|sub| !sub.hidden())
+                .for_each(|sub| {
+                    let anchor = entry.sub_anchor(sub.as_ref());
+
+                    writeln!(
+                        &mut out,
+                        "- [{}](#{}){}",
+                        sub.name(),
+                        anchor,
+                        sub.description()
+                            .map(|description| format!(": {}", description))
+                            .unwrap_or_default()
+                    )
+                    .unwrap();
+                });
+        }
+
+        if !command.examples().is_empty() {
+            writeln!(&mut out, "\n### Examples\n").unwrap();
+            writeln!(&mut out, "```").unwrap();
+            command.examples().iter().for_each(|example| {
+                writeln!(&mut out, "{}", example).unwrap();
+            });
+            writeln!(&mut out, "```").unwrap();
+        }
+    });
+
+    if let Some(after_help) = model.metadata.after_help.as_ref() {
+        writeln!(&mut out, "\n---\n\n{}", after_help).unwrap();
+    }
+
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(model: &Model, name: &str, entries: &[Entry]) -> String {
+    let mut out = String::new();
+
+    writeln!(&mut out, "<!DOCTYPE html>").unwrap();
+    writeln!(&mut out, "<html>").unwrap();
+    writeln!(&mut out, "<head>").unwrap();
+    writeln!(&mut out, "<meta charset=\"utf-8\">").unwrap();
+    writeln!(&mut out, "<title>{}</title>", escape_html(name)).unwrap();
+    writeln!(&mut out, "</head>").unwrap();
+    writeln!(&mut out, "<body>").unwrap();
+    writeln!(&mut out, "<h1>{}</h1>", escape_html(name)).unwrap();
+
+    if let Some(version) = model.metadata.version.as_ref() {
+        writeln!(&mut out, "<p>Version {}</p>", escape_html(version)).unwrap();
+    }
+
+    if let Some(description) = model.metadata.description.as_ref() {
+        writeln!(&mut out, "<p>{}</p>", escape_html(description)).unwrap();
+    }
+
+    if !model.metadata.options.is_empty() {
+        writeln!(&mut out, "<h2>Global options</h2>").unwrap();
+        writeln!(&mut out, "<table>").unwrap();
+        writeln!(&mut out, "<tr><th>Option</th><th>Description</th></tr>").unwrap();
+
+        model.metadata.options.iter().for_each(|option| {
+            writeln!(
+                &mut out,
+                "<tr><td><code>{}</code></td><td>{}</td></tr>",
+                escape_html(&option_usage(option)),
+                escape_html(&described(
+                    option.description.as_deref(),
+                    option.env.as_ref()
+                ))
+            )
+            .unwrap();
+        });
+
+        writeln!(&mut out, "</table>").unwrap();
+    }
+
+    writeln!(&mut out, "<h2>Commands</h2>").unwrap();
+    writeln!(&mut out, "<ul>").unwrap();
+    entries.iter().for_each(|entry| {
+        writeln!(
+            &mut out,
+            "<li style=\"margin-left: {}em\"><a href=\"#{}\">{}</a></li>",
+            entry.depth() * 2,
+            escape_html(&entry.anchor()),
+            escape_html(&entry.title())
+        )
+        .unwrap();
+    });
+    writeln!(&mut out, "</ul>").unwrap();
+
+    entries.iter().for_each(|entry| {
+        let command = entry.command;
+
+        writeln!(
+            &mut out,
+            "<h2 id=\"{}\">{}</h2>",
+            escape_html(&entry.anchor()),
+            escape_html(&entry.title())
+        )
+        .unwrap();
+
+        if let Some(description) = command.description() {
+            writeln!(&mut out, "<p>{}</p>", escape_html(description)).unwrap();
+        }
+
+        if let Some(details) = command.details() {
+            writeln!(&mut out, "<p>{}</p>", escape_html(details)).unwrap();
+        }
+
+        writeln!(
+            &mut out,
+            "<p><strong>Usage:</strong> <code>{}</code></p>",
+            escape_html(&entry.usage())
+        )
+        .unwrap();
+
+        if !command.aliases().is_empty() {
+            writeln!(
+                &mut out,
+                "<p><strong>Aliases:</strong> {}</p>",
+                command
+                    .aliases()
+                    .iter()
+                    .map(|alias| format!("<code>{}</code>", escape_html(alias)))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .unwrap();
+        }
+
+        if !command.options().is_empty() {
+            writeln!(&mut out, "<h3>Options</h3>").unwrap();
+            writeln!(&mut out, "<table>").unwrap();
+            writeln!(&mut out, "<tr><th>Option</th><th>Description</th></tr>").unwrap();
+
+            command.options().iter().for_each(|option| {
+                writeln!(
+                    &mut out,
+                    "<tr><td><code>{}</code></td><td>{}</td></tr>",
+                    escape_html(&option_usage(option)),
+                    escape_html(&described(
+                        option.description.as_deref(),
+                        option.env.as_ref()
+                    ))
+                )
+                .unwrap();
+            });
+
+            writeln!(&mut out, "</table>").unwrap();
+        }
+
+        if !command.args().is_empty() {
+            writeln!(&mut out, "<h3>Arguments</h3>").unwrap();
+            writeln!(&mut out, "<table>").unwrap();
+            writeln!(
+                &mut out,
+                "<tr><th>Argument</th><th>Type</th><th>Required</th><th>Description</th></tr>"
+            )
+            .unwrap();
+
+            command.args().iter().for_each(|arg| {
+                writeln!(
+                    &mut out,
+                    "<tr><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&arg.name),
+                    arg.arg_type,
+                    arg_required(arg),
+                    escape_html(&described(arg.description.as_deref(), arg.env.as_ref()))
+                )
+                .unwrap();
+            });
+
+            writeln!(&mut out, "</table>").unwrap();
+        }
+
+        if !command.sub_commands().is_empty() {
+            writeln!(&mut out, "<h3>Commands</h3>").unwrap();
+            writeln!(&mut out, "<ul>").unwrap();
+
+            command
+                .sub_commands()
+                .iter()
+                .filter(|sub| !sub.hidden())
+                .for_each(|sub| {
+                    let anchor = entry.sub_anchor(sub.as_ref());
+
+                    writeln!(
+                        &mut out,
+                        "<li><a href=\"#{}\">{}</a>{}</li>",
+                        escape_html(&anchor),
+                        escape_html(sub.name()),
+                        sub.description()
+                            .map(|description| format!(": {}", escape_html(description)))
+                            .unwrap_or_default()
+                    )
+                    .unwrap();
+                });
+
+            writeln!(&mut out, "</ul>").unwrap();
+        }
+
+        if !command.examples().is_empty() {
+            writeln!(&mut out, "<h3>Examples</h3>").unwrap();
+            writeln!(
+                &mut out,
+                "<pre><code>{}</code></pre>",
+                escape_html(&command.examples().join("\n"))
+            )
+            .unwrap();
+        }
+    });
+
+    if let Some(after_help) = model.metadata.after_help.as_ref() {
+        writeln!(&mut out, "<hr>").unwrap();
+        writeln!(&mut out, "<p>{}</p>", escape_html(after_help)).unwrap();
+    }
+
+    writeln!(&mut out, "</body>").unwrap();
+    writeln!(&mut out, "</html>").unwrap();
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::model::test::NO_DESCRIPTION;
+    use crate::model::{ArgType, CliMetadata, EmbeddedCommand, ScriptCommand};
+
+    use super::*;
+
+    fn model() -> Model {
+        let sub = EmbeddedCommand::new(
+            "bar",
+            Some("Does bar"),
+            vec![CommandOption::new(
+                "loud",
+                Some('l'),
+                false,
+                Some("Be | loud"),
+            )],
+            vec![CommandArg::new(
+                "file",
+                true,
+                false,
+                ArgType::File,
+                NO_DESCRIPTION,
+            )],
+        );
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            Some("Does foo".to_owned()),
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(sub)],
+        );
+
+        Model::new(vec![Box::new(foo)]).with_metadata(CliMetadata {
+            name: Some("mycli".to_string()),
+            ..CliMetadata::default()
+        })
+    }
+
+    #[test]
+    fn docs_format_from_str() {
+        assert_eq!(DocsFormat::from_str("markdown"), Ok(DocsFormat::Markdown));
+        assert_eq!(DocsFormat::from_str("MD"), Ok(DocsFormat::Markdown));
+        assert_eq!(DocsFormat::from_str("html"), Ok(DocsFormat::Html));
+        assert!(DocsFormat::from_str("pdf").is_err());
+    }
+
+    #[test]
+    fn markdown_has_toc_and_anchors() {
+        let docs = render_docs(&model(), DocsFormat::Markdown);
+
+        assert!(docs.starts_with("# mycli\n"));
+        assert!(docs.contains("- [mycli foo](#mycli-foo)\n  - [mycli foo bar](#mycli-foo-bar)\n"));
+        assert!(docs.contains("<a id=\"mycli-foo-bar\"></a>\n## mycli foo bar\n"));
+        assert!(docs.contains("**Usage:** `mycli foo bar [OPTIONS] [file]`"));
+        assert!(docs.contains("| `-l, --loud` | Be \\| loud |"));
+        assert!(docs.contains("| `file` | file | no |  |"));
+        assert!(docs.contains("- [bar](#mycli-foo-bar): Does bar"));
+    }
+
+    #[test]
+    fn html_has_toc_and_anchors() {
+        let docs = render_docs(&model(), DocsFormat::Html);
+
+        assert!(docs.contains("<a href=\"#mycli-foo-bar\">mycli foo bar</a>"));
+        assert!(docs.contains("<h2 id=\"mycli-foo-bar\">mycli foo bar</h2>"));
+        assert!(docs.contains("<code>mycli foo bar [OPTIONS] [file]</code>"));
+    }
+
+    #[test]
+    fn html_escapes_anchors() {
+        let mut model = model();
+        model.add_command(EmbeddedCommand::new(
+            "my \"tool\"",
+            NO_DESCRIPTION,
+            vec![],
+            vec![],
+        ));
+
+        let docs = render_docs(&model, DocsFormat::Html);
+
+        assert!(docs.contains("<a href=\"#mycli-my-&quot;tool&quot;\">"));
+        assert!(docs.contains("<h2 id=\"mycli-my-&quot;tool&quot;\">"));
+    }
+}
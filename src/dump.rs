@@ -0,0 +1,280 @@
+//! Serialization of the model, for use by tools built on top of easy-cli
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::model::{
+    ArgRelation, Command, CommandArg, CommandOption, Confirmation, EnvVar, Hook, Hooks, Model,
+};
+
+/// The version of the JSON format. It is increased whenever the format changes in a way that is
+/// not backwards-compatible.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The JSON schema describing the format produced by [dump_model].
+pub const JSON_SCHEMA: &str = include_str!("../schema/model.schema.json");
+
+/// The formats in which the model can be dumped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    Json,
+    JsonSchema,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("json") {
+            Ok(DumpFormat::Json)
+        } else if s.eq_ignore_ascii_case("json-schema") {
+            Ok(DumpFormat::JsonSchema)
+        } else {
+            Err(format!("Unknown dump format '{}'", s))
+        }
+    }
+}
+
+/// Renders the model in the given format; the schema format renders the schema of the JSON format
+/// rather than the model itself.
+pub fn dump(model: &Model, format: DumpFormat) -> String {
+    match format {
+        DumpFormat::Json => {
+            serde_json::to_string_pretty(&dump_model(model)).expect("Failed to serialize the model")
+        }
+        DumpFormat::JsonSchema => JSON_SCHEMA.to_owned(),
+    }
+}
+
+/// Converts the model into its JSON representation.
+pub fn dump_model(model: &Model) -> Value {
+    json!({
+        "format_version": FORMAT_VERSION,
+        "name": model.metadata.name,
+        "version": model.metadata.version,
+        "author": model.metadata.author,
+        "description": model.metadata.description,
+        "after_help": model.metadata.after_help,
+        "options": model.metadata.options.iter().map(dump_option).collect::<Vec<Value>>(),
+        "hooks": dump_hooks(&model.metadata.hooks),
+        "commands": model
+            .commands
+            .iter()
+            .map(|command| dump_command(command.as_ref(), &[], None))
+            .collect::<Vec<Value>>(),
+    })
+}
+
+/// Converts a command into its JSON representation. Commands without a file of their own are
+/// defined in the file of their parent.
+fn dump_command(command: &dyn Command, parent: &[&str], parent_file: Option<&Path>) -> Value {
+    let mut path = parent.to_vec();
+    path.push(command.name());
+
+    let file = command
+        .get_path()
+        .map(|path| path.as_path())
+        .or(parent_file);
+
+    json!({
+        "name": command.name(),
+        "path": path,
+        "description": command.description(),
+        "details": command.details(),
+        "examples": command.examples(),
+        "aliases": command.aliases(),
+        "hidden": command.hidden(),
+        "source": {
+            "file": file.map(|file| file.to_string_lossy()),
+            "line": command.line(),
+        },
+        "options": command.options().iter().map(dump_option).collect::<Vec<Value>>(),
+        "args": command.args().iter().map(dump_arg).collect::<Vec<Value>>(),
+        "relations": command.relations().iter().map(dump_relation).collect::<Vec<Value>>(),
+        "confirm": command.confirmation().map(Confirmation::text),
+        "hooks": dump_hooks(command.hooks()),
+        "timeout": command.timeout().map(|timeout| timeout.as_secs_f64()),
+        "retry": command.retry().map(|retry| json!({
+            "count": retry.count,
+            "backoff": retry.backoff.as_secs_f64(),
+        })),
+        "commands": command
+            .sub_commands()
+            .iter()
+            .map(|sub| dump_command(sub.as_ref(), &path, file))
+            .collect::<Vec<Value>>(),
+    })
+}
+
+fn dump_option(option: &CommandOption) -> Value {
+    json!({
+        "name": option.name,
+        "short": option.short.map(String::from),
+        "has_param": option.has_param,
+        "kind": option.kind.to_string(),
+        "description": option.description,
+        "env": option.env.as_ref().map(dump_env),
+        "value_name": option.value_name,
+        "heading": option.heading,
+    })
+}
+
+fn dump_arg(arg: &CommandArg) -> Value {
+    json!({
+        "name": arg.name,
+        "optional": arg.optional,
+        "var_arg": arg.var_arg,
+        "type": arg.arg_type.to_string(),
+        "description": arg.description,
+        "env": arg.env.as_ref().map(dump_env),
+        "value_name": arg.value_name,
+        "heading": arg.heading,
+        "prompt": arg.prompt,
+    })
+}
+
+fn dump_env(env: &EnvVar) -> Value {
+    json!({
+        "name": env.name,
+        "secret": env.secret,
+    })
+}
+
+fn dump_hooks(hooks: &Hooks) -> Value {
+    json!({
+        "pre": hooks.pre.as_ref().map(dump_hook),
+        "post": hooks.post.as_ref().map(dump_hook),
+    })
+}
+
+fn dump_hook(hook: &Hook) -> Value {
+    json!({
+        "file": hook.path.to_string_lossy(),
+        "function": hook.function,
+    })
+}
+
+fn dump_relation(relation: &ArgRelation) -> Value {
+    match relation {
+        ArgRelation::Conflicts(names) => json!({
+            "kind": "conflicts",
+            "names": names,
+        }),
+        ArgRelation::Requires(name, required) => json!({
+            "kind": "requires",
+            "name": name,
+            "names": required,
+        }),
+        ArgRelation::Group {
+            name,
+            required,
+            members,
+        } => json!({
+            "kind": "group",
+            "name": name,
+            "required": required,
+            "names": members,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::model::{ArgType, EmbeddedCommand, ScriptCommand, WithMeta};
+
+    use super::*;
+
+    #[test]
+    fn dump_format_from_str() {
+        assert_eq!(DumpFormat::from_str("json"), Ok(DumpFormat::Json));
+        assert_eq!(
+            DumpFormat::from_str("json-schema"),
+            Ok(DumpFormat::JsonSchema)
+        );
+        assert!(DumpFormat::from_str("yaml").is_err());
+    }
+
+    #[test]
+    fn schema_is_valid_json() {
+        let schema: Value = serde_json::from_str(JSON_SCHEMA).unwrap();
+
+        assert_eq!(
+            schema["properties"]["format_version"]["const"],
+            json!(FORMAT_VERSION)
+        );
+    }
+
+    #[test]
+    fn dump_includes_sub_commands_with_source() {
+        let sub = EmbeddedCommand::new(
+            "bar",
+            Some("Does bar"),
+            vec![CommandOption::new(
+                "loud",
+                Some('l'),
+                false,
+                Some("Be loud"),
+            )],
+            vec![CommandArg::new(
+                "file",
+                true,
+                false,
+                ArgType::File,
+                Some("A file"),
+            )],
+        )
+        .with_line(Some(7));
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(sub)],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+
+        let dumped = dump_model(&model);
+
+        assert_eq!(dumped["format_version"], json!(FORMAT_VERSION));
+
+        let foo = &dumped["commands"][0];
+        assert_eq!(foo["source"], json!({"file": "/tmp/foo.sh", "line": null}));
+
+        let bar = &foo["commands"][0];
+        assert_eq!(bar["path"], json!(["foo", "bar"]));
+        assert_eq!(bar["source"], json!({"file": "/tmp/foo.sh", "line": 7}));
+        assert_eq!(
+            bar["options"][0],
+            json!({
+                "name": "loud",
+                "short": "l",
+                "has_param": false,
+                "kind": "single",
+                "description": "Be loud",
+                "env": null,
+                "value_name": null,
+                "heading": null
+            })
+        );
+        assert_eq!(
+            bar["args"][0],
+            json!({
+                "name": "file",
+                "optional": true,
+                "var_arg": false,
+                "type": "file",
+                "prompt": false,
+                "description": "A file",
+                "env": null,
+                "value_name": null,
+                "heading": null
+            })
+        );
+    }
+}
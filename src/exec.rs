@@ -0,0 +1,1665 @@
+//! Execution of the command selected by the command line, either directly (executed mode) or by
+//! emitting a script for the calling shell to evaluate (evaluated mode).
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, exit};
+
+use clap::error::{Error, ErrorKind};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use log::debug;
+
+use crate::history::{history_action, HistoryAction, HistoryEntry};
+use crate::hooks::{run_hooked, write_hooked, HookRun};
+use crate::model::{
+    Command, CommandArg, CommandOption, Confirmation, HasSubCommands, Model, OptionKind,
+};
+use crate::prompt::{confirm, flag, pending_confirmation, pick_command, prompt_missing_args};
+use crate::repl::{repl_requested, run_repl};
+use crate::supervise::supervision_lines;
+use crate::utils::shell_quote;
+use crate::watch::{run_watched, watch_globs};
+
+/// The prefix of the environment variables holding the values of the root options in executed mode.
+pub const ROOT_OPTION_ENV_PREFIX: &str = "CLI_OPT_";
+
+/// The name of the global flag explaining how the selected command would be run, instead of
+/// running it.
+pub const DRY_RUN_OPTION: &str = "dry-run";
+
+/// A further name of the [DRY_RUN_OPTION] flag.
+pub const EXPLAIN_ALIAS: &str = "explain";
+
+/// Shown instead of the values of secret environment variables.
+pub(crate) const SECRET_VALUE: &str = "<secret>";
+
+/// Builds the script to be evaluated by the calling shell, which sets up the options and arguments and runs
+/// the selected command. If the arguments do not match the cli, the script echoes the error instead.
+pub fn build_embedded_script(model: &Model, cli: clap::Command, cli_args: Vec<String>) -> Vec<u8> {
+    let cli_args = expand_aliases(model, &cli, cli_args);
+    let cli_args = hoist_dry_run(&cli, cli_args);
+
+    build_expanded_script(model, cli, cli_args)
+}
+
+/// Builds the script as [build_embedded_script] does, for a command line whose aliases have been
+/// expanded.
+fn build_expanded_script(model: &Model, mut cli: clap::Command, cli_args: Vec<String>) -> Vec<u8> {
+    cli.try_get_matches_from_mut(cli_args.iter()).map_or_else(
+        |err| {
+            // Render the error. This is also where help and usage messages are rendered, since they are represented
+            // as errors in clap.
+            echo_error_script(err)
+        },
+        |matches| {
+            // render shell commands to execute the appropriate script, having setup the parameters
+            exec_commands_script(model, matches)
+        },
+    )
+}
+
+/// Writes the script built by [build_embedded_script] to stdout.
+pub fn write_embedded_script(model: &mut Model, cli: clap::Command, cli_args: Vec<String>) {
+    // In embedded mode, don't let clap print to stdout because stdout is to be evaled. So we need to capture
+    // version and help requests (which are returned here as errors)
+
+    // The commands run in the session print to stderr, as stdout is to be evaluated
+    if repl_requested(&cli, &cli_args) {
+        run_repl(model, cli, true);
+        return;
+    }
+
+    let cli_args = match history_action(model, &cli, &cli_args) {
+        None => cli_args,
+        Some(HistoryAction::Rerun(cli_args)) => cli_args,
+        Some(HistoryAction::Show(text)) => {
+            io::stdout()
+                .write_all(&echo_text_script(&text))
+                .expect("Failed to write to stdout");
+            return;
+        }
+        Some(HistoryAction::Failed(message)) => {
+            io::stdout()
+                .write_all(&echo_text_script(&format!("{}\n", message)))
+                .expect("Failed to write to stdout");
+            return;
+        }
+    };
+
+    let cli_args = pick_command(model, &cli, cli_args);
+    let cli_args = expand_aliases(model, &cli, cli_args);
+    let cli_args = hoist_dry_run(&cli, cli_args);
+    let cli_args = prompt_missing_args(model, &cli, cli_args);
+
+    // Watched commands are run directly, as the calling shell would only run them once
+    if let Ok(arg_matches) = cli.clone().try_get_matches_from(cli_args.iter()) {
+        let globs = watch_globs(model, &arg_matches);
+
+        if !globs.is_empty() {
+            run_watched(model, &arg_matches, &globs, true);
+            return;
+        }
+    }
+
+    let buffer = build_expanded_script(model, cli, cli_args);
+
+    // Write the produced content to stdout
+    io::stdout()
+        .write_all(&buffer)
+        .expect("Failed to write to stdout");
+}
+
+/// Replaces an alias at the start of the command line, after any root options, with the command
+/// line it expands to; aliases may expand to other aliases, but an alias is not expanded within its
+/// own expansion.
+pub fn expand_aliases(
+    model: &Model,
+    cli: &clap::Command,
+    mut cli_args: Vec<String>,
+) -> Vec<String> {
+    let mut expanded = Vec::<String>::new();
+
+    while let Some(position) = command_position(cli, &cli_args) {
+        let command_name = cli_args[position].clone();
+
+        let expansion = match model
+            .get_command(&command_name)
+            .and_then(Command::expansion)
+        {
+            Some(expansion) if !expanded.contains(&command_name) => expansion,
+            _ => break,
+        };
+
+        debug!(
+            "expanding alias {} to {}",
+            command_name,
+            expansion.join(" ")
+        );
+
+        cli_args.splice(position..position + 1, expansion.iter().cloned());
+        expanded.push(command_name);
+    }
+
+    cli_args
+}
+
+/// Moves the dry-run flag given after the command name before it, so that a trailing var-arg of
+/// the command does not take the flag as one of its values, and the command is explained rather
+/// than run. Words after `--` are left alone, so that the flag can still be passed on that way.
+pub fn hoist_dry_run(cli: &clap::Command, mut cli_args: Vec<String>) -> Vec<String> {
+    let Some(dry_run) = cli
+        .get_arguments()
+        .find(|arg| arg.get_id() == DRY_RUN_OPTION && !arg.get_action().takes_values())
+    else {
+        return cli_args;
+    };
+
+    let words: Vec<String> = dry_run
+        .get_long()
+        .into_iter()
+        .chain(dry_run.get_all_aliases().into_iter().flatten())
+        .map(|long| format!("--{}", long))
+        .collect();
+
+    let Some(position) = command_position(cli, &cli_args) else {
+        return cli_args;
+    };
+
+    // The words after the command name, up to any `--`
+    let end = cli_args[position..]
+        .iter()
+        .position(|word| word == "--")
+        .map_or(cli_args.len(), |index| position + index);
+    let after_command = position + 1..end;
+
+    if cli_args[after_command.clone()]
+        .iter()
+        .any(|word| words.contains(word))
+    {
+        let kept: Vec<String> = cli_args
+            .drain(after_command)
+            .filter(|word| !words.contains(word))
+            .collect();
+
+        cli_args.splice(position + 1..position + 1, kept);
+        cli_args.insert(1, words[0].clone());
+    }
+
+    cli_args
+}
+
+/// The position of the command name in the command line, after the options of the root command
+/// given before it; `None` if there is no command name.
+fn command_position(cli: &clap::Command, cli_args: &[String]) -> Option<usize> {
+    let mut position = 1;
+
+    while let Some(word) = cli_args.get(position) {
+        let takes_value = if word == "--" {
+            return None;
+        } else if let Some(long) = word.strip_prefix("--") {
+            !long.contains('=')
+                && cli
+                    .get_arguments()
+                    .find(|arg| {
+                        arg.get_long() == Some(long)
+                            || arg
+                                .get_all_aliases()
+                                .is_some_and(|aliases| aliases.contains(&long))
+                    })
+                    .is_some_and(|arg| arg.get_action().takes_values())
+        } else if let Some(shorts) = word.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+            // Only the last of several short flags can take its value from the next word
+            let value_at = shorts.char_indices().find(|(_, short)| {
+                cli.get_arguments()
+                    .any(|arg| arg.get_short() == Some(*short) && arg.get_action().takes_values())
+            });
+
+            value_at.is_some_and(|(index, short)| index + short.len_utf8() == shorts.len())
+        } else {
+            return Some(position);
+        };
+
+        position += if takes_value { 2 } else { 1 };
+    }
+
+    None
+}
+
+/// Builds a script printing the given text.
+fn echo_text_script(text: &str) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write!(&mut buffer, "printf '%s' {}", shell_quote(text)).expect("Failed to write to buffer");
+    buffer
+}
+
+fn echo_error_script(err: Error) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    write!(
+        &mut buffer,
+        "printf '%s\\n' {}",
+        shell_quote(&err.render().ansi().to_string())
+    )
+    .expect("Failed to write to buffer");
+    buffer
+}
+
+/// The command selected by a command line, resolved from its matches once for both modes: the
+/// commands along the path to it, the values given for them, and how it is run.
+struct Selection<'a> {
+    /// The name of the top-level command.
+    name: &'a str,
+    /// The top-level command.
+    command: &'a dyn Command,
+    /// The selected command, which is the top-level command or one of its sub-commands.
+    selected: &'a dyn Command,
+    /// The names of the sub-commands leading to the selected command.
+    sub_commands: Vec<String>,
+    /// The root options and those of the commands along the path, with the matches holding their
+    /// values.
+    opts: Vec<(&'a CommandOption, &'a ArgMatches)>,
+    /// The names and values of the arguments of the commands along the path.
+    args: Vec<(&'a str, String)>,
+    /// The args the selected command is run with.
+    command_args: Vec<String>,
+    /// The confirmation of the innermost command asking for one.
+    confirmation: Option<&'a Confirmation>,
+    /// The file of the innermost command along the path that has one.
+    path: Option<&'a PathBuf>,
+    /// The names of the nested sub-commands defined in the file at path.
+    function: Vec<&'a str>,
+    /// The environment variables values were taken from.
+    environment: Vec<(String, String)>,
+    /// The hooks of the cli and of the commands along the path.
+    hooks: HookRun<'a>,
+}
+
+impl<'a> Selection<'a> {
+    /// Resolves the command selected by the matches, which must select one.
+    fn new(model: &'a Model, arg_matches: &'a ArgMatches) -> Self {
+        let (name, matches) = arg_matches.subcommand().unwrap();
+
+        let command = model.get_command(name).unwrap();
+
+        debug!("args-{}", command.name());
+
+        let mut selected = command;
+        let mut current = matches;
+
+        // The root options come first
+        let mut opts: Vec<(&CommandOption, &ArgMatches)> = model
+            .metadata
+            .options
+            .iter()
+            .map(|option| (option, arg_matches))
+            .collect();
+        let mut args = Vec::<(&str, String)>::new();
+
+        let mut inherited = Vec::<&CommandOption>::new();
+        let mut confirmation = command.confirmation();
+
+        // The commands along the path to the selected one, whose hooks are run
+        let mut commands = vec![command];
+
+        let mut path: Option<&PathBuf> = command.get_path();
+        let mut function = Vec::<&str>::new();
+        let mut environment = env_values(arg_matches, &model.metadata.options, &[]);
+
+        // recursively collect subcommand names into a vector while it is not None
+        let mut sub_commands = vec![];
+        loop {
+            add_opts_and_args(current, selected, &mut opts, &mut args);
+            environment.extend(env_values(current, selected.options(), selected.args()));
+
+            match current.subcommand() {
+                None => break,
+                Some((sub_name, sub_matches)) => {
+                    sub_commands.push(sub_name.to_owned());
+                    current = sub_matches;
+                    inherited.extend(selected.options());
+                    selected = selected.get_command(sub_name).unwrap();
+                    confirmation = selected.confirmation().or(confirmation);
+                    commands.push(selected);
+
+                    if let Some(new_path) = selected.get_path() {
+                        path = Some(new_path);
+                        function.clear();
+                    } else {
+                        function.push(selected.name());
+                    }
+                }
+            }
+        }
+
+        let command_args = exec_args(current, selected, &inherited);
+
+        let hooks = HookRun::new(model, &commands, &command_args);
+
+        Selection {
+            name,
+            command,
+            selected,
+            sub_commands,
+            opts,
+            args,
+            command_args,
+            confirmation,
+            path,
+            function,
+            environment,
+            hooks,
+        }
+    }
+
+    /// The names the selected command is called by on the command line.
+    fn command_line(&self) -> String {
+        std::iter::once(self.name)
+            .chain(self.sub_commands.iter().map(String::as_str))
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    /// The args the top-level command is run with in executed mode: the names of the sub-commands,
+    /// followed by the args of the selected command.
+    fn process_args(&self) -> Vec<String> {
+        self.sub_commands
+            .iter()
+            .chain(&self.command_args)
+            .cloned()
+            .collect()
+    }
+}
+
+fn exec_commands_script(model: &Model, arg_matches: clap::ArgMatches) -> Vec<u8> {
+    let selection = Selection::new(model, &arg_matches);
+    let command_line = selection.command_line();
+
+    let Selection {
+        selected,
+        opts,
+        args,
+        command_args,
+        confirmation,
+        path,
+        function,
+        environment,
+        hooks,
+        ..
+    } = selection;
+
+    let code = selected.eval_code(&command_args);
+
+    if flag(&arg_matches, DRY_RUN_OPTION) {
+        let mut lines = vec![("command", command_line)];
+
+        match code.as_ref() {
+            Some(code) => lines.push(("code", code.clone())),
+            None => lines.push((
+                "script",
+                path.map(|path| path.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            )),
+        }
+
+        lines.push(("interpreter", "the calling shell".to_owned()));
+
+        if !function.is_empty() {
+            lines.push(("function", function.join(".")));
+        }
+
+        lines.extend(hooks.lines());
+
+        lines.extend(args.iter().map(|(name, value)| {
+            (
+                "variable",
+                format!("cli_args[{}]={}", name, shell_quote(value)),
+            )
+        }));
+        lines.extend(opts.iter().map(|(option, matches)| {
+            (
+                "variable",
+                format!(
+                    "cli_opts[{}]={}",
+                    option.var_name(),
+                    opt_value(matches, option)
+                ),
+            )
+        }));
+        lines.extend(
+            environment
+                .into_iter()
+                .map(|(name, value)| ("environment", format!("{}={}", name, value))),
+        );
+
+        return echo_text_script(&explanation(&lines));
+    }
+
+    let confirmation = match pending_confirmation(confirmation, &arg_matches) {
+        Ok(confirmation) => confirmation,
+        Err(message) => return echo_error_script(unconfirmed_error(message)),
+    };
+
+    let mut buffer = Vec::new();
+
+    writeln!(&mut buffer, "#eval").expect("Failed to write to buffer");
+    writeln!(&mut buffer, "typeset -A cli_args").expect("Failed to write to buffer");
+    writeln!(
+        &mut buffer,
+        "cli_args=({})",
+        args.iter()
+            .map(|arg| format!("\"{}\" {}", arg.0, shell_quote(&arg.1)))
+            .collect::<Vec<String>>()
+            .join(" ")
+    )
+    .expect("Failed to write to buffer");
+    writeln!(&mut buffer, "typeset -A cli_opts").expect("Failed to write to buffer");
+    writeln!(
+        &mut buffer,
+        "cli_opts=({})",
+        opts.iter()
+            .map(|(option, matches)| {
+                format!("\"{}\" {}", option.var_name(), opt_value(matches, option))
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    )
+    .expect("Failed to write to buffer");
+
+    // The values of options that may be given many times are also set as arrays
+    opts.iter()
+        .filter(|(option, _)| option.kind == OptionKind::Many)
+        .for_each(|(option, matches)| {
+            writeln!(
+                &mut buffer,
+                "cli_opts_{}=({})",
+                option.var_name(),
+                raw_values(matches, &option.name)
+                    .iter()
+                    .map(|value| shell_quote(value))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            )
+            .expect("Failed to write to buffer");
+        });
+
+    let mut run = Vec::new();
+
+    match code {
+        Some(code) => writeln!(&mut run, "{}", code).expect("Failed to write to buffer"),
+        None => {
+            let path = path.expect("A command without eval code must have a script");
+
+            writeln!(&mut run, "source {}", shell_quote(path.to_str().unwrap()))
+                .expect("Failed to write to buffer");
+
+            // The function of a nested sub-command is named by the dotted path to it
+            if selected.get_path().is_none() {
+                writeln!(&mut run, "{}", function.join(".")).expect("Failed to write to buffer");
+            }
+        }
+    }
+
+    // The command is logged once it has finished
+    let finally = HistoryEntry::new(model, &arg_matches)
+        .map(|entry| entry.append_script())
+        .unwrap_or_default();
+
+    let mut hooked = Vec::new();
+    write_hooked(&mut hooked, &hooks, &run, &finally);
+
+    match confirmation {
+        Some(confirmation) => write_confirmed(&mut buffer, confirmation, &hooked),
+        None => buffer.extend(hooked),
+    }
+
+    buffer
+}
+
+/// The error for a command that cannot be run as it cannot be confirmed.
+fn unconfirmed_error(message: String) -> Error {
+    Error::raw(ErrorKind::MissingRequiredArgument, format!("{}\n", message))
+}
+
+/// Writes shell code asking for the confirmation, and running the given code only if the user
+/// answers yes.
+fn write_confirmed(buffer: &mut Vec<u8>, confirmation: &Confirmation, run: &[u8]) {
+    writeln!(
+        buffer,
+        "printf '%s [y/N] ' {} >&2\nread -r cli_confirmed\ncase \"$cli_confirmed\" in\n[yY]*)",
+        shell_quote(confirmation.text())
+    )
+    .expect("Failed to write to buffer");
+    buffer.extend(run);
+    writeln!(buffer, ";;\n*)\necho \"Aborted\" >&2\nfalse\n;;\nesac")
+        .expect("Failed to write to buffer");
+}
+
+/// The value of an option in `cli_opts`, quoted unless it is a flag or a count.
+fn opt_value(matches: &ArgMatches, option: &CommandOption) -> String {
+    let text = opt_text(matches, option).unwrap_or_default();
+
+    if option.has_param || option.kind == OptionKind::Negatable {
+        shell_quote(&text)
+    } else {
+        text
+    }
+}
+
+/// The value of an option as a single string: the value given, `true` or `false` for a flag, the
+/// number of times a counted flag is given, the values of an option given many times joined by
+/// commas, or `true`, `false` or empty for a negatable flag. `None` if an option with a parameter
+/// is not given.
+fn opt_text(matches: &ArgMatches, option: &CommandOption) -> Option<String> {
+    let name = option.name.as_str();
+
+    match (option.has_param, option.kind) {
+        (true, OptionKind::Many) => Some(raw_values(matches, name).join(",")),
+        (true, _) => matches.get_one::<String>(name).cloned(),
+        (false, OptionKind::Count) => Some(matches.get_count(name).to_string()),
+        (false, OptionKind::Negatable) => Some(negatable_value(matches, option).to_owned()),
+        (false, _) => Some(matches.get_flag(name).to_string()),
+    }
+}
+
+/// The tri-state value of a negatable flag: `true` if it was turned on, `false` if it was turned
+/// off, and empty if neither.
+fn negatable_value(matches: &ArgMatches, option: &CommandOption) -> &'static str {
+    if matches.get_flag(&option.name) {
+        "true"
+    } else if matches.get_flag(&option.negation()) {
+        "false"
+    } else {
+        ""
+    }
+}
+
+fn add_opts_and_args<'a>(
+    matches: &'a ArgMatches,
+    command: &'a dyn Command,
+    opts: &mut Vec<(&'a CommandOption, &'a ArgMatches)>,
+    args: &mut Vec<(&'a str, String)>,
+) {
+    matches.ids().for_each(|id| {
+        let name = id.as_str();
+
+        if let Some(option) = command.get_option(name) {
+            opts.push((option, matches));
+        }
+
+        if command.get_arg(name).is_some() {
+            let value_str = matches
+                .get_raw(name)
+                .map(|value| {
+                    let strings = value
+                        .map(|v| v.to_str().unwrap().to_string())
+                        .collect::<Vec<String>>();
+                    strings.join(",")
+                })
+                .unwrap_or("".to_string());
+            args.push((name, value_str));
+        }
+    });
+}
+
+/// Matches the arguments against the cli, and executes the selected command directly, passing the
+/// sub-command names and arguments to it.
+pub fn execute_cli(model: &mut Model, cli: clap::Command, cli_args: Vec<String>) {
+    if repl_requested(&cli, &cli_args) {
+        run_repl(model, cli, false);
+        exit(0);
+    }
+
+    let cli_args = match history_action(model, &cli, &cli_args) {
+        None => cli_args,
+        Some(HistoryAction::Rerun(cli_args)) => cli_args,
+        Some(HistoryAction::Show(text)) => {
+            print!("{}", text);
+            exit(0);
+        }
+        Some(HistoryAction::Failed(message)) => {
+            eprintln!("{}", message);
+            exit(1);
+        }
+    };
+
+    let cli_args = pick_command(model, &cli, cli_args);
+    let cli_args = expand_aliases(model, &cli, cli_args);
+    let cli_args = hoist_dry_run(&cli, cli_args);
+    let cli_args = prompt_missing_args(model, &cli, cli_args);
+
+    let arg_matches = cli.get_matches_from(cli_args.iter());
+
+    let globs = watch_globs(model, &arg_matches);
+
+    if !globs.is_empty() {
+        run_watched(model, &arg_matches, &globs, false);
+        exit(0);
+    }
+
+    match prepare_execution(model, &arg_matches) {
+        // Aliases that cannot be expanded explain why they cannot be run
+        Ok(Execution::Run(dispatch)) if dispatch.command.expansion().is_some() => {
+            dispatch.command.exec(Some(dispatch.args))
+        }
+        Ok(Execution::Run(dispatch)) => exit(dispatch.run(false)),
+        Ok(Execution::Explained(explanation)) => {
+            print!("{}", explanation);
+            exit(0);
+        }
+        Ok(Execution::Aborted) => {
+            eprintln!("Aborted");
+            exit(1);
+        }
+        Err(err) => err.exit(),
+    }
+}
+
+/// What is to be done for the command selected by a command line in executed mode.
+pub(crate) enum Execution<'a> {
+    /// The command is to be run.
+    Run(Dispatch<'a>),
+    /// The command is not run, as only the explanation of how it would be run was asked for.
+    Explained(String),
+    /// The command is not run, as the user did not confirm it.
+    Aborted,
+}
+
+/// A top-level command to be run with the sub-command names and args, the hooks around it, the
+/// environment variables passing the values of the root options, and its entry in the history.
+pub(crate) struct Dispatch<'a> {
+    pub(crate) command: &'a dyn Command,
+    pub(crate) args: Vec<String>,
+    pub(crate) hooks: HookRun<'a>,
+    pub(crate) environment: RootOptionEnv,
+    pub(crate) entry: Option<HistoryEntry>,
+}
+
+impl Dispatch<'_> {
+    /// Runs the command with the hooks around it, logs it in the history if it was run, and
+    /// returns its exit code. If `output_to_stderr`, the output is sent to stderr.
+    pub(crate) fn run(self, output_to_stderr: bool) -> i32 {
+        let (code, duration) = run_hooked(
+            self.command,
+            self.args,
+            &self.hooks,
+            &self.environment,
+            output_to_stderr,
+        );
+
+        if let (Some(entry), Some(duration)) = (self.entry, duration) {
+            entry.append(code, duration);
+        }
+
+        code
+    }
+}
+
+/// Prepares the execution of the command selected by the matches: unless it is only to be
+/// explained, asks for its confirmation if needed and sets up the environment variables passing
+/// the values of the root options to the executed command.
+pub(crate) fn prepare_execution<'a>(
+    model: &'a Model,
+    arg_matches: &'a ArgMatches,
+) -> Result<Execution<'a>, Error> {
+    let exported = root_option_variables(arg_matches, &model.metadata.options);
+
+    let selection = Selection::new(model, arg_matches);
+
+    if flag(arg_matches, DRY_RUN_OPTION) {
+        let mut lines = vec![("command", selection.command_line())];
+
+        if let Some(process) = selection.command.process(selection.process_args()) {
+            lines.extend(process_lines(
+                &process,
+                selection.path.map(PathBuf::as_path),
+            ));
+        }
+
+        if !selection.function.is_empty() {
+            lines.push(("function", selection.function.join(".")));
+        }
+
+        lines.extend(selection.hooks.lines());
+        lines.extend(supervision_lines(selection.command));
+
+        lines.extend(
+            exported
+                .into_iter()
+                .map(|(name, value)| ("export", format!("{}={}", name, shell_quote(&value)))),
+        );
+        lines.extend(
+            selection
+                .environment
+                .into_iter()
+                .map(|(name, value)| ("environment", format!("{}={}", name, value))),
+        );
+
+        return Ok(Execution::Explained(explanation(&lines)));
+    }
+
+    match pending_confirmation(selection.confirmation, arg_matches).map_err(unconfirmed_error)? {
+        Some(confirmation) if !confirm(confirmation) => return Ok(Execution::Aborted),
+        _ => {}
+    }
+
+    Ok(Execution::Run(Dispatch {
+        command: selection.command,
+        args: selection.process_args(),
+        hooks: selection.hooks,
+        environment: RootOptionEnv::new(arg_matches, &model.metadata.options),
+        entry: HistoryEntry::new(model, arg_matches),
+    }))
+}
+
+/// The environment variables passing the values of the root options to executed commands and their
+/// hooks. They are set for each process started, rather than in the environment of the cli itself,
+/// which other threads may be reading. The variables of options not given are removed, as they may
+/// be set for the cli itself, e.g. when it is run by a command of another cli.
+#[derive(Debug, Default)]
+pub(crate) struct RootOptionEnv(Vec<(String, Option<String>)>);
+
+impl RootOptionEnv {
+    fn new(matches: &ArgMatches, options: &[CommandOption]) -> Self {
+        RootOptionEnv(
+            options
+                .iter()
+                .map(|option| (root_option_variable(option), opt_text(matches, option)))
+                .collect(),
+        )
+    }
+
+    /// Sets the variables for the process.
+    pub(crate) fn apply(&self, process: &mut process::Command) {
+        for (name, value) in &self.0 {
+            match value {
+                Some(value) => process.env(name, value),
+                None => process.env_remove(name),
+            };
+        }
+    }
+}
+
+/// The environment variables the values of the root options are exported as for the executed
+/// command, named by the prefix [ROOT_OPTION_ENV_PREFIX] and the upper-case name of the option.
+fn root_option_variables(matches: &ArgMatches, options: &[CommandOption]) -> Vec<(String, String)> {
+    options
+        .iter()
+        .filter_map(|option| {
+            opt_text(matches, option).map(|value| (root_option_variable(option), value))
+        })
+        .collect()
+}
+
+/// The name of the environment variable a root option is exported as.
+fn root_option_variable(option: &CommandOption) -> String {
+    format!(
+        "{}{}",
+        ROOT_OPTION_ENV_PREFIX,
+        option.var_name().to_uppercase()
+    )
+}
+
+/// The environment variables from which the values of the given options and arguments were taken,
+/// as they were not given on the command line, along with the values. Secret values are hidden.
+fn env_values(
+    matches: &ArgMatches,
+    options: &[CommandOption],
+    args: &[CommandArg],
+) -> Vec<(String, String)> {
+    let options = options
+        .iter()
+        .map(|option| (option.name.as_str(), option.env.as_ref()));
+    let args = args.iter().map(|arg| (arg.name.as_str(), arg.env.as_ref()));
+
+    options
+        .chain(args)
+        .filter_map(|(name, env)| Some((name, env?)))
+        .filter(|(name, _)| matches.value_source(name) == Some(ValueSource::EnvVariable))
+        .map(|(name, env)| {
+            let value = if env.secret {
+                SECRET_VALUE.to_owned()
+            } else {
+                shell_quote(&raw_values(matches, name).join(","))
+            };
+
+            (env.name.clone(), value)
+        })
+        .collect()
+}
+
+/// Describes the process running a command: the program, the interpreter of the script at the
+/// given path, if it names one, the directory it is run in and its args.
+fn process_lines(process: &process::Command, script: Option<&Path>) -> Vec<(&'static str, String)> {
+    let mut lines = vec![(
+        "program",
+        process.get_program().to_string_lossy().to_string(),
+    )];
+
+    // Only the script itself is run by its interpreter, not e.g. a group file
+    if let Some(interpreter) = script
+        .filter(|script| script.as_os_str() == process.get_program())
+        .and_then(interpreter)
+    {
+        lines.push(("interpreter", interpreter));
+    }
+
+    if let Some(dir) = process.get_current_dir() {
+        lines.push(("directory", dir.to_string_lossy().to_string()));
+    }
+
+    lines.push((
+        "argv",
+        process
+            .get_args()
+            .map(|arg| shell_quote(&arg.to_string_lossy()))
+            .collect::<Vec<String>>()
+            .join(" "),
+    ));
+
+    lines
+}
+
+/// The interpreter named by the `#!` line of a script, if it starts with one.
+fn interpreter(script: &Path) -> Option<String> {
+    let mut line = String::new();
+
+    BufReader::new(File::open(script).ok()?)
+        .read_line(&mut line)
+        .ok()?;
+
+    line.strip_prefix("#!")
+        .map(|interpreter| interpreter.trim().to_owned())
+}
+
+/// Renders the labelled lines of an explanation of how a command would be run, with the values
+/// aligned.
+fn explanation(lines: &[(&str, String)]) -> String {
+    let width = lines
+        .iter()
+        .map(|(label, _)| label.len() + 1)
+        .max()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|(label, value)| format!("{:width$} {}\n", format!("{}:", label), value))
+        .collect()
+}
+
+/// Collects the args the selected command is executed with: either the options and arguments as
+/// they would be written on the command line, or the values of all of them - of its own options and
+/// arguments in the order they are defined, followed by the options inherited from the commands
+/// leading to it.
+fn exec_args(
+    matches: &ArgMatches,
+    command: &dyn Command,
+    inherited: &[&CommandOption],
+) -> Vec<String> {
+    if command.forwards_command_line() {
+        let options = command
+            .options()
+            .iter()
+            .flat_map(|option| option_words(matches, option));
+
+        let args = command
+            .args()
+            .iter()
+            .flat_map(|arg| raw_values(matches, &arg.name));
+
+        options.chain(args).collect()
+    } else {
+        let options = command
+            .options()
+            .iter()
+            .flat_map(|option| option_values(matches, option));
+        let args = command
+            .args()
+            .iter()
+            .flat_map(|arg| raw_values(matches, &arg.name));
+        let inherited = inherited
+            .iter()
+            .flat_map(|option| option_values(matches, option));
+
+        options.chain(args).chain(inherited).collect()
+    }
+}
+
+/// The values an option is passed as in executed mode. An option given many times is passed as the
+/// number of values followed by the values, so that the script can tell where they end.
+fn option_values(matches: &ArgMatches, option: &CommandOption) -> Vec<String> {
+    match (option.has_param, option.kind) {
+        (true, OptionKind::Many) => {
+            let values = raw_values(matches, &option.name);
+            std::iter::once(values.len().to_string())
+                .chain(values)
+                .collect()
+        }
+        (false, OptionKind::Count | OptionKind::Negatable) => {
+            opt_text(matches, option).into_iter().collect()
+        }
+        _ => raw_values(matches, &option.name),
+    }
+}
+
+/// The words giving the value of an option on a command line, e.g. `--tag a --tag b`.
+pub(crate) fn option_words(matches: &ArgMatches, option: &CommandOption) -> Vec<String> {
+    let long = format!("--{}", option.name);
+
+    match (option.has_param, option.kind) {
+        (true, _) => raw_values(matches, &option.name)
+            .into_iter()
+            .flat_map(|value| [long.clone(), value])
+            .collect(),
+        (false, OptionKind::Count) => vec![long; matches.get_count(&option.name).into()],
+        (false, OptionKind::Negatable) => match negatable_value(matches, option) {
+            "true" => vec![long],
+            "false" => vec![format!("--{}", option.negation())],
+            _ => vec![],
+        },
+        (false, _) if matches.get_flag(&option.name) => vec![long],
+        (false, _) => vec![],
+    }
+}
+
+pub(crate) fn raw_values(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches
+        .get_raw(name)
+        .into_iter()
+        .flatten()
+        .map(|value| value.to_str().unwrap().to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use crate::model::{
+        AliasCommand, ArgType, BinaryCommand, CliMetadata, CommandArg, CommandOption,
+        EmbeddedCommand, InlineCommand, ScriptCommand, WithMeta,
+    };
+    use crate::transform::ToCliCommand;
+
+    use super::*;
+
+    #[test]
+    fn test_build_cli_args() {
+        let bar: EmbeddedCommand = EmbeddedCommand::new(
+            "bar".to_owned(),
+            Option::<String>::None,
+            vec![],
+            vec![CommandArg::new(
+                "arg1".to_owned(),
+                false,
+                false,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+        );
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(bar)],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+        let command = model.to_cli();
+
+        // capture the ouput produced by embedded_commands
+        let out = build_embedded_script(
+            &model,
+            command,
+            vec![
+                "blah".to_owned(),
+                "foo".to_owned(),
+                "bar".to_owned(),
+                "arg1Val".to_owned(),
+            ],
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=(\"arg1\" 'arg1Val')\ntypeset -A cli_opts\ncli_opts=()\nsource '/tmp/foo.sh'\nbar\n");
+    }
+
+    #[test]
+    fn build_embedded_script_runs_function_of_nested_sub_command() {
+        let create = EmbeddedCommand::new(
+            "create",
+            Option::<String>::None,
+            vec![],
+            vec![CommandArg::new(
+                "name",
+                false,
+                false,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+        );
+
+        let cluster = EmbeddedCommand::new(
+            "cluster",
+            Option::<String>::None,
+            vec![CommandOption::new(
+                "region",
+                None,
+                true,
+                Option::<String>::None,
+            )],
+            vec![],
+        )
+        .with_sub_commands(vec![Box::new(create)]);
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(cluster)],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "foo", "cluster", "--region", "eu", "create", "c1"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=(\"name\" 'c1')\ntypeset -A cli_opts\ncli_opts=(\"region\" 'eu')\nsource '/tmp/foo.sh'\ncluster.create\n");
+    }
+
+    #[test]
+    fn selection_resolves_the_path_to_a_nested_sub_command() {
+        let create = EmbeddedCommand::new(
+            "create",
+            Option::<String>::None,
+            vec![],
+            vec![CommandArg::new(
+                "name",
+                false,
+                false,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+        )
+        .with_confirmation(Some(Confirmation::new(None)));
+
+        let cluster = EmbeddedCommand::new(
+            "cluster",
+            Option::<String>::None,
+            vec![CommandOption::new(
+                "region",
+                None,
+                true,
+                Option::<String>::None,
+            )],
+            vec![],
+        )
+        .with_sub_commands(vec![Box::new(create)]);
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(cluster)],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+        let matches = model
+            .to_cli()
+            .try_get_matches_from(to_args(&[
+                "mycli", "foo", "cluster", "--region", "eu", "create", "c1",
+            ]))
+            .unwrap();
+
+        let selection = Selection::new(&model, &matches);
+
+        assert_eq!(selection.command.name(), "foo");
+        assert_eq!(selection.selected.name(), "create");
+        assert_eq!(selection.command_line(), "foo cluster create");
+        assert_eq!(selection.path, Some(&PathBuf::from("/tmp/foo.sh")));
+        assert_eq!(selection.function, vec!["cluster", "create"]);
+        assert!(selection.confirmation.is_some());
+        assert_eq!(selection.args, vec![("name", "c1".to_owned())]);
+        assert_eq!(
+            selection.process_args(),
+            to_args(&["cluster", "create", "c1", "eu"])
+        );
+    }
+
+    #[test]
+    fn build_embedded_script_skips_confirmation_if_yes() {
+        let prod = EmbeddedCommand::new("prod", Option::<String>::None, vec![], vec![])
+            .with_confirmation(Some(Confirmation::new(None)));
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(prod)],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "foo", "prod", "--yes"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=()\ntypeset -A cli_opts\ncli_opts=()\nsource '/tmp/foo.sh'\nprod\n");
+    }
+
+    #[test]
+    fn build_embedded_script_explains_command_in_dry_run() {
+        let prod = EmbeddedCommand::new(
+            "prod",
+            Option::<String>::None,
+            vec![],
+            vec![CommandArg::new(
+                "app",
+                false,
+                false,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+        )
+        .with_confirmation(Some(Confirmation::new(None)));
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(prod)],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "foo", "prod", "--dry-run", "web"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "printf '%s' 'command:     foo prod\nscript:      /tmp/foo.sh\ninterpreter: the calling shell\nfunction:    prod\nvariable:    cli_args[app]='\\''web'\\''\n'");
+    }
+
+    #[test]
+    fn process_lines_describe_script_process() {
+        let test_dir = tempfile::tempdir().unwrap();
+        let script = test_dir.path().join("foo.sh");
+        std::fs::write(&script, "#!/bin/bash\necho foo\n").unwrap();
+
+        let mut process = process::Command::new(&script);
+        process.args(["sub", "it's"]);
+
+        assert_eq!(
+            process_lines(&process, Some(&script)),
+            vec![
+                ("program", script.to_string_lossy().to_string()),
+                ("interpreter", "/bin/bash".to_owned()),
+                ("argv", r"'sub' 'it'\''s'".to_owned()),
+            ]
+        );
+
+        // Only the script itself is run by its interpreter
+        assert_eq!(
+            process_lines(&process::Command::new("make"), Some(&script)),
+            vec![("program", "make".to_owned()), ("argv", String::new())]
+        );
+    }
+
+    #[test]
+    fn write_confirmed_runs_code_if_answered_yes() {
+        let mut buffer = Vec::new();
+
+        write_confirmed(
+            &mut buffer,
+            &Confirmation::new(Some("Deploy to 'prod'?".to_owned())),
+            b"deploy\n",
+        );
+
+        let out_str = String::from_utf8(buffer).expect("Failed to convert to string");
+        assert_eq!(out_str, "printf '%s [y/N] ' 'Deploy to '\\''prod'\\''?' >&2\nread -r cli_confirmed\ncase \"$cli_confirmed\" in\n[yY]*)\ndeploy\n;;\n*)\necho \"Aborted\" >&2\nfalse\n;;\nesac\n");
+    }
+
+    #[test]
+    fn build_embedded_script_sets_values_of_option_kinds() {
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![
+                CommandOption::new("verbose", Some('v'), false, Option::<String>::None)
+                    .with_kind(OptionKind::Count),
+                CommandOption::new("tag", None, true, Option::<String>::None)
+                    .with_kind(OptionKind::Many),
+                CommandOption::new("color", None, false, Option::<String>::None)
+                    .with_kind(OptionKind::Negatable),
+            ],
+            vec![],
+            vec![],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "foo", "-vv", "--tag", "a", "--tag", "b"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=()\ntypeset -A cli_opts\ncli_opts=(\"verbose\" 2 \"tag\" 'a,b' \"color\" '')\ncli_opts_tag=('a' 'b')\nsource '/tmp/foo.sh'\n");
+    }
+
+    #[test]
+    fn build_embedded_script_sets_dashed_options_with_underscores() {
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![
+                CommandOption::new("skip-tests", None, false, Option::<String>::None),
+                CommandOption::new("extra-tag", None, true, Option::<String>::None)
+                    .with_kind(OptionKind::Many),
+            ],
+            vec![],
+            vec![],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "foo", "--skip-tests", "--extra-tag", "a"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=()\ntypeset -A cli_opts\ncli_opts=(\"skip_tests\" true \"extra_tag\" 'a')\ncli_opts_extra_tag=('a')\nsource '/tmp/foo.sh'\n");
+        assert_eq!(
+            root_option_variable(&CommandOption::new(
+                "dry-run",
+                None,
+                false,
+                Option::<String>::None
+            )),
+            "CLI_OPT_DRY_RUN"
+        );
+    }
+
+    #[test]
+    fn build_embedded_script_quotes_values() {
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![
+                CommandOption::new("name", None, true, Option::<String>::None),
+                CommandOption::new("tag", None, true, Option::<String>::None)
+                    .with_kind(OptionKind::Many),
+            ],
+            vec![CommandArg::new(
+                "message",
+                false,
+                false,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+            vec![],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]);
+
+        let values = ["$(echo injected)", "say \"hi\"", "it's $HOME"];
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&[
+                "mycli", "foo", "--name", values[0], "--tag", values[1], "--tag", values[2],
+                values[2],
+            ]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=(\"message\" 'it'\\''s $HOME')\ntypeset -A cli_opts\ncli_opts=(\"name\" '$(echo injected)' \"tag\" 'say \"hi\",it'\\''s $HOME')\ncli_opts_tag=('say \"hi\"' 'it'\\''s $HOME')\nsource '/tmp/foo.sh'\n");
+
+        // The shell sees the values as given
+        let assignments = out_str.replace("source '/tmp/foo.sh'\n", "");
+        let output = process::Command::new("bash")
+            .arg("-c")
+            .arg(format!(
+                "{}printf '%s\\n' \"${{cli_opts[name]}}\" \"${{cli_opts_tag[@]}}\" \"${{cli_args[message]}}\"",
+                assignments
+            ))
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            format!(
+                "{}\n{}\n{}\n{}\n",
+                values[0], values[1], values[2], values[2]
+            )
+        );
+    }
+
+    #[test]
+    fn exec_args_passes_values_of_option_kinds() {
+        let options = vec![
+            CommandOption::new("verbose", Some('v'), false, Option::<String>::None)
+                .with_kind(OptionKind::Count),
+            CommandOption::new("tag", None, true, Option::<String>::None)
+                .with_kind(OptionKind::Many),
+            CommandOption::new("color", None, false, Option::<String>::None)
+                .with_kind(OptionKind::Negatable),
+        ];
+
+        let script = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            options.clone(),
+            vec![],
+            vec![],
+        );
+
+        let binary = BinaryCommand::new(
+            "k",
+            "kubectl",
+            vec![],
+            Option::<String>::None,
+            options,
+            vec![],
+        );
+
+        let args = to_args(&["foo", "-vv", "--tag", "a", "--tag", "b c", "--no-color"]);
+
+        let matches = script.to_cli().try_get_matches_from(&args).unwrap();
+        assert_eq!(
+            exec_args(&matches, &script, &[]),
+            to_args(&["2", "2", "a", "b c", "false"])
+        );
+
+        let matches = binary.to_cli().try_get_matches_from(&args).unwrap();
+        assert_eq!(
+            exec_args(&matches, &binary, &[]),
+            to_args(&[
+                "--verbose",
+                "--verbose",
+                "--tag",
+                "a",
+                "--tag",
+                "b c",
+                "--no-color"
+            ])
+        );
+    }
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn expand_aliases_expands_aliases_of_aliases() {
+        let mut model = Model::new(vec![]);
+        model.add_command(AliasCommand::new(
+            "dp",
+            to_args(&["deployProd", "--force"]),
+            Option::<String>::None,
+        ));
+        model.add_command(AliasCommand::new(
+            "deployProd",
+            to_args(&["deploy", "--env", "prod"]),
+            Option::<String>::None,
+        ));
+
+        assert_eq!(
+            expand_aliases(&model, &model.to_cli(), to_args(&["mycli", "dp", "app"])),
+            to_args(&["mycli", "deploy", "--env", "prod", "--force", "app"])
+        );
+
+        // Only the command itself is expanded
+        assert_eq!(
+            expand_aliases(&model, &model.to_cli(), to_args(&["mycli", "deploy", "dp"])),
+            to_args(&["mycli", "deploy", "dp"])
+        );
+    }
+
+    #[test]
+    fn expand_aliases_skips_root_options() {
+        let mut model = Model::new(vec![]).with_metadata(CliMetadata {
+            options: vec![
+                CommandOption::new("verbose", Some('v'), false, Option::<String>::None),
+                CommandOption::new("env", Some('e'), true, Option::<String>::None),
+            ],
+            ..CliMetadata::default()
+        });
+        model.add_command(AliasCommand::new(
+            "greet",
+            to_args(&["hello", "--loud"]),
+            Option::<String>::None,
+        ));
+        let cli = model.to_cli();
+
+        assert_eq!(
+            expand_aliases(&model, &cli, to_args(&["mycli", "-v", "greet", "hi"])),
+            to_args(&["mycli", "-v", "hello", "--loud", "hi"])
+        );
+
+        // The values of root options are not taken for the command
+        assert_eq!(
+            expand_aliases(&model, &cli, to_args(&["mycli", "--env", "greet", "greet"])),
+            to_args(&["mycli", "--env", "greet", "hello", "--loud"])
+        );
+        assert_eq!(
+            expand_aliases(
+                &model,
+                &cli,
+                to_args(&["mycli", "-ve", "prod", "--env=qa", "greet"])
+            ),
+            to_args(&["mycli", "-ve", "prod", "--env=qa", "hello", "--loud"])
+        );
+        assert_eq!(
+            expand_aliases(&model, &cli, to_args(&["mycli", "-eprod", "greet"])),
+            to_args(&["mycli", "-eprod", "hello", "--loud"])
+        );
+    }
+
+    #[test]
+    fn dry_run_after_trailing_var_arg_explains_the_command() {
+        let listing = ScriptCommand::new(
+            "listing".to_owned(),
+            None,
+            PathBuf::from("/tmp/listing.sh"),
+            vec![],
+            vec![CommandArg::new(
+                "args",
+                true,
+                true,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+            vec![],
+        );
+        let model = Model::new(vec![Box::new(listing)]);
+        let cli = model.to_cli();
+
+        assert_eq!(
+            hoist_dry_run(
+                &cli,
+                to_args(&["mycli", "listing", "/tmp", "--explain", "x"])
+            ),
+            to_args(&["mycli", "--dry-run", "listing", "/tmp", "x"])
+        );
+        // After `--`, the flag is passed on to the command
+        assert_eq!(
+            hoist_dry_run(&cli, to_args(&["mycli", "listing", "--", "--dry-run"])),
+            to_args(&["mycli", "listing", "--", "--dry-run"])
+        );
+
+        let out = build_embedded_script(
+            &model,
+            cli,
+            to_args(&["mycli", "listing", "/tmp", "--dry-run"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "printf '%s' 'command:     listing\nscript:      /tmp/listing.sh\ninterpreter: the calling shell\nvariable:    cli_args[args]='\\''/tmp'\\''\n'");
+    }
+
+    #[test]
+    fn expand_aliases_stops_at_cycle() {
+        let mut model = Model::new(vec![]);
+        model.add_command(AliasCommand::new(
+            "loop",
+            to_args(&["loop", "again"]),
+            Option::<String>::None,
+        ));
+
+        assert_eq!(
+            expand_aliases(&model, &model.to_cli(), to_args(&["mycli", "loop"])),
+            to_args(&["mycli", "loop", "again"])
+        );
+    }
+
+    #[test]
+    fn build_embedded_script_runs_inline_code() {
+        let mut model = Model::new(vec![]);
+        model.add_command(InlineCommand::new(
+            "greet",
+            "echo \"Hello $1\"",
+            Option::<String>::None,
+            vec![],
+            vec![CommandArg::new(
+                "name",
+                false,
+                false,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+        ));
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "greet", "it's me"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=(\"name\" 'it'\\''s me')\ntypeset -A cli_opts\ncli_opts=()\ncli_inline() {\necho \"Hello $1\"\n}\ncli_inline 'it'\\''s me'\neval \"unset -f cli_inline; (exit $?)\"\n");
+    }
+
+    #[test]
+    fn build_embedded_script_forwards_command_line_to_binary() {
+        let mut model = Model::new(vec![]);
+        model.add_command(BinaryCommand::new(
+            "k",
+            "kubectl",
+            vec![],
+            Option::<String>::None,
+            vec![
+                CommandOption::new("namespace", Some('n'), true, Option::<String>::None),
+                CommandOption::new("watch", Some('w'), false, Option::<String>::None),
+            ],
+            vec![CommandArg::new(
+                "resource",
+                false,
+                false,
+                ArgType::Unknown,
+                Option::<String>::None,
+            )],
+        ));
+
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "k", "pods", "-n", "kube-system"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert!(out_str.ends_with("command 'kubectl' '--namespace' 'kube-system' 'pods'\n"));
+    }
+
+    #[test]
+    fn build_embedded_script_sets_root_options() {
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![],
+        );
+
+        let mut model = Model::new(vec![Box::new(foo)]);
+        model.metadata.options = vec![
+            CommandOption::new("verbose", Some('v'), false, Option::<String>::None),
+            CommandOption::new("env", None, true, Option::<String>::None),
+        ];
+
+        // Root options can be given after the command
+        let out = build_embedded_script(
+            &model,
+            model.to_cli(),
+            to_args(&["mycli", "--env", "prod", "foo", "-v"]),
+        );
+
+        let out_str = String::from_utf8(out).expect("Failed to convert to string");
+        assert!(out_str.contains("cli_opts=(\"verbose\" true \"env\" 'prod')\n"));
+    }
+
+    #[test]
+    fn prepare_execution_passes_root_options_to_the_process() {
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            None,
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![],
+        );
+
+        let mut model = Model::new(vec![Box::new(foo)]);
+        model.metadata.options = vec![
+            CommandOption::new("env", None, true, Option::<String>::None),
+            CommandOption::new("region", None, true, Option::<String>::None),
+        ];
+
+        let matches = model
+            .to_cli()
+            .get_matches_from(["mycli", "--env", "prod", "foo"]);
+
+        let Ok(Execution::Run(dispatch)) = prepare_execution(&model, &matches) else {
+            panic!("The command is not run");
+        };
+
+        let mut process = process::Command::new("sh");
+        process
+            .args(["-c", "echo \"$CLI_OPT_ENV:${CLI_OPT_REGION-unset}\""])
+            .env("CLI_OPT_REGION", "eu");
+        dispatch.environment.apply(&mut process);
+
+        let output = process.output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "prod:unset\n");
+
+        // The environment of the cli itself is left as it is
+        assert!(std::env::var_os("CLI_OPT_ENV").is_none());
+    }
+}
@@ -0,0 +1,522 @@
+//! The history of the commands run, which is kept in a log file for auditing, and the global
+//! options listing the history and re-running a command from it.
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use log::debug;
+use serde_json::{json, Map, Value};
+
+use crate::exec::{option_words, raw_values, SECRET_VALUE};
+use crate::model::{
+    AuditLog, Command, CommandArg, CommandOption, EnvVar, HasSubCommands, Model, OptionKind,
+};
+use crate::utils::shell_quote;
+
+/// The name of the global option listing the history, optionally only the entries containing the
+/// given text.
+pub const HISTORY_OPTION: &str = "history";
+
+/// The name of the global option running the command of an entry of the history again.
+pub const RERUN_OPTION: &str = "rerun";
+
+/// The suffix of the name of the log file in the home directory, after `.` and the name of the cli.
+const LOG_FILE_SUFFIX: &str = "_audit.log";
+
+/// The log file of the cli with the given name, which holds one JSON record per line; `None` if
+/// the commands are not logged, or there is no home directory to find it in.
+fn log_file(audit_log: &AuditLog, name: &str) -> Option<PathBuf> {
+    let path = match audit_log {
+        AuditLog::Off => return None,
+        AuditLog::File(path) if path.is_absolute() => return Some(path.clone()),
+        AuditLog::File(path) => path.clone(),
+        AuditLog::InHome => PathBuf::from(format!(".{}{}", name, LOG_FILE_SUFFIX)),
+    };
+
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(path))
+}
+
+/// The record of a command being run, which is logged once it has finished.
+pub(crate) struct HistoryEntry {
+    file: PathBuf,
+    record: Map<String, Value>,
+}
+
+impl HistoryEntry {
+    /// The record of running the command selected by the matches now. Values of secret options and
+    /// arguments, and values taken from environment variables, are replaced by a placeholder.
+    /// `None` if the commands of the cli are not logged, the cli has no name, or there is no home directory to keep the log file in.
+    pub(crate) fn new(model: &Model, arg_matches: &ArgMatches) -> Option<Self> {
+        let name = model.metadata.name.as_ref()?;
+        let file = log_file(&model.metadata.audit_log, name)?;
+
+        let (path, args) = command_line(model, arg_matches);
+
+        let cwd = std::env::current_dir()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let record = json!({
+            "timestamp": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            "cli": name,
+            "command": path.join(" "),
+            "args": args,
+            "cwd": cwd,
+        });
+
+        match record {
+            Value::Object(record) => Some(HistoryEntry { file, record }),
+            _ => None,
+        }
+    }
+
+    /// Appends the record of the command, which finished with the given exit code after running
+    /// for the given time, to the log file.
+    pub(crate) fn append(mut self, code: i32, duration: Duration) {
+        self.record.insert("exit".to_owned(), json!(code));
+        self.record
+            .insert("duration".to_owned(), json!(duration.as_secs_f64()));
+
+        let result = open_log_file(&self.file)
+            .and_then(|mut file| writeln!(file, "{}", Value::Object(self.record)));
+
+        if let Err(e) = result {
+            debug!("Unable to write to {}: {}", self.file.to_string_lossy(), e);
+        }
+    }
+
+    /// Shell code appending the record to the log file, for the calling shell to evaluate once the
+    /// command has finished, with its exit status in `cli_status` and the value of `SECONDS` when
+    /// it started in `cli_started`.
+    pub(crate) fn append_script(&self) -> Vec<u8> {
+        // Created here, so that only the user can read it
+        if let Err(e) = open_log_file(&self.file) {
+            debug!("Unable to create {}: {}", self.file.to_string_lossy(), e);
+        }
+
+        // The record without its closing brace, to which the status and duration are added
+        let record = Value::Object(self.record.clone()).to_string();
+        let prefix = record.strip_suffix('}').unwrap_or(&record);
+
+        format!(
+            "{{ printf '%s,\"exit\":%s,\"duration\":%s}}\\n' {} \"$cli_status\" \"$((SECONDS - cli_started))\" >> {}; }} 2>/dev/null\n",
+            shell_quote(prefix),
+            shell_quote(&self.file.to_string_lossy())
+        )
+        .into_bytes()
+    }
+}
+
+fn open_log_file(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+}
+
+/// The path of the command selected by the matches, and the words of the command line selecting
+/// it again: the root options and, for each command along the path, its name followed by its
+/// options and arguments. Values taken from environment variables are included, redacted, but
+/// not those prompted for.
+fn command_line(model: &Model, arg_matches: &ArgMatches) -> (Vec<String>, Vec<String>) {
+    let mut path = vec![];
+    let mut words = given_words(arg_matches, &model.metadata.options, &[]);
+
+    let mut current = arg_matches;
+    let mut command: Option<&dyn Command> = None;
+
+    while let Some((name, matches)) = current.subcommand() {
+        let Some(sub) =
+            command.map_or(model.get_command(name), |command| command.get_command(name))
+        else {
+            break;
+        };
+
+        path.push(name.to_owned());
+        words.push(name.to_owned());
+        words.extend(given_words(matches, sub.options(), sub.args()));
+
+        current = matches;
+        command = Some(sub);
+    }
+
+    (path, words)
+}
+
+/// The words giving the options and arguments that were given on the command line or taken from
+/// environment variables, with the values of secret ones, and of those taken from environment
+/// variables, replaced by a placeholder.
+fn given_words(
+    matches: &ArgMatches,
+    options: &[CommandOption],
+    args: &[CommandArg],
+) -> Vec<String> {
+    let is_given = |name: &str| {
+        matches!(
+            matches.value_source(name),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        )
+    };
+    let is_redacted = |name: &str, env: Option<&EnvVar>| {
+        env.is_some_and(|env| env.secret)
+            || matches.value_source(name) == Some(ValueSource::EnvVariable)
+    };
+
+    let options = options
+        .iter()
+        .filter(|option| {
+            is_given(&option.name)
+                || (option.kind == OptionKind::Negatable && is_given(&option.negation()))
+        })
+        .flat_map(|option| {
+            let redacted = is_redacted(&option.name, option.env.as_ref());
+
+            option_words(matches, option).into_iter().map(move |word| {
+                if redacted && !word.starts_with("--") {
+                    SECRET_VALUE.to_owned()
+                } else {
+                    word
+                }
+            })
+        });
+
+    let args = args
+        .iter()
+        .filter(|arg| is_given(&arg.name))
+        .flat_map(|arg| {
+            let redacted = is_redacted(&arg.name, arg.env.as_ref());
+
+            raw_values(matches, &arg.name)
+                .into_iter()
+                .map(move |value| {
+                    if redacted {
+                        SECRET_VALUE.to_owned()
+                    } else {
+                        value
+                    }
+                })
+        });
+
+    options.chain(args).collect()
+}
+
+/// What is to be done for a command line asking for the history.
+pub enum HistoryAction {
+    /// The text listing the entries of the history is to be shown.
+    Show(String),
+    /// The command line of an entry is to be run again.
+    Rerun(Vec<String>),
+    /// The history cannot be shown, or the entry cannot be run again, for the given reason.
+    Failed(String),
+}
+
+/// What is to be done if the command line asks for the history: it includes `--history` or
+/// `--rerun`, but selects no command. `None` if the command line is to be run as usual, the
+/// commands of the cli are not logged, or the cli defines options of those names itself. The
+/// command line of an entry that is run again is shown on stderr.
+pub fn history_action(
+    model: &Model,
+    cli: &clap::Command,
+    cli_args: &[String],
+) -> Option<HistoryAction> {
+    if !model.metadata.audit_log.is_on() {
+        return None;
+    }
+
+    let is_defined = |name: &str| {
+        model
+            .metadata
+            .options
+            .iter()
+            .any(|option| option.name == name)
+    };
+
+    let matches = cli
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(cli_args.iter())
+        .ok()
+        .filter(|matches| matches.subcommand().is_none())?;
+
+    let is_given = |name: &str| {
+        !is_defined(name) && matches.value_source(name) == Some(ValueSource::CommandLine)
+    };
+
+    let rerun = is_given(RERUN_OPTION)
+        .then(|| matches.get_one::<usize>(RERUN_OPTION).copied())
+        .flatten();
+
+    if !is_given(HISTORY_OPTION) && rerun.is_none() {
+        return None;
+    }
+
+    let name = cli.get_name();
+
+    let Some(file) = log_file(&model.metadata.audit_log, name) else {
+        return Some(HistoryAction::Failed(
+            "There is no home directory to keep the history in".to_owned(),
+        ));
+    };
+
+    let entries = match read_entries(&file) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Some(HistoryAction::Failed(format!(
+                "Unable to read the history in {}: {}",
+                file.to_string_lossy(),
+                e
+            )))
+        }
+    };
+
+    Some(match rerun {
+        Some(index) => rerun_entry(name, &entries, index),
+        None => HistoryAction::Show(list_entries(
+            &entries,
+            matches
+                .get_one::<String>(HISTORY_OPTION)
+                .map(String::as_str),
+        )),
+    })
+}
+
+/// The records in the log file, numbered from one by their lines. There is no history before the
+/// first command is run.
+fn read_entries(file: &Path) -> std::io::Result<Vec<(usize, Value)>> {
+    let reader = match File::open(file) {
+        Ok(file) => BufReader::new(file),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut entries = vec![];
+
+    for (index, line) in reader.lines().enumerate() {
+        // Lines that are not records, e.g. of a command interrupted while being logged, are skipped
+        if let Ok(record) = serde_json::from_str::<Value>(&line?) {
+            entries.push((index + 1, record));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// The words of the command line of a record, after the name of the cli.
+fn record_args(record: &Value) -> Vec<String> {
+    record["args"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|arg| arg.as_str().map(str::to_owned))
+        .collect()
+}
+
+/// Lists the entries, oldest first, with their number, when they were run, the exit code, how long
+/// they ran, the command line and the directory they were run in. If a filter is given, only the
+/// entries whose command line or directory contains it, ignoring case, are listed.
+fn list_entries(entries: &[(usize, Value)], filter: Option<&str>) -> String {
+    let filter = filter.map(str::to_lowercase);
+
+    entries
+        .iter()
+        .map(|(index, record)| {
+            let text = |field: &str| record[field].as_str().unwrap_or_default().to_owned();
+
+            (
+                index,
+                text("timestamp"),
+                record["exit"]
+                    .as_i64()
+                    .map(|code| code.to_string())
+                    .unwrap_or("?".to_owned()),
+                record["duration"]
+                    .as_f64()
+                    .map(|duration| format!("{:.1}s", duration))
+                    .unwrap_or("?".to_owned()),
+                shell_words::join(record_args(record)),
+                text("cwd"),
+            )
+        })
+        .filter(|(_, _, _, _, command_line, cwd)| {
+            filter.as_ref().is_none_or(|filter| {
+                command_line.to_lowercase().contains(filter) || cwd.to_lowercase().contains(filter)
+            })
+        })
+        .map(|(index, timestamp, code, duration, command_line, cwd)| {
+            format!(
+                "{:>5}  {}  {:>3}  {:>7}  {}  ({})\n",
+                index, timestamp, code, duration, command_line, cwd
+            )
+        })
+        .collect()
+}
+
+/// The command line of the entry with the given number, prefixed by the name of the cli.
+fn rerun_entry(name: &str, entries: &[(usize, Value)], index: usize) -> HistoryAction {
+    let Some((_, record)) = entries.iter().find(|(i, _)| *i == index) else {
+        return HistoryAction::Failed(format!("There is no entry {} in the history", index));
+    };
+
+    let args = record_args(record);
+
+    if args.iter().any(|arg| arg == SECRET_VALUE) {
+        return HistoryAction::Failed(format!(
+            "Entry {} cannot be run again, as its secret values are not kept in the history",
+            index
+        ));
+    }
+
+    eprintln!("{} {}", name, shell_words::join(&args));
+
+    HistoryAction::Rerun(std::iter::once(name.to_owned()).chain(args).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::model::test::NO_DESCRIPTION;
+    use crate::model::{ArgType, EmbeddedCommand, EnvVar, ScriptCommand};
+    use crate::transform::ToCliCommand;
+
+    use super::*;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn command_line_redacts_secrets_and_values_from_the_environment() {
+        let login = EmbeddedCommand::new(
+            "login",
+            NO_DESCRIPTION,
+            vec![
+                CommandOption::new("token", None, true, NO_DESCRIPTION)
+                    .with_env(Some(EnvVar::new("TOKEN", true))),
+                CommandOption::new("region", None, true, NO_DESCRIPTION)
+                    .with_env(Some(EnvVar::new("REGION_FOR_HISTORY_TEST", false))),
+            ],
+            vec![CommandArg::new(
+                "user",
+                false,
+                false,
+                ArgType::Unknown,
+                NO_DESCRIPTION,
+            )],
+        );
+
+        let mut model = Model::new(vec![Box::new(ScriptCommand::new(
+            "cloud".to_owned(),
+            None,
+            PathBuf::from("/tmp/cloud.sh"),
+            vec![CommandOption::new(
+                "verbose",
+                Some('v'),
+                false,
+                NO_DESCRIPTION,
+            )],
+            vec![],
+            vec![Box::new(login)],
+        ))]);
+        model.metadata.name = Some("mycli".to_owned());
+
+        std::env::set_var("REGION_FOR_HISTORY_TEST", "eu");
+
+        let matches = model
+            .to_cli()
+            .try_get_matches_from(to_args(&[
+                "mycli", "cloud", "-v", "login", "--token", "s3cr3t", "jane",
+            ]))
+            .unwrap();
+
+        assert_eq!(
+            command_line(&model, &matches),
+            (
+                to_args(&["cloud", "login"]),
+                to_args(&[
+                    "cloud",
+                    "--verbose",
+                    "login",
+                    "--token",
+                    "<secret>",
+                    "--region",
+                    "<secret>",
+                    "jane"
+                ])
+            )
+        );
+    }
+
+    #[test]
+    fn history_entry_is_made_unless_the_audit_log_is_off() {
+        let mut model = Model::new(vec![Box::new(ScriptCommand::new(
+            "cloud".to_owned(),
+            None,
+            PathBuf::from("/tmp/cloud.sh"),
+            vec![],
+            vec![],
+            vec![],
+        ))]);
+        model.metadata.name = Some("mycli".to_owned());
+
+        let matches = model
+            .to_cli()
+            .try_get_matches_from(to_args(&["mycli", "cloud"]))
+            .unwrap();
+
+        model.metadata.audit_log = AuditLog::File(PathBuf::from("/var/log/mycli.log"));
+
+        assert_eq!(
+            HistoryEntry::new(&model, &matches).map(|entry| entry.file),
+            Some(PathBuf::from("/var/log/mycli.log"))
+        );
+
+        model.metadata.audit_log = AuditLog::Off;
+
+        assert!(HistoryEntry::new(&model, &matches).is_none());
+        assert!(
+            history_action(&model, &model.to_cli(), &to_args(&["mycli", "--history"])).is_none()
+        );
+    }
+
+    #[test]
+    fn list_entries_filters_and_rerun_refuses_secrets() {
+        let entries = vec![
+            (
+                1,
+                json!({"timestamp": "2026-10-18T09:00:00Z", "args": ["db", "migrate", "v 5"], "cwd": "/srv", "exit": 0, "duration": 1.24}),
+            ),
+            (
+                3,
+                json!({"timestamp": "2026-10-18T10:00:00Z", "args": ["deploy", "--token", "<secret>"], "cwd": "/srv/prod", "exit": 2, "duration": 3}),
+            ),
+        ];
+
+        assert_eq!(
+            list_entries(&entries, Some("MIGRATE")),
+            "    1  2026-10-18T09:00:00Z    0     1.2s  db migrate 'v 5'  (/srv)\n"
+        );
+        assert_eq!(list_entries(&entries, None).lines().count(), 2);
+        assert_eq!(list_entries(&entries, Some("prod")).lines().count(), 1);
+
+        match rerun_entry("mycli", &entries, 1) {
+            HistoryAction::Rerun(args) => {
+                assert_eq!(args, to_args(&["mycli", "db", "migrate", "v 5"]))
+            }
+            _ => panic!("Entry 1 should be run again"),
+        }
+        assert!(matches!(
+            rerun_entry("mycli", &entries, 3),
+            HistoryAction::Failed(_)
+        ));
+        assert!(matches!(
+            rerun_entry("mycli", &entries, 2),
+            HistoryAction::Failed(_)
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+//! Running the hooks around the selected command: the scripts in the hooks directory, and the
+//! functions named by `@pre` and `@post` tags.
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::exec::RootOptionEnv;
+use crate::model::{Command, Hook, Model};
+use crate::supervise::{exit_code, run_supervised};
+use crate::utils::shell_quote;
+
+/// The environment variable holding the path of the command a hook is run for, e.g. `db migrate`.
+pub const HOOK_COMMAND_ENV: &str = "CLI_HOOK_COMMAND";
+
+/// The environment variable holding the exit status of the command, for post hooks.
+pub const HOOK_STATUS_ENV: &str = "CLI_HOOK_STATUS";
+
+/// The environment variable holding how long the command ran in whole seconds, for post hooks.
+pub const HOOK_DURATION_ENV: &str = "CLI_HOOK_DURATION";
+
+/// The hooks to run around the selected command, and what they are passed.
+pub(crate) struct HookRun<'a> {
+    /// The hooks run before the command, outermost first.
+    pre: Vec<&'a Hook>,
+    /// The hooks run after the command, innermost first.
+    post: Vec<&'a Hook>,
+    /// The names of the selected command and the commands containing it.
+    command: String,
+    /// The args of the hooks: the names of the commands, followed by the args of the command.
+    args: Vec<String>,
+}
+
+impl<'a> HookRun<'a> {
+    /// The hooks of the cli and of the given commands, from the top-level command to the selected
+    /// one, to be run with the given args of the selected command.
+    pub(crate) fn new(model: &'a Model, commands: &[&'a dyn Command], args: &[String]) -> Self {
+        let hooks: Vec<_> = std::iter::once(&model.metadata.hooks)
+            .chain(commands.iter().map(|command| command.hooks()))
+            .collect();
+
+        let names: Vec<String> = commands
+            .iter()
+            .map(|command| command.name().to_owned())
+            .collect();
+
+        HookRun {
+            pre: hooks
+                .iter()
+                .filter_map(|hooks| hooks.pre.as_ref())
+                .collect(),
+            post: hooks
+                .iter()
+                .rev()
+                .filter_map(|hooks| hooks.post.as_ref())
+                .collect(),
+            command: names.join(" "),
+            args: names.into_iter().chain(args.iter().cloned()).collect(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pre.is_empty() && self.post.is_empty()
+    }
+
+    /// Describes the hooks, for a dry run.
+    pub(crate) fn lines(&self) -> Vec<(&'static str, String)> {
+        let line = |hook: &Hook| self.words(hook).join(" ");
+
+        self.pre
+            .iter()
+            .map(|hook| ("pre hook", line(hook)))
+            .chain(self.post.iter().map(|hook| ("post hook", line(hook))))
+            .collect()
+    }
+
+    /// The line running the hook with the given variable assignments in the calling shell. A hook
+    /// function is called after sourcing its script, as the command itself is, since sourced scripts
+    /// do not call the function named by their first argument; a hook script is run as a process.
+    fn sourced_line(&self, hook: &Hook, variables: &str) -> String {
+        let args = self.args.iter().map(|arg| shell_quote(arg));
+
+        match hook.function.as_ref() {
+            Some(function) => format!(
+                "source {} && {} {}",
+                shell_quote(&hook.path.to_string_lossy()),
+                variables,
+                std::iter::once(function.clone())
+                    .chain(args)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            None => format!("{} {}", variables, self.words(hook).join(" ")),
+        }
+    }
+
+    /// The words of the command line running the hook.
+    fn words(&self, hook: &Hook) -> Vec<String> {
+        hook.words()
+            .into_iter()
+            .chain(self.args.iter().cloned())
+            .map(|word| shell_quote(&word))
+            .collect()
+    }
+}
+
+/// Runs the command with the given args in executed mode, with the hooks around it, and returns
+/// its exit code and how long it ran. If a pre hook fails, neither the command nor the later hooks
+/// are run, and its exit code is returned instead, without a duration. If `output_to_stderr`, the
+/// output of the command and the hooks is sent to stderr, as stdout is to be evaluated. The
+/// command and the hooks are passed the values of the root options in the environment.
+pub(crate) fn run_hooked(
+    command: &dyn Command,
+    args: Vec<String>,
+    hooks: &HookRun,
+    environment: &RootOptionEnv,
+    output_to_stderr: bool,
+) -> (i32, Option<Duration>) {
+    for hook in &hooks.pre {
+        let mut process = hook.process(&hooks.args);
+        environment.apply(&mut process);
+        process.env(HOOK_COMMAND_ENV, &hooks.command);
+
+        if output_to_stderr {
+            process.stdout(io::stderr());
+        }
+
+        match process.status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => return (exit_code(status), None),
+            Err(e) => {
+                eprintln!(
+                    "Error in running hook {} : {}",
+                    hook.path.to_string_lossy(),
+                    e
+                );
+                return (1, None);
+            }
+        }
+    }
+
+    let started = Instant::now();
+
+    let Some(code) = run_supervised(command, args, environment, output_to_stderr) else {
+        eprintln!("The command '{}' cannot be run", command.name());
+        return (1, None);
+    };
+
+    let duration = started.elapsed();
+
+    for hook in &hooks.post {
+        let mut process = hook.process(&hooks.args);
+        environment.apply(&mut process);
+        process
+            .env(HOOK_COMMAND_ENV, &hooks.command)
+            .env(HOOK_STATUS_ENV, code.to_string())
+            .env(HOOK_DURATION_ENV, duration.as_secs().to_string());
+
+        if output_to_stderr {
+            process.stdout(io::stderr());
+        }
+
+        // The exit code is the command's, whatever the post hooks do
+        if let Err(e) = process.status() {
+            eprintln!(
+                "Error in running hook {} : {}",
+                hook.path.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    (code, Some(duration))
+}
+
+/// Writes shell code running the given code for the command with the hooks around it, for the
+/// calling shell to evaluate, followed by the `finally` code, which can use the exit status of the
+/// command in `cli_status` and the value of `SECONDS` when it started in `cli_started`. The status
+/// of the code is that of the command, or of the failing pre hook. Both variables are unset again,
+/// so that they are not left in the calling shell.
+pub(crate) fn write_hooked(buffer: &mut Vec<u8>, hooks: &HookRun, run: &[u8], finally: &[u8]) {
+    if hooks.is_empty() && finally.is_empty() {
+        buffer.extend(run);
+        return;
+    }
+
+    let command = format!("{}={}", HOOK_COMMAND_ENV, shell_quote(&hooks.command));
+
+    hooks.pre.iter().for_each(|hook| {
+        writeln!(buffer, "{} &&", hooks.sourced_line(hook, &command))
+            .expect("Failed to write to buffer");
+    });
+
+    writeln!(buffer, "{{\ncli_started=$SECONDS").expect("Failed to write to buffer");
+    buffer.extend(run);
+    writeln!(buffer, "cli_status=$?").expect("Failed to write to buffer");
+
+    hooks.post.iter().for_each(|hook| {
+        let variables = format!(
+            "{} {}=$cli_status {}=$((SECONDS - cli_started))",
+            command, HOOK_STATUS_ENV, HOOK_DURATION_ENV
+        );
+
+        writeln!(buffer, "{}", hooks.sourced_line(hook, &variables))
+            .expect("Failed to write to buffer");
+    });
+
+    buffer.extend(finally);
+    // The status is expanded before the variable is unset
+    writeln!(
+        buffer,
+        "eval \"unset cli_started cli_status; (exit $cli_status)\"\n}}"
+    )
+    .expect("Failed to write to buffer");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::model::test::NO_DESCRIPTION;
+    use crate::model::{EmbeddedCommand, Hooks, ScriptCommand, WithMeta};
+
+    use super::*;
+
+    #[test]
+    fn write_hooked_runs_pre_hooks_outermost_first_and_post_hooks_innermost_first() {
+        let script = PathBuf::from("/tmp/db.sh");
+
+        let mut model = Model::new(vec![]);
+        model.metadata.hooks = Hooks::new(
+            Some(Hook::new(PathBuf::from("/tmp/_hooks/pre.sh"), None)),
+            Some(Hook::new(PathBuf::from("/tmp/_hooks/post.sh"), None)),
+        );
+
+        let db = ScriptCommand::new(
+            "db".to_owned(),
+            None,
+            script.clone(),
+            vec![],
+            vec![],
+            vec![],
+        )
+        .with_hooks(Hooks::new(
+            Some(Hook::new(script.clone(), Some("login".to_owned()))),
+            None,
+        ));
+        let migrate = EmbeddedCommand::new("migrate", NO_DESCRIPTION, vec![], vec![]).with_hooks(
+            Hooks::new(None, Some(Hook::new(script, Some("notify".to_owned())))),
+        );
+
+        let hooks = HookRun::new(&model, &[&db, &migrate], &["it's".to_owned()]);
+
+        let mut buffer = Vec::new();
+        write_hooked(
+            &mut buffer,
+            &hooks,
+            b"source \"/tmp/db.sh\"\ndb.migrate\n",
+            b"",
+        );
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "CLI_HOOK_COMMAND='db migrate' '/tmp/_hooks/pre.sh' 'db' 'migrate' 'it'\\''s' &&\n\
+             source '/tmp/db.sh' && CLI_HOOK_COMMAND='db migrate' login 'db' 'migrate' 'it'\\''s' &&\n\
+             {\n\
+             cli_started=$SECONDS\n\
+             source \"/tmp/db.sh\"\n\
+             db.migrate\n\
+             cli_status=$?\n\
+             source '/tmp/db.sh' && CLI_HOOK_COMMAND='db migrate' CLI_HOOK_STATUS=$cli_status CLI_HOOK_DURATION=$((SECONDS - cli_started)) notify 'db' 'migrate' 'it'\\''s'\n\
+             CLI_HOOK_COMMAND='db migrate' CLI_HOOK_STATUS=$cli_status CLI_HOOK_DURATION=$((SECONDS - cli_started)) '/tmp/_hooks/post.sh' 'db' 'migrate' 'it'\\''s'\n\
+             eval \"unset cli_started cli_status; (exit $cli_status)\"\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn write_hooked_without_hooks_only_runs_the_command() {
+        let model = Model::new(vec![]);
+        let hooks = HookRun::new(&model, &[], &[]);
+
+        let mut buffer = Vec::new();
+        write_hooked(&mut buffer, &hooks, b"run\n", b"");
+
+        assert_eq!(buffer, b"run\n");
+    }
+
+    #[test]
+    fn write_hooked_calls_hook_functions_of_sourced_scripts() {
+        let test_dir = tempfile::tempdir().unwrap();
+        let script = test_dir.path().join("db.sh");
+
+        // A sourced script only defines its functions, and does not dispatch on its first argument
+        std::fs::write(
+            &script,
+            "login() { echo \"login $*\"; }\n\
+             notify() { echo \"notify $CLI_HOOK_STATUS $*\"; }\n\
+             migrate() { echo migrate; return 3; }\n",
+        )
+        .unwrap();
+
+        let model = Model::new(vec![]);
+        let db = ScriptCommand::new(
+            "db".to_owned(),
+            None,
+            script.clone(),
+            vec![],
+            vec![],
+            vec![],
+        )
+        .with_hooks(Hooks::new(
+            Some(Hook::new(script.clone(), Some("login".to_owned()))),
+            Some(Hook::new(script.clone(), Some("notify".to_owned()))),
+        ));
+
+        let hooks = HookRun::new(&model, &[&db], &["prod".to_owned()]);
+
+        let mut buffer = Vec::new();
+        let run = format!(
+            "source {}\nmigrate\n",
+            shell_quote(&script.to_string_lossy())
+        );
+        write_hooked(&mut buffer, &hooks, run.as_bytes(), b"");
+
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(String::from_utf8(buffer).unwrap())
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "login db prod\nmigrate\nnotify 3 db prod\n"
+        );
+        assert_eq!(output.status.code(), Some(3));
+    }
+}
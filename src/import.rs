@@ -0,0 +1,35 @@
+//! Importers, which turn the tasks defined for other tools into commands of the model.
+use std::path::Path;
+
+use crate::model::BinaryCommand;
+
+pub mod just;
+pub mod make;
+pub mod npm;
+
+/// Imports the tasks defined in the given file as commands, if the file is handled by one of the
+/// importers.
+pub fn import_commands(path: &Path) -> Option<Result<Vec<BinaryCommand>, String>> {
+    if make::is_makefile(path) {
+        Some(make::build_make_commands(path))
+    } else if npm::is_package_json(path) {
+        Some(npm::build_npm_commands(path))
+    } else if just::is_justfile(path) {
+        Some(just::build_just_commands(path))
+    } else {
+        None
+    }
+}
+
+/// Returns true if the given file is handled by one of the importers.
+pub fn is_import_file(path: &Path) -> bool {
+    make::is_makefile(path) || npm::is_package_json(path) || just::is_justfile(path)
+}
+
+/// The directory containing the given file, in which its tasks are run.
+fn parent_dir(path: &Path) -> String {
+    path.parent()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or(".".to_owned())
+}
@@ -0,0 +1,266 @@
+//! Imports the recipes of a justfile as commands, which run `just <recipe>`.
+use std::path::Path;
+
+use crate::model::{ArgType, BinaryCommand, CommandArg, WithMeta};
+
+/// The prefix of the attribute documenting a recipe, which takes precedence over its comment.
+const DOC_ATTRIBUTE: &str = "[doc(";
+
+/// The attribute hiding a recipe from `just --list`.
+const PRIVATE_ATTRIBUTE: &str = "[private]";
+
+/// A public recipe of a justfile.
+#[derive(Debug, Clone, PartialEq)]
+struct Recipe {
+    name: String,
+    line: usize,
+    description: Option<String>,
+    params: Vec<CommandArg>,
+}
+
+/// Returns true if the given path is a file just reads by default; just ignores the case of the
+/// name.
+pub fn is_justfile(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| {
+            name.eq_ignore_ascii_case("justfile") || name.eq_ignore_ascii_case(".justfile")
+        })
+}
+
+/// Builds a command for each public recipe in the given justfile, with the parameters of the
+/// recipe as arguments.
+pub fn build_just_commands(path: &Path) -> Result<Vec<BinaryCommand>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    Ok(parse_recipes(&content)
+        .into_iter()
+        .map(|recipe| {
+            let description = recipe
+                .description
+                .unwrap_or(format!("Runs `just {}`", recipe.name));
+
+            // just runs the recipe in the directory of the justfile
+            BinaryCommand::new(
+                recipe.name.clone(),
+                "just",
+                vec![
+                    "--justfile".to_owned(),
+                    path.to_string_lossy().to_string(),
+                    recipe.name,
+                ],
+                Some(description),
+                vec![],
+                recipe.params,
+            )
+            .with_line(Some(recipe.line))
+            .with_path(Some(path.to_path_buf()))
+        })
+        .collect())
+}
+
+/// Finds the public recipes in the content of a justfile, along with their documentation: the
+/// comment on the line before the recipe, or its `[doc]` attribute.
+fn parse_recipes(content: &str) -> Vec<Recipe> {
+    let mut recipes = vec![];
+
+    let mut comment: Option<String> = None;
+    let mut doc: Option<String> = None;
+    let mut private = false;
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some(text) = line.strip_prefix('#') {
+            // A comment documents the recipe directly after it; shebangs are not comments
+            comment = Some(text.trim().to_owned()).filter(|_| !text.starts_with('!'));
+            continue;
+        }
+
+        if line.starts_with('[') {
+            if let Some(text) = line.strip_prefix(DOC_ATTRIBUTE) {
+                doc = Some(unquote(text.trim_end_matches([']', ')'])));
+            }
+            private |= line.starts_with(PRIVATE_ATTRIBUTE);
+            continue;
+        }
+
+        if let Some(mut words) = recipe_header(line) {
+            let name = words.remove(0);
+            let name = name.trim_start_matches('@');
+
+            if !private && !name.starts_with('_') {
+                recipes.push(Recipe {
+                    name: name.to_owned(),
+                    line: index + 1,
+                    description: doc.take().or(comment.take()),
+                    params: words.iter().map(|word| param(word)).collect(),
+                });
+            }
+        }
+
+        comment = None;
+        doc = None;
+        private = false;
+    }
+
+    recipes
+}
+
+/// Splits the header of a recipe, up to the colon, into the name and the parameters. Returns `None`
+/// if the line is not the header of a recipe, e.g. an assignment or a line of a recipe's body.
+fn recipe_header(line: &str) -> Option<Vec<String>> {
+    if line.starts_with(char::is_whitespace) || line.is_empty() {
+        return None;
+    }
+
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+    let mut depth = 0;
+
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ':') if depth == 0 => {
+                // := is an assignment, e.g. of a variable or alias
+                if chars.peek() == Some(&'=') {
+                    return None;
+                }
+
+                if !word.is_empty() {
+                    words.push(word);
+                }
+
+                return Some(words).filter(|words| !words.is_empty());
+            }
+            (None, c) if c.is_whitespace() && depth == 0 => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        word.push(c);
+    }
+
+    None
+}
+
+/// Converts a parameter of a recipe, e.g. `+files` or `target='debug'`, into an argument.
+fn param(word: &str) -> CommandArg {
+    let (word, var_arg, required) = match word.chars().next() {
+        Some('+') => (&word[1..], true, true),
+        Some('*') => (&word[1..], true, false),
+        _ => (word, false, true),
+    };
+
+    // Exported parameters are prefixed with $
+    let word = word.trim_start_matches('$');
+
+    match word.split_once('=') {
+        Some((name, default)) => CommandArg::new(
+            name,
+            true,
+            var_arg,
+            ArgType::Unknown,
+            Some(format!("Defaults to {}", default)),
+        ),
+        None => CommandArg::new(word, !required, var_arg, ArgType::Unknown, None::<String>),
+    }
+}
+
+/// Removes the quotes around a string literal.
+fn unquote(text: &str) -> String {
+    text.trim_matches(['\'', '"']).to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn parse_recipes_finds_public_recipes_with_params() {
+        let content = indoc! {r#"
+            set shell := ["bash", "-c"]
+            alias b := build
+
+            # Builds the app
+            build target='debug':
+                cargo build --profile {{target}}
+
+            [doc('Runs the tests')]
+            @test +filters: build
+                cargo test {{filters}}
+
+            _helper:
+                echo hidden
+
+            [private]
+            cleanup:
+                rm -rf target
+
+            serve $port *args:
+                ./serve {{port}} {{args}}
+            "#};
+
+        let recipes = parse_recipes(content);
+
+        assert_eq!(
+            recipes,
+            vec![
+                Recipe {
+                    name: "build".to_owned(),
+                    line: 5,
+                    description: Some("Builds the app".to_owned()),
+                    params: vec![CommandArg::new(
+                        "target",
+                        true,
+                        false,
+                        ArgType::Unknown,
+                        Some("Defaults to 'debug'"),
+                    )],
+                },
+                Recipe {
+                    name: "test".to_owned(),
+                    line: 9,
+                    description: Some("Runs the tests".to_owned()),
+                    params: vec![CommandArg::new(
+                        "filters",
+                        false,
+                        true,
+                        ArgType::Unknown,
+                        None::<String>,
+                    )],
+                },
+                Recipe {
+                    name: "serve".to_owned(),
+                    line: 19,
+                    description: None,
+                    params: vec![
+                        CommandArg::new("port", false, false, ArgType::Unknown, None::<String>),
+                        CommandArg::new("args", true, true, ArgType::Unknown, None::<String>),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn recipe_header_rejects_assignments() {
+        assert_eq!(recipe_header("version := '1.0'"), None);
+        assert_eq!(recipe_header("    echo a:b"), None);
+        assert_eq!(
+            recipe_header("run arg=(\"a:\" + b): build"),
+            Some(vec!["run".to_owned(), "arg=(\"a:\" + b)".to_owned()])
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Imports the targets of a Makefile as commands, which run `make <target>`.
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::import::parent_dir;
+use crate::model::{ArgType, BinaryCommand, CommandArg, WithMeta};
+
+/// The names of the files make reads by default.
+const MAKEFILE_NAMES: [&str; 3] = ["GNUmakefile", "makefile", "Makefile"];
+
+/// The prefix of the comments documenting a target, either on the lines before it or at the end of
+/// its rule.
+const DOC_COMMENT: &str = "##";
+
+lazy_static! {
+    /// Matches the line starting a rule, capturing the targets and the rest of the line. The targets
+    /// cannot contain `=`, so that variable assignments containing a colon do not match.
+    static ref RULE: Regex =
+        Regex::new(r"^([^\s:#=][^:#=]*?)\s*::?(.*)$").expect("Failed to compile regex");
+    /// Matches the names of targets that are tasks, rather than files or patterns.
+    static ref TASK_NAME: Regex =
+        Regex::new(r"^[A-Za-z0-9_][A-Za-z0-9_-]*$").expect("Failed to compile regex");
+}
+
+/// A target of a Makefile that is a task.
+#[derive(Debug, Clone, PartialEq)]
+struct Target {
+    name: String,
+    line: usize,
+    description: Option<String>,
+    details: Option<String>,
+}
+
+/// Returns true if the given path is a file make reads by default.
+pub fn is_makefile(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| MAKEFILE_NAMES.contains(&name))
+}
+
+/// Builds a command for each task target in the given Makefile. The command runs make in the
+/// directory of the Makefile, passing any args as variables.
+pub fn build_make_commands(path: &Path) -> Result<Vec<BinaryCommand>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    let dir = parent_dir(path);
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or(format!("Not a file: {}", path.to_string_lossy()))?;
+
+    Ok(parse_targets(&content)
+        .into_iter()
+        .map(|target| {
+            let description = target
+                .description
+                .unwrap_or(format!("Runs `make {}`", target.name));
+
+            BinaryCommand::new(
+                target.name.clone(),
+                "make",
+                vec![
+                    "-C".to_owned(),
+                    dir.clone(),
+                    "-f".to_owned(),
+                    file_name.clone(),
+                    target.name,
+                ],
+                Some(description),
+                vec![],
+                vec![CommandArg::new(
+                    "variables",
+                    true,
+                    true,
+                    ArgType::Unknown,
+                    Some("Variables to set, as NAME=value"),
+                )],
+            )
+            .with_line(Some(target.line))
+            .with_details(target.details)
+            .with_path(Some(path.to_path_buf()))
+        })
+        .collect())
+}
+
+/// Finds the task targets in the content of a Makefile, along with their documentation. A target
+/// defined by more than one rule is only returned for the first.
+fn parse_targets(content: &str) -> Vec<Target> {
+    let mut targets: Vec<Target> = vec![];
+
+    // The doc comments directly preceding the current line
+    let mut docs: Vec<String> = vec![];
+
+    for (index, line) in content.lines().enumerate() {
+        if let Some(doc) = line.strip_prefix(DOC_COMMENT) {
+            docs.push(doc.trim().to_owned());
+            continue;
+        }
+
+        let rule = RULE
+            .captures(line)
+            // Exclude assignments with := and ::=
+            .filter(|captures| !captures[2].starts_with('='));
+
+        if let Some(captures) = rule {
+            // A doc comment at the end of the rule takes precedence over the preceding ones
+            let (description, details) = match captures[2].split_once(DOC_COMMENT) {
+                Some((_, doc)) => (Some(doc.trim().to_owned()), None),
+                None => {
+                    let mut docs = docs.iter();
+                    let description = docs.next().cloned();
+                    let details = docs.cloned().collect::<Vec<String>>().join("\n");
+
+                    (
+                        description,
+                        Some(details).filter(|details| !details.is_empty()),
+                    )
+                }
+            };
+
+            captures[1]
+                .split_whitespace()
+                .filter(|name| TASK_NAME.is_match(name))
+                .for_each(|name| {
+                    if !targets.iter().any(|target| target.name == name) {
+                        targets.push(Target {
+                            name: name.to_owned(),
+                            line: index + 1,
+                            description: description.clone(),
+                            details: details.clone(),
+                        })
+                    }
+                });
+        }
+
+        docs.clear();
+    }
+
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn is_makefile_matches_default_names() {
+        assert!(is_makefile(Path::new("/tmp/Makefile")));
+        assert!(is_makefile(Path::new("GNUmakefile")));
+        assert!(!is_makefile(Path::new("/tmp/Makefile.sh")));
+    }
+
+    #[test]
+    fn parse_targets_finds_tasks_and_docs() {
+        let content = indoc! {"
+            .PHONY: build test
+
+            VERSION := 1.0
+            CC = gcc
+
+            ## Builds the project
+            ## with the default toolchain
+            build: deps
+            \t@echo building $(VERSION)
+
+            test: build ## Runs the tests
+            \t@echo testing
+
+            docker-build docker-push:
+            \t@echo $@
+
+            %.o: %.c
+            \t$(CC) -c $<
+
+            main.o: main.c
+
+            build: MODE = release
+            "};
+
+        let targets = parse_targets(content);
+
+        assert_eq!(
+            targets,
+            vec![
+                Target {
+                    name: "build".to_owned(),
+                    line: 8,
+                    description: Some("Builds the project".to_owned()),
+                    details: Some("with the default toolchain".to_owned()),
+                },
+                Target {
+                    name: "test".to_owned(),
+                    line: 11,
+                    description: Some("Runs the tests".to_owned()),
+                    details: None,
+                },
+                Target {
+                    name: "docker-build".to_owned(),
+                    line: 14,
+                    description: None,
+                    details: None,
+                },
+                Target {
+                    name: "docker-push".to_owned(),
+                    line: 14,
+                    description: None,
+                    details: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Imports the scripts of a `package.json` as commands, which run `npm run <script>`.
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::import::parent_dir;
+use crate::model::{ArgType, BinaryCommand, CommandArg};
+
+const PACKAGE_JSON: &str = "package.json";
+
+/// The key of the descriptions of the scripts, as used by e.g. `npm-scripts-info`. npm itself has
+/// no way of describing a script.
+const SCRIPTS_INFO: &str = "scripts-info";
+
+/// Returns true if the given path is a `package.json`.
+pub fn is_package_json(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == PACKAGE_JSON)
+}
+
+/// Builds a command for each script in the given `package.json`, except for the pre- and
+/// post-scripts npm runs around other scripts. The command passes any args on to the script.
+pub fn build_npm_commands(path: &Path) -> Result<Vec<BinaryCommand>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    let package: Value = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+
+    let Some(scripts) = package["scripts"].as_object() else {
+        return Ok(vec![]);
+    };
+
+    let infos = package[SCRIPTS_INFO].as_object();
+
+    let dir = parent_dir(path);
+
+    Ok(scripts
+        .iter()
+        .filter(|(name, _)| !is_hook(name, scripts))
+        .filter(|(name, _)| !name.contains(char::is_whitespace))
+        .filter_map(|(name, script)| script.as_str().map(|script| (name, script)))
+        .map(|(name, script)| {
+            // Without a description, show what the script runs
+            let description = infos
+                .and_then(|infos| infos.get(name))
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+                .unwrap_or(format!("Runs `{}`", script));
+
+            BinaryCommand::new(
+                name.clone(),
+                "npm",
+                vec![
+                    "--prefix".to_owned(),
+                    dir.clone(),
+                    "run".to_owned(),
+                    name.clone(),
+                    "--".to_owned(),
+                ],
+                Some(description),
+                vec![],
+                vec![CommandArg::new(
+                    "args",
+                    true,
+                    true,
+                    ArgType::Unknown,
+                    Some("Any arguments are passed to the script"),
+                )],
+            )
+            .with_path(Some(path.to_path_buf()))
+        })
+        .collect())
+}
+
+/// Returns true if the script is run by npm before or after another script.
+fn is_hook(name: &str, scripts: &Map<String, Value>) -> bool {
+    ["pre", "post"].iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|script| scripts.contains_key(script))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+
+    use indoc::indoc;
+
+    use crate::model::Command;
+
+    use super::*;
+
+    #[test]
+    fn build_npm_commands_skips_hooks_and_reads_info() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let package_path = test_dir.path().join(PACKAGE_JSON);
+
+        File::create(&package_path)
+            .unwrap()
+            .write_all(
+                indoc! {r#"
+                {
+                  "name": "app",
+                  "scripts": {
+                    "build": "tsc -p .",
+                    "prebuild": "rm -rf dist",
+                    "test:unit": "jest"
+                  },
+                  "scripts-info": {
+                    "build": "Compiles the app"
+                  }
+                }
+                "#}
+                .as_bytes(),
+            )
+            .unwrap_or_else(|_| panic!("Unable to create file {}", package_path.to_str().unwrap()));
+
+        let commands = build_npm_commands(&package_path).unwrap();
+
+        assert_eq!(commands.len(), 2);
+
+        assert_eq!(commands[0].name(), "build");
+        assert_eq!(commands[0].description(), Some("Compiles the app"));
+
+        assert_eq!(commands[1].name(), "test:unit");
+        assert_eq!(commands[1].description(), Some("Runs `jest`"));
+        assert_eq!(
+            commands[1].eval_code(&["--watch".to_owned()]),
+            Some(format!(
+                "command 'npm' '--prefix' '{}' 'run' 'test:unit' '--' '--watch'",
+                test_dir.path().to_str().unwrap()
+            ))
+        );
+    }
+}
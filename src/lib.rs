@@ -0,0 +1,69 @@
+//! easy-cli builds a command line interface from a directory of scripts.
+//!
+//! Each script becomes a command of the cli; annotations in the comments of a script describe
+//! its options, arguments and sub-commands (see `ANNOTATIONS.md`). The main parts are:
+//!
+//! - [model]: the [Model](model::Model) of a cli, made up of [Command](model::Command)s, which is
+//!   usually read from a directory of scripts with [Model::from](model::Model#impl-From<P>-for-Model).
+//! - [builder]: the annotation parser, which builds a command from a single script with
+//!   [build_script_command](builder::build_script_command).
+//! - [import]: importers turning the tasks of other tools - Makefile targets, npm scripts and just
+//!   recipes - into commands.
+//! - [transform]: the conversion of a model to a clap command, via
+//!   [ToCliCommand](transform::ToCliCommand).
+//! - [exec]: the execution of the command selected by the command line, either directly or by
+//!   emitting a script for the calling shell to evaluate.
+//! - [history]: the log of the commands run, and the global options listing it and running a
+//!   command from it again.
+//! - [hooks]: the scripts and functions run before and after the selected command.
+//! - [prompt]: interactive prompting for the command to run and the required arguments missing
+//!   from a command line, and for the confirmation of dangerous commands.
+//! - [repl]: an interactive session for running commands one after the other.
+//! - [reload]: hot reloading of a model as its scripts change, for long-lived processes.
+//! - [supervise]: the timeouts and retries of commands run in executed mode.
+//! - [watch]: watch mode, running a command again whenever the files it depends on change.
+//!
+//! Tools embedding a script-backed cli can add their own commands to the model before converting
+//! it:
+//!
+//! ```
+//! use std::path::PathBuf;
+//!
+//! use easy_cli::exec::build_embedded_script;
+//! use easy_cli::model::{Model, ScriptCommand};
+//! use easy_cli::transform::ToCliCommand;
+//!
+//! let mut model = Model::from("/path/to/scripts");
+//!
+//! model.add_command(ScriptCommand::new(
+//!     "deploy".to_owned(),
+//!     Some("Deploys the application".to_owned()),
+//!     PathBuf::from("/opt/tools/deploy.sh"),
+//!     vec![],
+//!     vec![],
+//!     vec![],
+//! ));
+//!
+//! let cli = model.to_cli();
+//!
+//! let script = build_embedded_script(&model, cli, vec!["mycli".to_owned(), "deploy".to_owned()]);
+//!
+//! assert!(String::from_utf8(script).unwrap().contains("source '/opt/tools/deploy.sh'"));
+//! ```
+pub mod builder;
+pub mod docs;
+pub mod dump;
+pub mod exec;
+pub mod history;
+pub mod hooks;
+pub mod import;
+pub mod man;
+pub mod model;
+pub mod prompt;
+pub mod reload;
+pub mod repl;
+pub mod supervise;
+pub mod transform;
+pub mod watch;
+
+mod utils;
@@ -2,23 +2,22 @@ use std::io::Write;
 use std::path::PathBuf;
 use std::{io, process::exit, str::FromStr};
 
-use clap::error::Error;
-use clap::{parser::ValuesRef, Arg, ArgMatches};
+use clap::builder::PossibleValue;
+use clap::{Arg, ArgGroup};
 use clap_complete::{generate, Shell};
 use log::debug;
 
-use crate::model::Command;
-use crate::transform::ToCliCommand;
-use model::HasSubCommands;
-use model::Model;
-
-mod model;
-mod utils;
-
-mod builder;
-mod transform;
+use easy_cli::docs::{render_docs, DocsFormat};
+use easy_cli::dump::{dump, DumpFormat};
+use easy_cli::exec::{execute_cli, write_embedded_script};
+use easy_cli::man::generate_man_pages;
+use easy_cli::model::Model;
+use easy_cli::transform::ToCliCommand;
 
 const COMPLETIONS_ARG: &str = "completions";
+const MAN_ARG: &str = "man";
+const DOCS_ARG: &str = "docs";
+const DUMP_MODEL_ARG: &str = "dump-model";
 
 const CLI_SRC_ARG: &str = "SOURCE PATH";
 const CLI_NAME_ARG: &str = "name";
@@ -26,208 +25,51 @@ const CLI_EXECUTED_ARG: &str = "executed";
 
 const COMMAND_ARGS: &str = "command_args";
 
+/// The group of the args selecting what easy-cli does: executing the command, rather than having it
+/// evaluated, or something else instead of running a command. Only one may be given.
+const MODE_GROUP: &str = "mode";
+
 const DEFAULT_CLI_NAME: &str = "cli";
 
 enum Mode {
     Executed,
     Evaluated,
     Completions(String),
+    Man(PathBuf),
+    Docs(DocsFormat),
+    Dump(DumpFormat),
 }
 fn main() {
     env_logger::init();
 
-    let (cli_source, cli_args, mode) = extract_cli_source_and_args();
-
-    let model = Model::from(&cli_source);
-
-    let cli: clap::Command = model.to_cli();
-
-    debug!("args-{}", cli_args.join(" "));
-
-    match mode {
-        Mode::Completions(shell) => handle_completions(cli, cli_args.iter().next().unwrap(), shell),
-        Mode::Executed => execute_cli(model, cli, cli_args),
-        Mode::Evaluated => write_embedded_script(model, cli, cli_args),
-    }
-}
-
-fn build_embedded_script(model: Model, mut cli: clap::Command, cli_args: Vec<String>) -> Vec<u8> {
-    cli.try_get_matches_from_mut(cli_args.iter()).map_or_else(
-        |err| {
-            // Render the error. This is also where help and usage messages are rendered, since they are represented
-            // as errors in clap.
-            echo_error_script(err)
-        },
-        |matches| {
-            // render shell commands to execute the appropriate script, having setup the parameters
-            exec_commands_script(model, matches)
-        },
-    )
-}
+    let (cli_source, name, command_args, mode) = extract_cli_source_and_args();
 
-fn write_embedded_script(model: Model, cli: clap::Command, cli_args: Vec<String>) {
-    // In embedded mode, don't let clap print to stdout because stdout is to be evaled. So we need to capture
-    // version and help requests (which are returned here as errors)
+    let mut model = Model::from(&cli_source);
 
-    let buffer = build_embedded_script(model, cli, cli_args);
-
-    // Write the produced content to stdout
-    io::stdout()
-        .write_all(&buffer)
-        .expect("Failed to write to stdout");
-}
-
-fn echo_error_script(err: Error) -> Vec<u8> {
-    let mut buffer = Vec::new();
-    write!(&mut buffer, "echo \"{}\"", err.render().ansi()).expect("Failed to write to buffer");
-    buffer
-}
-
-fn exec_commands_script(model: Model, arg_matches: clap::ArgMatches) -> Vec<u8> {
-    let (script_to_call, matches) = arg_matches.subcommand().unwrap();
-
-    let command = model.get_command(script_to_call).unwrap();
-
-    let mut current_command = command;
-    let mut path: &PathBuf = current_command.get_path().unwrap();
-
-    debug!("args-{}", command.name());
-
-    let mut current = matches;
-
-    let mut opts = Vec::<(&str, bool)>::new();
-    let mut args = Vec::<(&str, String)>::new();
-
-    // recursively collect subcommand names into a vector while it is not None
-    let mut result = vec![];
-    loop {
-        add_opts_and_args(current, current_command, &mut opts, &mut args);
-        match current.subcommand() {
-            None => break,
-
-            Some((sub_name, sub_matches)) => {
-                result.push(sub_name.to_owned());
-                current = sub_matches;
-                current_command = current_command.get_command(sub_name).unwrap();
-
-                if let Some(new_path) = current_command.get_path() {
-                    path = new_path
-                }
-            }
-        }
-    }
-
-    let mut buffer = Vec::new();
-
-    writeln!(&mut buffer, "#eval").expect("Failed to write to buffer");
-    writeln!(&mut buffer, "typeset -A cli_args").expect("Failed to write to buffer");
-    writeln!(
-        &mut buffer,
-        "cli_args=({})",
-        args.iter()
-            .map(|arg| format!("\"{}\" \"{}\"", arg.0, arg.1))
-            .collect::<Vec<String>>()
-            .join(" ")
-    )
-    .expect("Failed to write to buffer");
-    writeln!(&mut buffer, "typeset -A cli_opts").expect("Failed to write to buffer");
-    writeln!(
-        &mut buffer,
-        "cli_opts=({})",
-        opts.iter()
-            .map(|opt| format!("\"{}\" {}", opt.0, opt.1))
-            .collect::<Vec<String>>()
-            .join(" ")
-    )
-    .expect("Failed to write to buffer");
-    writeln!(&mut buffer, "source \"{}\"", path.to_str().unwrap())
-        .expect("Failed to write to buffer");
-
-    if current_command.get_path() == None {
-        writeln!(&mut buffer, "{}", current_command.name()).expect("Failed to write to buffer");
-    }
-
-    buffer
-}
-
-fn add_opts_and_args<'a>(
-    matches: &'a ArgMatches,
-    command: &'a Box<dyn Command>,
-    opts: &mut Vec<(&'a str, bool)>,
-    args: &mut Vec<(&'a str, String)>,
-) {
-    matches.ids().for_each(|id| {
-        let name = id.as_str();
-
-        if let Some(option) = command.get_option(name) {
-            if option.has_param {
-                todo!("Handle options with args")
-            } else {
-                let opt_set = matches.get_flag(name);
-
-                opts.push((name, opt_set));
-            }
-        }
-
-        if let Some(_) = command.get_arg(name) {
-            let value_str = matches
-                .get_raw(name)
-                .map(|value| {
-                    let strings = value
-                        .map(|v| v.to_str().unwrap().to_string())
-                        .collect::<Vec<String>>();
-                    strings.join(",")
-                })
-                .unwrap_or("".to_string());
-            args.push((name, value_str));
-        }
-    });
-}
-
-fn execute_cli(model: Model, cli: clap::Command, cli_args: Vec<String>) {
-    let arg_matches = cli.get_matches_from(cli_args.iter());
-
-    let (script_to_call, matches) = arg_matches.subcommand().unwrap();
-
-    let command = model.get_command(script_to_call).unwrap();
-
-    let current_command = command;
+    // The name given to the launcher takes precedence over the one in the cli metadata.
+    let name = name
+        .or(model.metadata.name.clone())
+        .unwrap_or(DEFAULT_CLI_NAME.to_owned());
 
-    debug!("args-{}", command.name());
+    model.metadata.name = Some(name.clone());
 
-    let mut current = matches;
+    let cli_args = build_cli_args(name, command_args);
 
-    let mut opts = Vec::<(&str, bool)>::new();
-    let mut args = Vec::<(&str, String)>::new();
+    let cli: clap::Command = model.to_cli();
 
-    // recursively collect subcommand names into a vector while it is not None
-    let mut result = vec![];
-    loop {
-        add_opts_and_args(current, current_command, &mut opts, &mut args);
+    debug!("args-{}", cli_args.join(" "));
 
-        match current.subcommand() {
-            None => break,
-            Some((sub_name, sub_matches)) => {
-                result.push(sub_name.to_owned());
-                current = sub_matches;
-            }
-        }
+    match mode {
+        Mode::Completions(shell) => handle_completions(cli, cli_args.first().unwrap(), shell),
+        Mode::Man(out_dir) => handle_man_pages(cli, out_dir),
+        Mode::Docs(format) => handle_docs(&model, format),
+        Mode::Dump(format) => handle_dump(&model, format),
+        Mode::Executed => execute_cli(&mut model, cli, cli_args),
+        Mode::Evaluated => write_embedded_script(&mut model, cli, cli_args),
     }
-
-    // Collect the args again, to pass to the script
-    current
-        .ids()
-        .filter_map(|id| current.get_raw(id.as_str()))
-        .for_each(|args| {
-            args.for_each(|arg| {
-                result.push(arg.to_str().unwrap().to_owned());
-            });
-        });
-
-    command.exec(Some(result));
 }
 
-fn extract_cli_source_and_args() -> (String, Vec<String>, Mode) {
+fn extract_cli_source_and_args() -> (String, Option<String>, Vec<String>, Mode) {
     // Create an argument-parser for easy-cli itself.
     let mut launcher_cli = launcher_cli();
 
@@ -247,52 +89,57 @@ fn extract_cli_source_and_args() -> (String, Vec<String>, Mode) {
         .clone();
 
     // Determine the name of the cli, used in help messages.
-    let name: String = launcher_matches
-        .get_one::<String>(CLI_NAME_ARG)
-        .map(String::clone)
-        .unwrap_or(DEFAULT_CLI_NAME.to_owned());
+    let name: Option<String> = launcher_matches.get_one::<String>(CLI_NAME_ARG).cloned();
 
     let executed: bool = launcher_matches
         .get_one::<bool>(CLI_EXECUTED_ARG)
-        .map(|x| *x)
+        .copied()
         .unwrap_or(false);
 
-    let shell_for_completions: Option<String> = launcher_matches
-        .get_one::<String>(COMPLETIONS_ARG)
-        .map(String::clone);
-
-    let mode = match shell_for_completions {
-        None => {
-            if executed {
-                Mode::Executed
-            } else {
-                Mode::Evaluated
-            }
-        }
-        Some(shell) => Mode::Completions(shell),
+    let shell_for_completions: Option<String> =
+        launcher_matches.get_one::<String>(COMPLETIONS_ARG).cloned();
+
+    let man_dir: Option<PathBuf> = launcher_matches
+        .get_one::<String>(MAN_ARG)
+        .map(PathBuf::from);
+
+    let docs_format: Option<DocsFormat> = launcher_matches
+        .get_one::<String>(DOCS_ARG)
+        .map(|format| DocsFormat::from_str(format).unwrap(/* Restricted to possible values */));
+
+    let dump_format: Option<DumpFormat> = launcher_matches
+        .get_one::<String>(DUMP_MODEL_ARG)
+        .map(|format| DumpFormat::from_str(format).unwrap(/* Restricted to possible values */));
+
+    let mode = if let Some(shell) = shell_for_completions {
+        Mode::Completions(shell)
+    } else if let Some(out_dir) = man_dir {
+        Mode::Man(out_dir)
+    } else if let Some(format) = docs_format {
+        Mode::Docs(format)
+    } else if let Some(format) = dump_format {
+        Mode::Dump(format)
+    } else if executed {
+        Mode::Executed
+    } else {
+        Mode::Evaluated
     };
 
     let command_args = launcher_matches
         .get_many::<String>(COMMAND_ARGS)
-        .map(|args| args.clone());
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
 
-    (cli_source, build_cli_args(name, command_args), mode)
+    (cli_source, name, command_args, mode)
 }
 
 /// Builds the artificial command line args for use with the cli-parser for the configured cli.
-fn build_cli_args(name: String, command_args: Option<ValuesRef<String>>) -> Vec<String> {
-    // The full list of args for the cli contains the cli name...
-    Box::new([name].into_iter())
-        .chain(
-            //... followed by all the trailing args to easy-cli.
-            Box::new(
-                command_args
-                    .into_iter()
-                    .flat_map(|values| values)
-                    .map(String::clone),
-            ),
-        )
-        .collect()
+fn build_cli_args(name: String, command_args: Vec<String>) -> Vec<String> {
+    // The full list of args for the cli contains the cli name, followed by all the trailing args to
+    // easy-cli.
+    [name].into_iter().chain(command_args).collect()
 }
 
 /// Creates an argument-parser for easy-cli itself.
@@ -323,17 +170,44 @@ fn launcher_cli() -> clap::Command {
                 .help("Generate shell completions")
                 .value_name("shell"),
         )
+        .arg(
+            Arg::new(MAN_ARG)
+                .long(MAN_ARG)
+                .help("Generate man pages for the cli and all its commands into the given directory")
+                .value_name("dir"),
+        )
+        .arg(
+            Arg::new(DOCS_ARG)
+                .long(DOCS_ARG)
+                .help("Print a reference of the cli and all its commands in the given format")
+                // The formats accepted by DocsFormat::from_str
+                .value_parser([PossibleValue::new("markdown").alias("md"), PossibleValue::new("html")])
+                .ignore_case(true)
+                .value_name("format"),
+        )
+        .arg(
+            Arg::new(DUMP_MODEL_ARG)
+                .long(DUMP_MODEL_ARG)
+                .help("Print the model of the cli as JSON, or the JSON schema of that format")
+                .value_parser(["json", "json-schema"])
+                .value_name("format"),
+        )
         .arg(
             Arg::new(COMMAND_ARGS)
                 .allow_hyphen_values(true)
                 .num_args(0..=10)
                 .trailing_var_arg(true),
         )
+        .group(ArgGroup::new(MODE_GROUP).args([
+            CLI_EXECUTED_ARG,
+            COMPLETIONS_ARG,
+            MAN_ARG,
+            DOCS_ARG,
+            DUMP_MODEL_ARG,
+        ]))
 }
 
 fn handle_completions(mut cli: clap::Command, cli_name: &str, shell_name: String) {
-    let cli_name = cli_name;
-
     match Shell::from_str(shell_name.as_str()) {
         Ok(shell) => {
             generate(shell, &mut cli, cli_name, &mut io::stdout());
@@ -346,54 +220,28 @@ fn handle_completions(mut cli: clap::Command, cli_name: &str, shell_name: String
     };
 }
 
-#[cfg(test)]
-mod tests {
-    use std::vec;
-
-    use crate::model::{ArgType, CommandArg, EmbeddedCommand, ScriptCommand};
-
-    use super::*;
-
-    #[test]
-    fn test_build_cli_args() {
-        let bar: EmbeddedCommand = EmbeddedCommand::new(
-            "bar".to_owned(),
-            Option::<String>::None,
-            vec![],
-            vec![CommandArg::new(
-                "arg1".to_owned(),
-                false,
-                false,
-                ArgType::Unknown,
-                Option::<String>::None,
-            )],
-        );
-
-        let foo = ScriptCommand::new(
-            "foo".to_owned(),
-            None,
-            PathBuf::from("/tmp/foo.sh"),
-            vec![],
-            vec![],
-            vec![Box::new(bar)],
-        );
-
-        let model = Model::new(vec![Box::new(foo)]);
-        let command = model.to_cli();
-
-        // capture the ouput produced by embedded_commands
-        let out = build_embedded_script(
-            model,
-            command,
-            vec![
-                "blah".to_owned(),
-                "foo".to_owned(),
-                "bar".to_owned(),
-                "arg1Val".to_owned(),
-            ],
-        );
-
-        let out_str = String::from_utf8(out).expect("Failed to convert to string");
-        assert_eq!(out_str, "#eval\ntypeset -A cli_args\ncli_args=(\"arg1\" \"arg1Val\")\ntypeset -A cli_opts\ncli_opts=()\nsource \"/tmp/foo.sh\"\nbar\n");
+fn handle_man_pages(cli: clap::Command, out_dir: PathBuf) {
+    match generate_man_pages(cli, &out_dir) {
+        Ok(()) => exit(0),
+        Err(e) => {
+            eprintln!(
+                "Error writing man pages to '{}': {}",
+                out_dir.to_string_lossy(),
+                e
+            );
+            exit(1);
+        }
     }
 }
+
+fn handle_docs(model: &Model, format: DocsFormat) {
+    io::stdout()
+        .write_all(render_docs(model, format).as_bytes())
+        .expect("Failed to write to stdout");
+    exit(0);
+}
+
+fn handle_dump(model: &Model, format: DumpFormat) {
+    println!("{}", dump(model, format));
+    exit(0);
+}
@@ -0,0 +1,62 @@
+//! Generation of man pages for the CLI
+use std::fs::create_dir_all;
+use std::io;
+use std::path::Path;
+
+/// Writes a man page for the given command, and one for each of its (transitive) sub-commands,
+/// into the given directory, creating the directory if necessary.
+pub fn generate_man_pages(cli: clap::Command, out_dir: &Path) -> io::Result<()> {
+    create_dir_all(out_dir)?;
+
+    clap_mangen::generate_to(cli, out_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::read_to_string;
+    use std::path::PathBuf;
+
+    use crate::model::{CliMetadata, Command};
+    use crate::model::{EmbeddedCommand, Model, ScriptCommand, WithMeta};
+    use crate::transform::ToCliCommand;
+
+    use super::*;
+
+    #[test]
+    fn generates_page_per_command() {
+        let sub = EmbeddedCommand::new("bar", Some("Does bar"), vec![], vec![])
+            .with_examples(vec!["mycli foo bar".to_string()]);
+
+        let foo = ScriptCommand::new(
+            "foo".to_owned(),
+            Some("Does foo".to_owned()),
+            PathBuf::from("/tmp/foo.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(sub) as Box<dyn Command>],
+        );
+
+        let model = Model::new(vec![Box::new(foo)]).with_metadata(CliMetadata {
+            name: Some("mycli".to_string()),
+            ..CliMetadata::default()
+        });
+
+        let out_dir = tempfile::tempdir().unwrap();
+        let man_dir = out_dir.path().join("man1");
+
+        generate_man_pages(model.to_cli(), &man_dir).unwrap();
+
+        let mut pages: Vec<String> = std::fs::read_dir(&man_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        pages.sort();
+
+        assert_eq!(pages, vec!["mycli-foo-bar.1", "mycli-foo.1", "mycli.1"]);
+
+        let bar_page = read_to_string(man_dir.join("mycli-foo-bar.1")).unwrap();
+
+        assert!(bar_page.contains("Does bar"));
+        assert!(bar_page.contains("mycli foo bar"));
+    }
+}
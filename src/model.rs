@@ -1,5 +1,7 @@
+use std::fmt::{self, Display, Formatter};
 use std::fs::read_dir;
 use std::path::Path;
+use std::time::Duration;
 use std::{
     path::PathBuf,
     process::{self, exit},
@@ -8,7 +10,15 @@ use std::{
 use lazy_static::lazy_static;
 use regex::Regex;
 
-use crate::builder::build_script_command;
+use crate::builder::{
+    build_cli_metadata, build_config_commands, build_dir_hooks, build_group_command,
+    build_script_command, check_global_option_clashes, is_cli_metadata_file, is_group_file,
+};
+use crate::exec::RootOptionEnv;
+use crate::import::import_commands;
+use crate::supervise::{exit_code, run_supervised};
+use crate::transform::replaced_built_in_options;
+use crate::utils::shell_quote;
 
 lazy_static! {
     pub static ref SUB_COMMAND: Regex =
@@ -17,57 +27,282 @@ lazy_static! {
         Regex::new(r"# @ignore-at-root").expect("Failed to compile regex");
 }
 
+/// The model of a single CLI tool: its metadata and its (top-level) commands.
 pub struct Model {
+    pub metadata: CliMetadata,
     pub commands: Vec<Box<dyn Command>>,
+    /// The directory the model was read from, if any.
+    pub source: Option<PathBuf>,
+}
+
+/// Metadata describing the CLI tool itself, rather than any of its commands.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CliMetadata {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub after_help: Option<String>,
+    /// The options of the root command, which can be given to every command.
+    pub options: Vec<CommandOption>,
+    /// The hooks run before and after every command.
+    pub hooks: Hooks,
+    /// Where the commands run are logged.
+    pub audit_log: AuditLog,
+}
+
+/// The file in which the commands run are logged, as set by the `@audit` tag.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum AuditLog {
+    /// `.<name>_audit.log` in the home directory.
+    #[default]
+    InHome,
+    /// The file at the path, relative to the home directory unless absolute.
+    File(PathBuf),
+    /// The commands run are not logged, as set by `@audit off`.
+    Off,
+}
+
+impl AuditLog {
+    /// Whether the commands run are logged.
+    pub fn is_on(&self) -> bool {
+        *self != AuditLog::Off
+    }
 }
 
+/// Implemented by anything containing commands, to look them up by name.
 pub trait HasSubCommands {
-    fn get_command(&self, name: &str) -> Option<&Box<dyn Command>>;
+    fn get_command(&self, name: &str) -> Option<&dyn Command>;
 }
 
-/// The model of a single CLI tool.
 impl Model {
     pub fn new(commands: Vec<Box<dyn Command>>) -> Model {
-        Model { commands }
+        Model {
+            metadata: CliMetadata::default(),
+            commands,
+            source: None,
+        }
+    }
+
+    /// Adds a command to the model, e.g. one that is not backed by a script in the source directory.
+    pub fn add_command<C: Command + 'static>(&mut self, command: C) {
+        self.commands.push(Box::new(command));
+    }
+
+    /// Replaces the metadata of the CLI tool.
+    pub fn with_metadata(mut self, metadata: CliMetadata) -> Model {
+        self.metadata = metadata;
+        self
     }
 }
 
 impl<P: AsRef<Path>> From<P> for Model {
     fn from(path: P) -> Self {
-        let commands = read_dir(path)
-            .map(|scripts| {
-                scripts
-                    .filter_map(|entry| {
-                        entry
-                            .ok()
-                            .filter(|entry| {
-                                entry
-                                    .file_type()
-                                    .ok()
-                                    .map_or(false, |file_type| file_type.is_file())
-                            })
-                            .map(|entry| {
-                                let path = entry.path();
-                                build_script_command(path)
-                                    .ok()
-                                    .flatten()
-                                    .map(|command| Box::new(command) as Box<dyn Command>)
-                            })
-                            .flatten()
-                    })
-                    .collect()
-            })
-            .unwrap_or(Vec::new());
-        Model::new(commands)
+        let contents = read_command_dir(path.as_ref());
+
+        // The metadata file describes the cli itself and the commands defined in it
+        let (mut metadata, config_commands) = match contents.cli_file.as_ref() {
+            Some(cli_file) => (
+                build_cli_metadata(cli_file).unwrap_or_else(|e| report_file_error(cli_file, e)),
+                build_config_commands(cli_file).unwrap_or_else(|e| report_file_error(cli_file, e)),
+            ),
+            None => (CliMetadata::default(), vec![]),
+        };
+
+        metadata.hooks = build_dir_hooks(path.as_ref());
+
+        replaced_built_in_options(&metadata)
+            .into_iter()
+            .for_each(report_replaced_option);
+
+        let commands = contents
+            .into_commands(config_commands)
+            .into_iter()
+            .filter(|command| has_no_option_clashes(&metadata, command.as_ref(), path.as_ref()))
+            .collect();
+
+        let mut model = Model::new(commands).with_metadata(metadata);
+        model.source = Some(path.as_ref().to_path_buf());
+        model
+    }
+}
+
+/// Reports an error in a file of the source directory, which is then read as if it were empty, so
+/// a script with an error is left out of the cli. The report goes to stderr, as stdout may be
+/// evaluated.
+pub(crate) fn report_file_error<T: Default>(path: &Path, error: String) -> T {
+    eprintln!("Error in reading {} : {}", path.to_string_lossy(), error);
+    T::default()
+}
+
+/// Whether none of the options and arguments of the command clash with those every command of the
+/// cli has. A command with a clash is reported as an error in the file it is read from, or else in
+/// the given directory, and left out of the cli.
+pub(crate) fn has_no_option_clashes(
+    metadata: &CliMetadata,
+    command: &dyn Command,
+    dir: &Path,
+) -> bool {
+    check_global_option_clashes(metadata, command)
+        .map_err(|e| report_file_error::<()>(command.get_path().map_or(dir, PathBuf::as_path), e))
+        .is_ok()
+}
+
+/// Reports a root option of the cli that replaces the built-in option of the same name. The report
+/// goes to stderr, as stdout may be evaluated.
+fn report_replaced_option(name: &str) {
+    eprintln!(
+        "The option '--{}' of the cli replaces the built-in option of that name",
+        name
+    );
+}
+
+/// The commands found in a directory, along with the files describing the directory itself.
+#[derive(Default)]
+struct DirContents {
+    scripts: Vec<Box<dyn Command>>,
+    groups: Vec<Box<dyn Command>>,
+    imported_commands: Vec<Box<dyn Command>>,
+    cli_file: Option<PathBuf>,
+    group_file: Option<PathBuf>,
+}
+
+impl DirContents {
+    /// Combines the commands of the directory with the given ones, in a stable order. Of commands
+    /// of the same name only the first is kept, and the others are reported: scripts come first, in
+    /// the order of their files, then groups, then the given commands. Imported commands are
+    /// skipped silently if their names are taken.
+    fn into_commands(mut self, defined: Vec<Box<dyn Command>>) -> Vec<Box<dyn Command>> {
+        // The order of directory entries is not defined
+        self.scripts.sort_by(|a, b| a.get_path().cmp(&b.get_path()));
+
+        let mut commands = Vec::<Box<dyn Command>>::new();
+
+        let is_taken = |commands: &[Box<dyn Command>], name: &str| {
+            commands.iter().any(|command| command.name() == name)
+        };
+
+        self.scripts
+            .into_iter()
+            .chain(self.groups)
+            .chain(defined)
+            .for_each(|command| {
+                if is_taken(&commands, command.name()) {
+                    report_duplicate_command(command.as_ref());
+                } else {
+                    commands.push(command);
+                }
+            });
+
+        // Imported commands are skipped if their names are taken, by a script or by clap's help
+        self.imported_commands.into_iter().for_each(|command| {
+            if command.name() != "help" && !is_taken(&commands, command.name()) {
+                commands.push(command);
+            }
+        });
+
+        // Sorted for stable help and docs
+        commands.sort_by(|a, b| a.name().cmp(b.name()));
+
+        commands
+    }
+}
+
+/// Reports a command that is skipped, as another command has its name. The report goes to stderr,
+/// as stdout may be evaluated.
+fn report_duplicate_command(command: &dyn Command) {
+    let source = command
+        .get_path()
+        .map(|path| format!(" in {}", path.to_string_lossy()))
+        .unwrap_or_default();
+
+    eprintln!(
+        "The command '{}'{} is skipped, as there is another command of that name",
+        command.name(),
+        source
+    );
+}
+
+/// Reads the commands in the given directory: a command for each script, a group for each
+/// sub-directory, and the commands imported from the files of other tools.
+fn read_command_dir(dir: &Path) -> DirContents {
+    let mut contents = DirContents::default();
+
+    read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .for_each(|entry| {
+            let path = entry.path();
+
+            let Ok(file_type) = entry.file_type() else {
+                return;
+            };
+
+            if file_type.is_dir() {
+                if let Some(group) = build_group(&path) {
+                    contents.groups.push(Box::new(group));
+                }
+            } else if !file_type.is_file() {
+                // Neither a script nor a group
+            } else if is_cli_metadata_file(&path) {
+                contents.cli_file = Some(path);
+            } else if is_group_file(&path) {
+                contents.group_file = Some(path);
+            } else if let Some(imported) = import_commands(&path) {
+                // Files of other tools, such as Makefiles, are not scripts
+                contents.imported_commands.extend(
+                    imported
+                        .unwrap_or_else(|e| report_file_error(&path, e))
+                        .into_iter()
+                        .map(|command| Box::new(command) as Box<dyn Command>),
+                );
+            } else if let Some(command) =
+                build_script_command(path.clone()).unwrap_or_else(|e| report_file_error(&path, e))
+            {
+                contents.scripts.push(Box::new(command));
+            }
+        });
+
+    contents
+}
+
+/// Builds the group of commands in the given directory. Returns `None` for directories that are
+/// hidden, i.e. whose names start with `.` or `_`, and for directories without commands.
+pub(crate) fn build_group(dir: &Path) -> Option<GroupCommand> {
+    let hidden = dir
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .is_none_or(|name| name.starts_with('.') || name.starts_with('_'));
+
+    if hidden {
+        return None;
+    }
+
+    let contents = read_command_dir(dir);
+    let group_file = contents.group_file.clone();
+
+    let commands = contents.into_commands(vec![]);
+
+    if commands.is_empty() {
+        return None;
     }
+
+    build_group_command(dir, group_file.clone(), commands)
+        .map(Some)
+        .unwrap_or_else(|e| report_file_error(group_file.as_deref().unwrap_or(dir), e))
 }
 
 impl HasSubCommands for Model {
-    fn get_command(&self, name: &str) -> Option<&Box<dyn Command>> {
-        self.commands.iter().find(|command| command.name() == name)
+    fn get_command(&self, name: &str) -> Option<&dyn Command> {
+        self.commands
+            .iter()
+            .map(Box::as_ref)
+            .find(|command| command.name() == name)
     }
 }
 
+/// The type of the value of an argument, used e.g. for completion.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ArgType {
     Unknown,
@@ -90,6 +325,18 @@ impl From<&str> for ArgType {
     }
 }
 
+impl Display for ArgType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgType::Unknown => write!(f, "unknown"),
+            ArgType::Path => write!(f, "path"),
+            ArgType::File => write!(f, "file"),
+            ArgType::Dir => write!(f, "dir"),
+        }
+    }
+}
+
+/// A positional argument of a command.
 #[derive(Debug, Clone, PartialEq)]
 pub struct CommandArg {
     pub name: String,
@@ -97,6 +344,10 @@ pub struct CommandArg {
     pub var_arg: bool,
     pub arg_type: ArgType,
     pub description: Option<String>,
+    pub env: Option<EnvVar>,
+    pub value_name: Option<String>,
+    pub heading: Option<String>,
+    pub prompt: bool,
 }
 
 impl CommandArg {
@@ -117,16 +368,50 @@ impl CommandArg {
             var_arg,
             arg_type,
             description: description.map(Into::into),
+            env: None,
+            value_name: None,
+            heading: None,
+            prompt: false,
         }
     }
+
+    /// Sets whether the user is asked for the value, if it is required and missing from an
+    /// interactive command line.
+    pub fn with_prompt(mut self, prompt: bool) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Sets the name of the value shown in usage and help, instead of the name of the argument.
+    pub fn with_value_name(mut self, value_name: Option<String>) -> Self {
+        self.value_name = value_name;
+        self
+    }
+
+    /// Sets the heading under which the argument is listed in help.
+    pub fn with_heading(mut self, heading: Option<String>) -> Self {
+        self.heading = heading;
+        self
+    }
+
+    /// Sets the environment variable the value is taken from, if it is not given.
+    pub fn with_env(mut self, env: Option<EnvVar>) -> Self {
+        self.env = env;
+        self
+    }
 }
 
+/// An option of a command, with a long name and optionally a short one.
 #[derive(Debug, Clone, PartialEq)]
 pub struct CommandOption {
     pub name: String,
     pub short: Option<char>,
     pub has_param: bool,
+    pub kind: OptionKind,
     pub description: Option<String>,
+    pub env: Option<EnvVar>,
+    pub value_name: Option<String>,
+    pub heading: Option<String>,
 }
 
 impl CommandOption {
@@ -139,19 +424,225 @@ impl CommandOption {
             name: name.into(),
             short,
             has_param,
+            kind: OptionKind::Single,
             description: description.map(Into::into),
+            env: None,
+            value_name: None,
+            heading: None,
+        }
+    }
+
+    /// Sets the name of the parameter shown in usage and help, instead of the name of the option.
+    pub fn with_value_name(mut self, value_name: Option<String>) -> Self {
+        self.value_name = value_name;
+        self
+    }
+
+    /// Sets the heading under which the option is listed in help.
+    pub fn with_heading(mut self, heading: Option<String>) -> Self {
+        self.heading = heading;
+        self
+    }
+
+    pub fn with_kind(mut self, kind: OptionKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// The name of the flag turning off a negatable option, e.g. `no-color`.
+    pub fn negation(&self) -> String {
+        format!("{}{}", NEGATION_PREFIX, self.name)
+    }
+
+    /// Sets the environment variable the value is taken from, if it is not given.
+    pub fn with_env(mut self, env: Option<EnvVar>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// The name under which the value is set for scripts, in `cli_opts` or in an environment
+    /// variable, with any `-` replaced by `_`, e.g. `dry_run` for `dry-run`.
+    pub fn var_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
+}
+
+/// The prefix of the name of the flag turning off a negatable option.
+pub const NEGATION_PREFIX: &str = "no-";
+
+/// How often an option may be given, and so what its value is.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OptionKind {
+    /// A flag, or an option with a single value.
+    #[default]
+    Single,
+    /// A flag that may be repeated, whose value is the number of times it is given, e.g. `-vvv`.
+    Count,
+    /// An option with a parameter that may be repeated, whose value is the list of the values given.
+    Many,
+    /// A flag that can also be turned off, e.g. `--color` and `--no-color`. Its value is `true`,
+    /// `false` or empty, if neither is given.
+    Negatable,
+}
+
+impl Display for OptionKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionKind::Single => write!(f, "single"),
+            OptionKind::Count => write!(f, "count"),
+            OptionKind::Many => write!(f, "many"),
+            OptionKind::Negatable => write!(f, "negatable"),
+        }
+    }
+}
+
+/// An environment variable from which the value of an option or argument is taken, if it is not
+/// given on the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvVar {
+    pub name: String,
+    /// Whether the value is secret, e.g. a token, and so not shown in help.
+    pub secret: bool,
+}
+
+impl EnvVar {
+    pub fn new<S: Into<String>>(name: S, secret: bool) -> Self {
+        EnvVar {
+            name: name.into(),
+            secret,
+        }
+    }
+}
+
+/// A confirmation the user is asked for before a command is run, as the command is dangerous.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Confirmation {
+    /// The question asked, if not the default one.
+    pub message: Option<String>,
+}
+
+impl Confirmation {
+    /// The question asked if the confirmation has no message of its own.
+    pub const DEFAULT_MESSAGE: &'static str = "Are you sure?";
+
+    pub fn new(message: Option<String>) -> Self {
+        Confirmation { message }
+    }
+
+    /// The question asked.
+    pub fn text(&self) -> &str {
+        self.message.as_deref().unwrap_or(Self::DEFAULT_MESSAGE)
+    }
+}
+
+/// A relationship between the options and arguments of a command, checked when the command line
+/// is parsed, before the command is run. Options and arguments are referred to by name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgRelation {
+    /// At most one of the options and arguments may be given.
+    Conflicts(Vec<String>),
+    /// If the first option or argument is given, all the others must be given too.
+    Requires(String, Vec<String>),
+    /// A named group of options and arguments of which at most one may be given; if the group is
+    /// required, exactly one must be given.
+    Group {
+        name: String,
+        required: bool,
+        members: Vec<String>,
+    },
+}
+
+impl ArgRelation {
+    /// The names of the options and arguments the relation refers to.
+    pub fn names(&self) -> Vec<&str> {
+        match self {
+            ArgRelation::Conflicts(names) => names.iter().map(String::as_str).collect(),
+            ArgRelation::Requires(name, required) => std::iter::once(name)
+                .chain(required)
+                .map(String::as_str)
+                .collect(),
+            ArgRelation::Group { members, .. } => members.iter().map(String::as_str).collect(),
         }
     }
 }
 
-pub(crate) trait Command {
-    fn name(&self) -> &str;
+/// A command of the cli. Implementations provide everything needed to build the parser for the
+/// command, and to execute it.
+pub trait Command {
+    /// The description of the command, and what is asked for and run around it.
+    fn meta(&self) -> &CommandMeta;
+
+    /// The name by which the command is called.
+    fn name(&self) -> &str {
+        &self.meta().name
+    }
 
+    /// A short description of the command, shown in help.
     fn description(&self) -> Option<&str> {
+        self.meta().description.as_deref()
+    }
+
+    /// Further description of the command, shown in long help and man pages.
+    fn details(&self) -> Option<&str> {
+        self.meta().details.as_deref()
+    }
+
+    /// Examples of using the command, shown in long help and man pages.
+    fn examples(&self) -> &[String] {
+        &self.meta().examples
+    }
+
+    /// The line of the source file on which the command is defined, if it is not the whole file.
+    fn line(&self) -> Option<usize> {
+        self.meta().line
+    }
+
+    /// Further names by which the command can be called.
+    fn aliases(&self) -> &[String] {
+        &[]
+    }
+
+    /// Whether the command is hidden from help and completion.
+    fn hidden(&self) -> bool {
+        false
+    }
+
+    /// Whether the command can only be run through one of its sub-commands.
+    fn sub_command_required(&self) -> bool {
+        false
+    }
+
+    /// The command line the command expands to, if it is an alias for another command.
+    fn expansion(&self) -> Option<&[String]> {
+        None
+    }
+
+    /// Whether the command is passed its options and arguments as they would be written on the
+    /// command line, rather than only their values, when executed.
+    fn forwards_command_line(&self) -> bool {
+        false
+    }
+
+    /// The shell code that runs the command in evaluated mode, given the args it would be executed
+    /// with. If `None`, the command is run by sourcing its script.
+    fn eval_code(&self, _args: &[String]) -> Option<String> {
+        None
+    }
+
+    /// The process that runs the command directly, passing it the given args; `None` if the
+    /// command cannot be run on its own.
+    fn process(&self, _args: Vec<String>) -> Option<process::Command> {
         None
     }
 
-    fn exec(&self, args: Option<Vec<String>>);
+    /// Executes the command directly, passing it the given args.
+    fn exec(&self, args: Option<Vec<String>>) {
+        let command = self
+            .process(args.unwrap_or_default())
+            .expect("The command cannot be run on its own");
+
+        spawn_and_exit(command);
+    }
 
     fn sub_commands(&self) -> &Vec<Box<dyn Command>>;
 
@@ -161,6 +652,32 @@ pub(crate) trait Command {
 
     fn args(&self) -> &Vec<CommandArg>;
 
+    /// The relationships between the options and arguments of the command.
+    fn relations(&self) -> &[ArgRelation] {
+        &self.meta().relations
+    }
+
+    /// The confirmation asked for before the command, or any of its sub-commands, is run.
+    fn confirmation(&self) -> Option<&Confirmation> {
+        self.meta().confirmation.as_ref()
+    }
+
+    /// The hooks run before and after the command, or any of its sub-commands.
+    fn hooks(&self) -> &Hooks {
+        &self.meta().hooks
+    }
+
+    /// How long the command, or any of its sub-commands, may run in executed mode before it is
+    /// killed.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// How the command, or any of its sub-commands, is run again if it fails in executed mode.
+    fn retry(&self) -> Option<&Retry> {
+        None
+    }
+
     fn get_option(&self, name: &str) -> Option<&CommandOption> {
         self.options().iter().find(|option| option.name == name)
     }
@@ -168,18 +685,148 @@ pub(crate) trait Command {
     fn get_arg(&self, name: &str) -> Option<&CommandArg> {
         self.args().iter().find(|arg| arg.name == name)
     }
+
+    /// The file containing the command, if it has one of its own.
     fn get_path(&self) -> Option<&PathBuf>;
 }
 
+/// A script run before or after a command, or a function in one. The script is passed the
+/// function name, if any, followed by the args of the hook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hook {
+    pub path: PathBuf,
+    pub function: Option<String>,
+}
+
+impl Hook {
+    pub fn new(path: PathBuf, function: Option<String>) -> Self {
+        Hook { path, function }
+    }
+
+    /// The program and the args the hook is run with, before the args of the hook.
+    pub fn words(&self) -> Vec<String> {
+        std::iter::once(self.path.to_string_lossy().to_string())
+            .chain(self.function.iter().cloned())
+            .collect()
+    }
+
+    /// The process running the hook with the given args.
+    pub fn process(&self, args: &[String]) -> process::Command {
+        let mut command = process::Command::new(&self.path);
+
+        command.args(self.function.iter()).args(args);
+
+        command
+    }
+}
+
+/// The hooks run before and after a command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hooks {
+    pub pre: Option<Hook>,
+    pub post: Option<Hook>,
+}
+
+impl Hooks {
+    pub fn new(pre: Option<Hook>, post: Option<Hook>) -> Self {
+        Hooks { pre, post }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_none() && self.post.is_none()
+    }
+}
+
+/// How often a failing command is run again, and how long to wait before the first retry; the wait
+/// doubles with each further retry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retry {
+    pub count: u32,
+    pub backoff: Duration,
+}
+
+impl Retry {
+    /// The wait before the first retry, unless another is given.
+    pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+    pub fn new(count: u32, backoff: Option<Duration>) -> Self {
+        Retry {
+            count,
+            backoff: backoff.unwrap_or(Self::DEFAULT_BACKOFF),
+        }
+    }
+}
+
+/// What every kind of command has, apart from how it is run: its name and description, where it is
+/// defined, and what is checked, asked for and run around it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandMeta {
+    pub name: String,
+    /// The line of the source file on which the command is defined, if it is not the whole file.
+    pub line: Option<usize>,
+    pub description: Option<String>,
+    pub details: Option<String>,
+    pub examples: Vec<String>,
+    pub relations: Vec<ArgRelation>,
+    pub confirmation: Option<Confirmation>,
+    pub hooks: Hooks,
+}
+
+impl CommandMeta {
+    pub fn new<S: Into<String>, T: Into<String>>(name: S, description: Option<T>) -> Self {
+        CommandMeta {
+            name: name.into(),
+            description: description.map(Into::into),
+            ..CommandMeta::default()
+        }
+    }
+}
+
+/// Sets the [CommandMeta] of a command while it is built.
+pub trait WithMeta: Sized {
+    fn meta_mut(&mut self) -> &mut CommandMeta;
+
+    fn with_line(mut self, line: Option<usize>) -> Self {
+        self.meta_mut().line = line;
+        self
+    }
+
+    fn with_details(mut self, details: Option<String>) -> Self {
+        self.meta_mut().details = details;
+        self
+    }
+
+    fn with_examples(mut self, examples: Vec<String>) -> Self {
+        self.meta_mut().examples = examples;
+        self
+    }
+
+    fn with_relations(mut self, relations: Vec<ArgRelation>) -> Self {
+        self.meta_mut().relations = relations;
+        self
+    }
+
+    fn with_confirmation(mut self, confirmation: Option<Confirmation>) -> Self {
+        self.meta_mut().confirmation = confirmation;
+        self
+    }
+
+    fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.meta_mut().hooks = hooks;
+        self
+    }
+}
+
 /// A command that is located in a script file. The command may have sub-commands that are functions
 /// in the script file.
 pub struct ScriptCommand {
-    pub name: String,
-    pub description: Option<String>,
+    meta: CommandMeta,
     sub_commands: Vec<Box<dyn Command>>,
     path: PathBuf,
     options: Vec<CommandOption>,
     args: Vec<CommandArg>,
+    timeout: Option<Duration>,
+    retry: Option<Retry>,
 }
 
 impl ScriptCommand {
@@ -192,58 +839,67 @@ impl ScriptCommand {
         sub_commands: Vec<Box<dyn Command>>,
     ) -> ScriptCommand {
         ScriptCommand {
-            name,
-            description,
+            meta: CommandMeta::new(name, description),
             path,
             options,
             args,
+            timeout: None,
+            retry: None,
             sub_commands,
         }
     }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> ScriptCommand {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: Option<Retry>) -> ScriptCommand {
+        self.retry = retry;
+        self
+    }
 }
 
-impl<T> HasSubCommands for T
-where
-    T: AsRef<dyn Command>,
-{
-    fn get_command(&self, name: &str) -> Option<&Box<dyn Command>> {
-        let command: &dyn Command = self.as_ref();
-        command
-            .sub_commands()
+impl HasSubCommands for dyn Command + '_ {
+    fn get_command(&self, name: &str) -> Option<&dyn Command> {
+        self.sub_commands()
             .iter()
+            .map(Box::as_ref)
             .find(|command| command.name() == name)
     }
 }
 
-impl Command for ScriptCommand {
-    fn name(&self) -> &str {
-        self.name.as_str()
+impl WithMeta for ScriptCommand {
+    fn meta_mut(&mut self) -> &mut CommandMeta {
+        &mut self.meta
     }
+}
 
-    fn description(&self) -> Option<&str> {
-        self.description.as_deref()
+impl Command for ScriptCommand {
+    fn meta(&self) -> &CommandMeta {
+        &self.meta
     }
 
-    fn exec(&self, args: Option<Vec<String>>) {
+    fn process(&self, args: Vec<String>) -> Option<process::Command> {
         let mut command = process::Command::new(self.path.to_str().unwrap());
 
-        args.iter().flat_map(|args| args.iter()).for_each(|arg| {
-            command.arg(arg);
-        });
+        command.args(args);
 
-        let output = command.spawn();
+        Some(command)
+    }
 
-        match output {
-            Ok(mut child) => {
-                child.wait().unwrap();
-                exit(0);
-            }
-            Err(e) => {
-                eprintln!("{}", "Error in executing command : ");
-                eprintln!("{}", e);
-                exit(1);
-            }
-        }
+    /// Executes the script, within its timeout and retrying it if it fails, and exits with its
+    /// exit code.
+    fn exec(&self, args: Option<Vec<String>>) {
+        let code = run_supervised(
+            self,
+            args.unwrap_or_default(),
+            &RootOptionEnv::default(),
+            false,
+        )
+        .expect("A script can always be run");
+
+        exit(code);
     }
 
     fn sub_commands(&self) -> &Vec<Box<dyn Command>> {
@@ -260,14 +916,23 @@ impl Command for ScriptCommand {
     fn args(&self) -> &Vec<CommandArg> {
         &self.args
     }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn retry(&self) -> Option<&Retry> {
+        self.retry.as_ref()
+    }
+
     fn get_path(&self) -> Option<&PathBuf> {
         Some(&self.path)
     }
 }
 
+/// A command that is a function in the script file of its parent command.
 pub struct EmbeddedCommand {
-    name: String,
-    description: Option<String>,
+    meta: CommandMeta,
     options: Vec<CommandOption>,
     args: Vec<CommandArg>,
     sub_commands: Vec<Box<dyn Command>>,
@@ -285,26 +950,33 @@ impl EmbeddedCommand {
         T: Into<String>,
     {
         EmbeddedCommand {
-            name: name.into(),
-            description: description.map(Into::into),
+            meta: CommandMeta::new(name, description),
             options,
             args,
             sub_commands: vec![],
         }
     }
+
+    pub fn with_sub_commands(mut self, sub_commands: Vec<Box<dyn Command>>) -> EmbeddedCommand {
+        self.sub_commands = sub_commands;
+        self
+    }
 }
 
-impl Command for EmbeddedCommand {
-    fn name(&self) -> &str {
-        self.name.as_str()
+impl WithMeta for EmbeddedCommand {
+    fn meta_mut(&mut self) -> &mut CommandMeta {
+        &mut self.meta
     }
+}
 
-    fn description(&self) -> Option<&str> {
-        self.description.as_deref()
+impl Command for EmbeddedCommand {
+    fn meta(&self) -> &CommandMeta {
+        &self.meta
     }
-    fn exec(&self, _args: Option<Vec<String>>) {
-        // The handling of sub-command execution is currently handled by the script
-        unimplemented!()
+
+    fn sub_command_required(&self) -> bool {
+        // Only the functions of the leaves of the tree are run
+        self.has_sub_commands()
     }
 
     fn sub_commands(&self) -> &Vec<Box<dyn Command>> {
@@ -328,25 +1000,434 @@ impl Command for EmbeddedCommand {
     }
 }
 
-#[cfg(test)]
-pub(crate) mod test {
-    use std::fs::File;
-    use std::io::Write;
-
-    pub const NO_DESCRIPTION: Option<String> = None;
+/// A command that is an alias for another command line, e.g. `deploy --env prod`. Any args given to
+/// the alias are appended to the command line it expands to.
+pub struct AliasCommand {
+    meta: CommandMeta,
+    expansion: Vec<String>,
+    options: Vec<CommandOption>,
+    args: Vec<CommandArg>,
+    sub_commands: Vec<Box<dyn Command>>,
+}
 
-    #[test]
-    fn build_model_lists_scripts() {
-        let test_dir = tempfile::tempdir().unwrap();
+impl AliasCommand {
+    pub fn new<S, T>(name: S, expansion: Vec<String>, description: Option<T>) -> AliasCommand
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        AliasCommand {
+            meta: CommandMeta::new(name, description),
+            expansion,
+            options: vec![],
+            args: vec![CommandArg::new(
+                "args",
+                true,
+                true,
+                ArgType::Unknown,
+                Some("Any arguments are appended to the expansion"),
+            )],
+            sub_commands: vec![],
+        }
+    }
+
+    fn unexpandable_message(&self) -> String {
+        format!("The alias '{}' expands to itself", self.meta.name)
+    }
+}
+
+impl WithMeta for AliasCommand {
+    fn meta_mut(&mut self) -> &mut CommandMeta {
+        &mut self.meta
+    }
+}
+
+impl Command for AliasCommand {
+    fn meta(&self) -> &CommandMeta {
+        &self.meta
+    }
+
+    fn expansion(&self) -> Option<&[String]> {
+        Some(&self.expansion)
+    }
+
+    fn eval_code(&self, _args: &[String]) -> Option<String> {
+        // Aliases are expanded before the command line is matched, so an alias is only selected if
+        // its expansion is cyclic
+        Some(format!(
+            "echo {}",
+            shell_quote(&self.unexpandable_message())
+        ))
+    }
+
+    fn exec(&self, _args: Option<Vec<String>>) {
+        eprintln!("{}", self.unexpandable_message());
+        exit(1);
+    }
+
+    fn sub_commands(&self) -> &Vec<Box<dyn Command>> {
+        &self.sub_commands
+    }
+
+    fn has_sub_commands(&self) -> bool {
+        false
+    }
+
+    fn options(&self) -> &Vec<CommandOption> {
+        &self.options
+    }
+
+    fn args(&self) -> &Vec<CommandArg> {
+        &self.args
+    }
+
+    fn get_path(&self) -> Option<&PathBuf> {
+        None
+    }
+}
+
+/// The name of the function the code of an inline command is run in by the calling shell.
+const INLINE_FUNCTION: &str = "cli_inline";
+
+/// A command that is a line of shell code, rather than a script file. In evaluated mode the code is
+/// run by the calling shell, in a function whose positional parameters are the values of the
+/// arguments; in executed mode it is run by `sh`.
+pub struct InlineCommand {
+    meta: CommandMeta,
+    code: String,
+    options: Vec<CommandOption>,
+    args: Vec<CommandArg>,
+    sub_commands: Vec<Box<dyn Command>>,
+}
+
+impl InlineCommand {
+    pub fn new<S, C, T>(
+        name: S,
+        code: C,
+        description: Option<T>,
+        options: Vec<CommandOption>,
+        args: Vec<CommandArg>,
+    ) -> InlineCommand
+    where
+        S: Into<String>,
+        C: Into<String>,
+        T: Into<String>,
+    {
+        InlineCommand {
+            meta: CommandMeta::new(name, description),
+            code: code.into(),
+            options,
+            args,
+            sub_commands: vec![],
+        }
+    }
+}
+
+impl WithMeta for InlineCommand {
+    fn meta_mut(&mut self) -> &mut CommandMeta {
+        &mut self.meta
+    }
+}
+
+impl Command for InlineCommand {
+    fn meta(&self) -> &CommandMeta {
+        &self.meta
+    }
+
+    fn eval_code(&self, args: &[String]) -> Option<String> {
+        // A function keeps the positional parameters of the calling shell; it is unset once it has
+        // run, keeping its status, which is expanded first
+        Some(format!(
+            "{0}() {{\n{1}\n}}\n{0}{2}\neval \"unset -f {0}; (exit $?)\"",
+            INLINE_FUNCTION,
+            self.code,
+            quoted_words(args.iter().map(String::as_str))
+        ))
+    }
+
+    fn process(&self, args: Vec<String>) -> Option<process::Command> {
+        let mut command = process::Command::new("sh");
+
+        // The name of the command becomes $0 of the shell
+        command
+            .arg("-c")
+            .arg(&self.code)
+            .arg(&self.meta.name)
+            .args(args);
+
+        Some(command)
+    }
+
+    fn sub_commands(&self) -> &Vec<Box<dyn Command>> {
+        &self.sub_commands
+    }
+
+    fn has_sub_commands(&self) -> bool {
+        false
+    }
+
+    fn options(&self) -> &Vec<CommandOption> {
+        &self.options
+    }
+
+    fn args(&self) -> &Vec<CommandArg> {
+        &self.args
+    }
+
+    fn get_path(&self) -> Option<&PathBuf> {
+        None
+    }
+}
+
+/// A command that wraps an executable, e.g. one on `$PATH`. The options and arguments are passed to
+/// the executable as they were given on the command line, after any fixed arguments.
+pub struct BinaryCommand {
+    meta: CommandMeta,
+    program: String,
+    program_args: Vec<String>,
+    path: Option<PathBuf>,
+    options: Vec<CommandOption>,
+    args: Vec<CommandArg>,
+    sub_commands: Vec<Box<dyn Command>>,
+}
+
+impl BinaryCommand {
+    pub fn new<S, P, T>(
+        name: S,
+        program: P,
+        program_args: Vec<String>,
+        description: Option<T>,
+        options: Vec<CommandOption>,
+        args: Vec<CommandArg>,
+    ) -> BinaryCommand
+    where
+        S: Into<String>,
+        P: Into<String>,
+        T: Into<String>,
+    {
+        BinaryCommand {
+            meta: CommandMeta::new(name, description),
+            program: program.into(),
+            program_args,
+            path: None,
+            options,
+            args,
+            sub_commands: vec![],
+        }
+    }
+
+    /// Sets the file in which the command is defined, e.g. a Makefile. The file is not run.
+    pub fn with_path(mut self, path: Option<PathBuf>) -> BinaryCommand {
+        self.path = path;
+        self
+    }
+}
+
+impl WithMeta for BinaryCommand {
+    fn meta_mut(&mut self) -> &mut CommandMeta {
+        &mut self.meta
+    }
+}
+
+impl Command for BinaryCommand {
+    fn meta(&self) -> &CommandMeta {
+        &self.meta
+    }
+
+    fn forwards_command_line(&self) -> bool {
+        true
+    }
+
+    fn eval_code(&self, args: &[String]) -> Option<String> {
+        // Use the command builtin, in case the cli is a shell function of the same name
+        Some(format!(
+            "command {}{}",
+            shell_quote(&self.program),
+            quoted_words(self.program_args.iter().chain(args).map(String::as_str))
+        ))
+    }
+
+    fn process(&self, args: Vec<String>) -> Option<process::Command> {
+        let mut command = process::Command::new(&self.program);
+
+        command.args(&self.program_args).args(args);
+
+        Some(command)
+    }
+
+    fn sub_commands(&self) -> &Vec<Box<dyn Command>> {
+        &self.sub_commands
+    }
+
+    fn has_sub_commands(&self) -> bool {
+        false
+    }
+
+    fn options(&self) -> &Vec<CommandOption> {
+        &self.options
+    }
+
+    fn args(&self) -> &Vec<CommandArg> {
+        &self.args
+    }
+
+    fn get_path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+}
+
+/// A group of commands, read from a sub-directory of the source directory. The options of the group
+/// are global, so they can be given to, and are passed to, every command in the group.
+pub struct GroupCommand {
+    meta: CommandMeta,
+    path: Option<PathBuf>,
+    aliases: Vec<String>,
+    hidden: bool,
+    options: Vec<CommandOption>,
+    args: Vec<CommandArg>,
+    sub_commands: Vec<Box<dyn Command>>,
+}
+
+impl GroupCommand {
+    pub fn new<S, T>(
+        name: S,
+        description: Option<T>,
+        options: Vec<CommandOption>,
+        sub_commands: Vec<Box<dyn Command>>,
+    ) -> GroupCommand
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        GroupCommand {
+            meta: CommandMeta::new(name, description),
+            path: None,
+            aliases: vec![],
+            hidden: false,
+            options,
+            args: vec![],
+            sub_commands,
+        }
+    }
+
+    /// Sets the file describing the group.
+    pub fn with_path(mut self, path: Option<PathBuf>) -> GroupCommand {
+        self.path = path;
+        self
+    }
+
+    pub fn with_aliases(mut self, aliases: Vec<String>) -> GroupCommand {
+        self.aliases = aliases;
+        self
+    }
+
+    pub fn with_hidden(mut self, hidden: bool) -> GroupCommand {
+        self.hidden = hidden;
+        self
+    }
+}
+
+impl WithMeta for GroupCommand {
+    fn meta_mut(&mut self) -> &mut CommandMeta {
+        &mut self.meta
+    }
+}
+
+impl Command for GroupCommand {
+    fn meta(&self) -> &CommandMeta {
+        &self.meta
+    }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    fn sub_command_required(&self) -> bool {
+        true
+    }
+
+    fn process(&self, mut args: Vec<String>) -> Option<process::Command> {
+        // The first arg is the name of the selected command, which is passed the rest
+        let name = args.remove(0);
+
+        self.sub_commands
+            .iter()
+            .find(|command| command.name() == name)
+            .expect("The selected command is in the group")
+            .process(args)
+    }
+
+    fn sub_commands(&self) -> &Vec<Box<dyn Command>> {
+        &self.sub_commands
+    }
+
+    fn has_sub_commands(&self) -> bool {
+        !self.sub_commands.is_empty()
+    }
+
+    fn options(&self) -> &Vec<CommandOption> {
+        &self.options
+    }
+
+    fn args(&self) -> &Vec<CommandArg> {
+        &self.args
+    }
+
+    fn get_path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+}
+
+/// Quotes each of the words, prefixing each with a space.
+fn quoted_words<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    words
+        .map(|word| format!(" {}", shell_quote(word)))
+        .collect()
+}
+
+/// Runs the command to completion and exits with its exit code.
+fn spawn_and_exit(command: process::Command) {
+    exit(run_to_completion(command));
+}
+
+/// Runs the command to completion, and returns its exit code, or that of the shell if it was
+/// killed by a signal.
+fn run_to_completion(mut command: process::Command) -> i32 {
+    match command.spawn().and_then(|mut child| child.wait()) {
+        Ok(status) => exit_code(status),
+        Err(e) => {
+            eprintln!("Error in executing command : ");
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::fs::File;
+    use std::io::Write;
+
+    use crate::transform::ToCliCommand;
+
+    pub const NO_DESCRIPTION: Option<String> = None;
+
+    #[test]
+    fn build_model_lists_scripts() {
+        let test_dir = tempfile::tempdir().unwrap();
 
         let script1_path = test_dir.path().join("script1.sh");
 
         File::create(&script1_path)
-            .expect(format!("Unable to create file {}", script1_path.to_str().unwrap()).as_str());
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
 
         let script2_path = test_dir.path().join("script2.sh");
         File::create(&script2_path)
-            .expect(format!("Unable to create file {}", script2_path.to_str().unwrap()).as_str());
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script2_path.to_str().unwrap()));
 
         let model = super::Model::from(test_dir.path());
 
@@ -370,17 +1451,16 @@ pub(crate) mod test {
         let script1_path = test_dir.path().join("script1.sh");
 
         File::create(&script1_path)
-            .expect(format!("Unable to create file {}", script1_path.to_str().unwrap()).as_str());
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
 
         let subdir_path = test_dir.path().join("subdir");
         // Create a directory 'subdir'
-        std::fs::create_dir(&subdir_path).expect(
-            format!(
+        std::fs::create_dir(&subdir_path).unwrap_or_else(|_| {
+            panic!(
                 "Unable to create directory {}",
                 subdir_path.to_str().unwrap()
             )
-            .as_str(),
-        );
+        });
 
         let model = super::Model::from(test_dir.path());
 
@@ -396,8 +1476,10 @@ pub(crate) mod test {
 
         File::create(&script1_path)
             .unwrap()
-            .write("# @sub sub1\nfunction sub1(){}\n# @sub sub2\nfunction sub2(){}\n".as_bytes())
-            .expect(format!("Unable to create file {}", script1_path.to_str().unwrap()).as_str());
+            .write_all(
+                "# @sub sub1\nfunction sub1(){}\n# @sub sub2\nfunction sub2(){}\n".as_bytes(),
+            )
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
 
         let model = super::Model::from(test_dir.path());
 
@@ -406,7 +1488,7 @@ pub(crate) mod test {
 
         let mut names: Vec<String> = model.commands[0]
             .sub_commands()
-            .into_iter()
+            .iter()
             .map(|command| command.name().to_owned())
             .collect();
 
@@ -423,17 +1505,17 @@ pub(crate) mod test {
 
         File::create(&script1_path)
             .unwrap()
-            .write(
+            .write_all(
                 "# @sub sub1\nfunction sub1(){}\n# @sub sub2 script2.sh\nfunction sub2(){}\n"
                     .as_bytes(),
             )
-            .expect(format!("Unable to create file {}", script1_path.to_str().unwrap()).as_str());
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
 
         let script2_path = test_dir.path().join("script2.sh");
         File::create(&script2_path)
-            .expect(format!("Unable to create file {}", script2_path.to_str().unwrap()).as_str())
-            .write("# @ignore-at-root\n".as_bytes())
-            .expect(format!("Unable to write file {}", script2_path.to_str().unwrap()).as_str());
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script2_path.to_str().unwrap()))
+            .write_all("# @ignore-at-root\n".as_bytes())
+            .unwrap_or_else(|_| panic!("Unable to write file {}", script2_path.to_str().unwrap()));
 
         let model = super::Model::from(test_dir.path());
 
@@ -442,7 +1524,7 @@ pub(crate) mod test {
 
         let mut names: Vec<String> = model.commands[0]
             .sub_commands()
-            .into_iter()
+            .iter()
             .map(|command| command.name().to_owned())
             .collect();
 
@@ -451,6 +1533,153 @@ pub(crate) mod test {
         assert_eq!(names.join(","), "sub1,sub2");
     }
 
+    #[test]
+    fn build_model_reads_cli_metadata() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("script1.sh");
+
+        File::create(&script1_path)
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        let metadata_path = test_dir.path().join("_cli.sh");
+        File::create(&metadata_path)
+            .unwrap()
+            .write_all("# @version 2.1.0\n# @about The team cli\n".as_bytes())
+            .unwrap_or_else(|_| {
+                panic!("Unable to create file {}", metadata_path.to_str().unwrap())
+            });
+
+        let model = super::Model::from(test_dir.path());
+
+        // The metadata file is not a command
+        assert_eq!(model.commands.len(), 1);
+        assert_eq!(model.commands[0].name(), "script1");
+
+        assert_eq!(model.metadata.version, Some("2.1.0".to_string()));
+        assert_eq!(model.metadata.description, Some("The team cli".to_string()));
+    }
+
+    #[test]
+    fn build_model_includes_config_commands() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("script1.sh");
+
+        File::create(&script1_path)
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        let metadata_path = test_dir.path().join("_cli.sh");
+        File::create(&metadata_path)
+            .unwrap()
+            .write_all("# @command again alias script1 --again\n".as_bytes())
+            .unwrap_or_else(|_| {
+                panic!("Unable to create file {}", metadata_path.to_str().unwrap())
+            });
+
+        let model = super::Model::from(test_dir.path());
+
+        assert_eq!(model.commands.len(), 2);
+        assert_eq!(model.commands[0].name(), "again");
+        assert!(model.commands[0].expansion().is_some());
+        assert_eq!(model.commands[1].name(), "script1");
+    }
+
+    #[test]
+    fn build_model_imports_makefile_targets() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script1_path = test_dir.path().join("build.sh");
+
+        File::create(&script1_path)
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        let makefile_path = test_dir.path().join("Makefile");
+        File::create(&makefile_path)
+            .unwrap()
+            .write_all("build:\n\tcc main.c\n\ntest: ## Runs the tests\n\t./test\n".as_bytes())
+            .unwrap_or_else(|_| {
+                panic!("Unable to create file {}", makefile_path.to_str().unwrap())
+            });
+
+        let model = super::Model::from(test_dir.path());
+
+        // The script takes precedence over the target of the same name
+        assert_eq!(model.commands.len(), 2);
+        assert_eq!(model.commands[0].name(), "build");
+        assert!(!model.commands[0].forwards_command_line());
+
+        assert_eq!(model.commands[1].name(), "test");
+        assert_eq!(model.commands[1].description(), Some("Runs the tests"));
+        assert_eq!(model.commands[1].get_path(), Some(&makefile_path));
+    }
+
+    #[test]
+    fn build_model_includes_groups() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let group_dir = test_dir.path().join("deploy");
+        std::fs::create_dir(&group_dir).unwrap();
+
+        // Directories starting with . or _ are not groups
+        std::fs::create_dir(test_dir.path().join(".git")).unwrap();
+        File::create(test_dir.path().join(".git").join("config")).unwrap();
+
+        let script1_path = group_dir.join("app.sh");
+        File::create(&script1_path)
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script1_path.to_str().unwrap()));
+
+        let group_path = group_dir.join("_group.sh");
+        File::create(&group_path)
+            .unwrap()
+            .write_all("# @about Deploys things\n# @opt stage true\n".as_bytes())
+            .unwrap_or_else(|_| panic!("Unable to create file {}", group_path.to_str().unwrap()));
+
+        let model = super::Model::from(test_dir.path());
+
+        assert_eq!(model.commands.len(), 1);
+
+        let group = &model.commands[0];
+        assert_eq!(group.name(), "deploy");
+        assert_eq!(group.description(), Some("Deploys things"));
+        assert!(group.sub_command_required());
+
+        // The group file is not a command
+        assert_eq!(group.sub_commands().len(), 1);
+        assert_eq!(group.sub_commands()[0].name(), "app");
+    }
+
+    #[test]
+    fn build_model_skips_commands_with_taken_names() {
+        let test_dir = tempfile::tempdir().unwrap();
+
+        let script_path = test_dir.path().join("deploy.sh");
+        File::create(&script_path)
+            .unwrap_or_else(|_| panic!("Unable to create file {}", script_path.to_str().unwrap()));
+
+        let group_dir = test_dir.path().join("deploy");
+        std::fs::create_dir(&group_dir).unwrap();
+        File::create(group_dir.join("app.sh")).unwrap();
+
+        let metadata_path = test_dir.path().join("_cli.sh");
+        File::create(&metadata_path)
+            .unwrap()
+            .write_all("# @command deploy alias other\n# @command other alias deploy\n".as_bytes())
+            .unwrap_or_else(|_| {
+                panic!("Unable to create file {}", metadata_path.to_str().unwrap())
+            });
+
+        let model = super::Model::from(test_dir.path());
+
+        // The script takes precedence over the group and the config command of the same name
+        assert_eq!(model.commands.len(), 2);
+        assert_eq!(model.commands[0].name(), "deploy");
+        assert_eq!(model.commands[0].get_path(), Some(&script_path));
+        assert_eq!(model.commands[1].name(), "other");
+
+        model.to_cli().debug_assert();
+    }
+
     #[test]
     fn arg_type_from_str() {
         assert_eq!(super::ArgType::from("path"), super::ArgType::Path);
@@ -464,4 +1693,38 @@ pub(crate) mod test {
         assert_eq!(super::ArgType::from("foo"), super::ArgType::Unknown);
         assert_eq!(super::ArgType::from("bar"), super::ArgType::Unknown);
     }
+
+    #[test]
+    fn arg_type_display_round_trips() {
+        for arg_type in [
+            super::ArgType::Unknown,
+            super::ArgType::Path,
+            super::ArgType::File,
+            super::ArgType::Dir,
+        ] {
+            assert_eq!(
+                super::ArgType::from(arg_type.to_string().as_str()),
+                arg_type
+            );
+        }
+    }
+
+    #[test]
+    fn run_to_completion_returns_the_exit_code() {
+        let shell = |script: &str| {
+            let mut command = std::process::Command::new("sh");
+            command.args(["-c", script]);
+            command
+        };
+
+        assert_eq!(super::run_to_completion(shell("exit 3")), 3);
+
+        // A process killed by a signal exits as the shell reports it
+        assert_eq!(super::run_to_completion(shell("kill -TERM $$")), 143);
+
+        assert_eq!(
+            super::run_to_completion(std::process::Command::new("/nonexistent/command")),
+            1
+        );
+    }
 }
@@ -0,0 +1,693 @@
+//! Interactive prompting for the command to run and the required arguments missing from a command
+//! line, before it is matched for execution, and for the confirmation of dangerous commands before
+//! they are run.
+use std::fs::read_dir;
+use std::io::{stderr, stdin, IsTerminal};
+use std::path::Path;
+
+use clap::ArgMatches;
+use dialoguer::{Completion, Confirm, FuzzySelect, Input, Password};
+use log::debug;
+
+use crate::model::{ArgType, Command, CommandArg, Confirmation, HasSubCommands, Model};
+
+/// The name of the global flag asking for all missing required arguments.
+pub const INTERACTIVE_OPTION: &str = "interactive";
+
+/// The name of the global flag confirming commands without asking.
+pub const YES_OPTION: &str = "yes";
+
+/// The name of the global flag choosing the command to run from a list.
+pub const PICK_OPTION: &str = "pick";
+
+/// Asks the user for the value of an argument, or to choose a command.
+pub trait Prompter {
+    /// Returns the value entered for the argument, or `None` if the user gave up.
+    fn prompt(&mut self, arg: &CommandArg) -> Option<String>;
+
+    /// Returns the index of the item chosen, or `None` if the user gave up.
+    fn pick(&mut self, items: &[String]) -> Option<usize>;
+}
+
+/// Lets the user choose the command to run from a list of every command and sub-command, if the
+/// command line includes `--pick` or is empty. With `--pick`, the command is chosen from the
+/// sub-commands of the command the command line selects, if any. The names of the chosen command
+/// are inserted into the command line, along with `--interactive`, so that its missing arguments
+/// are asked for. Does nothing unless stdin and stderr are terminals.
+pub fn pick_command(model: &Model, cli: &clap::Command, cli_args: Vec<String>) -> Vec<String> {
+    if !stdin().is_terminal() || !stderr().is_terminal() {
+        return cli_args;
+    }
+
+    choose_command(model, cli, cli_args, &mut TerminalPrompter)
+}
+
+/// Completes the command line with the command chosen from the items given to the prompter, as
+/// described for [pick_command].
+pub fn choose_command(
+    model: &Model,
+    cli: &clap::Command,
+    mut cli_args: Vec<String>,
+    prompter: &mut dyn Prompter,
+) -> Vec<String> {
+    // Help and version requests are errors, even when ignoring errors
+    let Ok(matches) = cli
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(cli_args.iter())
+    else {
+        return cli_args;
+    };
+
+    let is_defined = |name: &str| {
+        model
+            .metadata
+            .options
+            .iter()
+            .any(|option| option.name == name)
+    };
+
+    // Unless the cli defines an option of the same name itself
+    let pick = !is_defined(PICK_OPTION) && flag(&matches, PICK_OPTION);
+
+    if !pick && cli_args.len() != 1 {
+        return cli_args;
+    }
+
+    // The command selected so far, whose sub-commands are chosen from
+    let mut selected: Option<&dyn Command> = None;
+    let mut current = &matches;
+
+    while let Some((name, sub_matches)) = current.subcommand() {
+        selected = match selected {
+            None => model.get_command(name),
+            Some(command) => command.get_command(name),
+        };
+        current = sub_matches;
+    }
+
+    let sub_commands = selected.map_or(&model.commands, |command| command.sub_commands());
+
+    let mut commands = Vec::<(Vec<&str>, &dyn Command)>::new();
+    add_runnable_commands(sub_commands, &[], &mut commands);
+
+    let width = commands
+        .iter()
+        .map(|(names, _)| names.join(" ").len())
+        .max()
+        .unwrap_or(0);
+
+    let items: Vec<String> = commands
+        .iter()
+        .map(|(names, command)| {
+            format!(
+                "{:width$}  {}",
+                names.join(" "),
+                command.description().unwrap_or_default()
+            )
+            .trim_end()
+            .to_owned()
+        })
+        .collect();
+
+    let Some((names, _)) = prompter.pick(&items).and_then(|index| commands.get(index)) else {
+        return cli_args;
+    };
+
+    debug!("picked {}", names.join(" "));
+
+    // The names of a sub-command follow those of the commands already given
+    let position = if selected.is_some() {
+        cli_args.len()
+    } else {
+        1
+    };
+
+    cli_args.splice(
+        position..position,
+        names.iter().map(|name| name.to_string()),
+    );
+
+    if !is_defined(INTERACTIVE_OPTION) {
+        cli_args.push(format!("--{}", INTERACTIVE_OPTION));
+    }
+
+    cli_args
+}
+
+/// Adds the commands that can be run, along with the names leading to them, to the given list. The
+/// hidden commands are left out.
+fn add_runnable_commands<'a>(
+    sub_commands: &'a [Box<dyn Command>],
+    parent: &[&'a str],
+    commands: &mut Vec<(Vec<&'a str>, &'a dyn Command)>,
+) {
+    sub_commands
+        .iter()
+        .filter(|command| !command.hidden())
+        .for_each(|command| {
+            let mut names = parent.to_vec();
+            names.push(command.name());
+
+            if !command.sub_command_required() {
+                commands.push((names.clone(), command.as_ref()));
+            }
+
+            add_runnable_commands(command.sub_commands(), &names, commands);
+        });
+}
+
+/// Asks for the value of the missing required arguments of the command selected by the command
+/// line, and appends them to it. Only the arguments marked with `@prompt` are asked for, unless the
+/// command line includes `--interactive`. Does nothing unless stdin and stderr are terminals.
+pub fn prompt_missing_args(
+    model: &Model,
+    cli: &clap::Command,
+    cli_args: Vec<String>,
+) -> Vec<String> {
+    if !stdin().is_terminal() || !stderr().is_terminal() {
+        return cli_args;
+    }
+
+    complete_args(model, cli, cli_args, &mut TerminalPrompter)
+}
+
+/// Completes the command line with the values given by the prompter for the missing required
+/// arguments, as described for [prompt_missing_args].
+pub fn complete_args(
+    model: &Model,
+    cli: &clap::Command,
+    mut cli_args: Vec<String>,
+    prompter: &mut dyn Prompter,
+) -> Vec<String> {
+    // Match without validation, so that the missing arguments are not an error
+    let Ok(matches) = cli
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(cli_args.iter())
+    else {
+        return cli_args;
+    };
+
+    let Some((command, matches)) = selected_command(model, &matches) else {
+        return cli_args;
+    };
+
+    let interactive = flag(matches, INTERACTIVE_OPTION);
+
+    // The values are appended, so only missing args up to the first that is not asked for can be
+    // completed
+    let missing = command
+        .args()
+        .iter()
+        .filter(|arg| !arg.optional && matches.get_raw(&arg.name).is_none())
+        .take_while(|arg| interactive || arg.prompt);
+
+    let mut values = Vec::<String>::new();
+
+    for arg in missing {
+        debug!("prompting for {}", arg.name);
+
+        let Some(value) = prompter.prompt(arg) else {
+            break;
+        };
+
+        if arg.var_arg {
+            values.extend(value.split_whitespace().map(str::to_owned));
+        } else {
+            values.push(value);
+        }
+    }
+
+    if !values.is_empty() {
+        // The values may start with a hyphen, so are passed after the end of the options
+        if !cli_args.iter().any(|arg| arg == "--") {
+            cli_args.push("--".to_owned());
+        }
+
+        cli_args.extend(values);
+    }
+
+    cli_args
+}
+
+/// Whether a global flag is given; `false` if the cli defines an option of that name itself.
+pub(crate) fn flag(matches: &ArgMatches, name: &str) -> bool {
+    matches
+        .try_get_one::<bool>(name)
+        .ok()
+        .flatten()
+        .copied()
+        .unwrap_or(false)
+}
+
+/// The confirmation still to be asked for before the selected command is run: none if the command
+/// needs none or `--yes` is given. Asking is refused with an error if stdin or stderr is not a
+/// terminal.
+pub fn pending_confirmation<'a>(
+    confirmation: Option<&'a Confirmation>,
+    matches: &ArgMatches,
+) -> Result<Option<&'a Confirmation>, String> {
+    check_confirmation(
+        confirmation,
+        matches,
+        stdin().is_terminal() && stderr().is_terminal(),
+    )
+}
+
+fn check_confirmation<'a>(
+    confirmation: Option<&'a Confirmation>,
+    matches: &ArgMatches,
+    terminal: bool,
+) -> Result<Option<&'a Confirmation>, String> {
+    match confirmation {
+        Some(_) if flag(matches, YES_OPTION) => Ok(None),
+        Some(_) if !terminal => Err(format!(
+            "The command needs confirmation, but the session is not interactive; pass --{} to run it",
+            YES_OPTION
+        )),
+        confirmation => Ok(confirmation),
+    }
+}
+
+/// Asks for the confirmation on the terminal; `false` unless the user answers yes.
+pub fn confirm(confirmation: &Confirmation) -> bool {
+    Confirm::new()
+        .with_prompt(confirmation.text())
+        .default(false)
+        .interact()
+        .unwrap_or(false)
+}
+
+/// Finds the command selected by the matches, along with its own matches.
+fn selected_command<'a>(
+    model: &'a Model,
+    matches: &'a ArgMatches,
+) -> Option<(&'a dyn Command, &'a ArgMatches)> {
+    let (name, mut current) = matches.subcommand()?;
+    let mut command = model.get_command(name)?;
+
+    while let Some((name, sub_matches)) = current.subcommand() {
+        command = command.get_command(name)?;
+        current = sub_matches;
+    }
+
+    Some((command, current))
+}
+
+/// Checks an entered value against the type of the argument: paths must exist, and files and
+/// directories be of that kind.
+fn validate(arg: &CommandArg, value: &str) -> Result<(), String> {
+    let path = Path::new(value);
+
+    match arg.arg_type {
+        _ if value.trim().is_empty() => Err(format!("A value for {} is required", arg.name)),
+        ArgType::File if !path.is_file() => Err(format!("No such file: {}", value)),
+        ArgType::Dir if !path.is_dir() => Err(format!("No such directory: {}", value)),
+        ArgType::Path if !path.exists() => Err(format!("No such path: {}", value)),
+        _ => Ok(()),
+    }
+}
+
+/// Asks for values on the terminal, masking secret ones.
+struct TerminalPrompter;
+
+impl Prompter for TerminalPrompter {
+    fn prompt(&mut self, arg: &CommandArg) -> Option<String> {
+        let text = arg.description.as_ref().unwrap_or(&arg.name);
+
+        let secret = arg.env.as_ref().is_some_and(|env| env.secret);
+
+        let result = if secret {
+            Password::new()
+                .with_prompt(text)
+                .validate_with(|value: &String| validate(arg, value))
+                .interact()
+        } else {
+            let completion = PathCompletion {
+                dirs_only: arg.arg_type == ArgType::Dir,
+            };
+
+            let input = Input::<String>::new()
+                .with_prompt(text)
+                .validate_with(|value: &String| validate(arg, value));
+
+            match arg.arg_type {
+                ArgType::Unknown => input.interact_text(),
+                _ => input.completion_with(&completion).interact_text(),
+            }
+        };
+
+        result.ok()
+    }
+
+    fn pick(&mut self, items: &[String]) -> Option<usize> {
+        FuzzySelect::new()
+            .with_prompt("Command to run")
+            .items(items)
+            .default(0)
+            .interact_opt()
+            .ok()
+            .flatten()
+    }
+}
+
+/// Completes the last part of a path to the entries of its directory, as far as they agree.
+struct PathCompletion {
+    dirs_only: bool,
+}
+
+impl Completion for PathCompletion {
+    fn get(&self, input: &str) -> Option<String> {
+        let (dir, prefix) = match input.rfind('/') {
+            Some(index) => (&input[..=index], &input[index + 1..]),
+            None => ("", input),
+        };
+
+        let candidates: Vec<String> = read_dir(if dir.is_empty() { "." } else { dir })
+            .ok()?
+            .filter_map(Result::ok)
+            .filter(|entry| !self.dirs_only || entry.path().is_dir())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                if entry.path().is_dir() {
+                    format!("{}/", name)
+                } else {
+                    name
+                }
+            })
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+
+        let completed = common_prefix(&candidates)?;
+
+        Some(format!("{}{}", dir, completed)).filter(|completion| completion != input)
+    }
+}
+
+/// The longest prefix the given strings share, if there are any.
+fn common_prefix(strings: &[String]) -> Option<String> {
+    let first = strings.first()?;
+
+    let len = strings
+        .iter()
+        .map(|string| {
+            first
+                .chars()
+                .zip(string.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum()
+        })
+        .min()
+        .unwrap_or(0);
+
+    Some(first[..len].to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, File};
+    use std::path::PathBuf;
+
+    use crate::model::test::NO_DESCRIPTION;
+    use crate::model::{CommandOption, EmbeddedCommand, ScriptCommand};
+    use crate::transform::ToCliCommand;
+
+    use super::*;
+
+    /// Answers each prompt with the next of the given values.
+    struct Answers(Vec<&'static str>);
+
+    impl Prompter for Answers {
+        fn prompt(&mut self, _arg: &CommandArg) -> Option<String> {
+            if self.0.is_empty() {
+                None
+            } else {
+                Some(self.0.remove(0).to_owned())
+            }
+        }
+
+        /// Picks the first item starting with the next answer.
+        fn pick(&mut self, items: &[String]) -> Option<usize> {
+            let answer = self.prompt(&CommandArg::new(
+                "command",
+                false,
+                false,
+                ArgType::Unknown,
+                NO_DESCRIPTION,
+            ))?;
+
+            items.iter().position(|item| item.starts_with(&answer))
+        }
+    }
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn model() -> Model {
+        let arg = |name: &str, prompt: bool| {
+            CommandArg::new(name, false, false, ArgType::Unknown, NO_DESCRIPTION)
+                .with_prompt(prompt)
+        };
+
+        Model::new(vec![Box::new(ScriptCommand::new(
+            "deploy".to_owned(),
+            None,
+            PathBuf::from("/tmp/deploy.sh"),
+            vec![],
+            vec![arg("app", true), arg("stage", false)],
+            vec![],
+        ))])
+    }
+
+    #[test]
+    fn complete_args_asks_for_args_marked_to_prompt() {
+        let model = model();
+
+        assert_eq!(
+            complete_args(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "deploy"]),
+                &mut Answers(vec!["-web", "prod"])
+            ),
+            to_args(&["mycli", "deploy", "--", "-web"])
+        );
+
+        // Given args are not asked for
+        assert_eq!(
+            complete_args(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "deploy", "web"]),
+                &mut Answers(vec!["prod"])
+            ),
+            to_args(&["mycli", "deploy", "web"])
+        );
+    }
+
+    #[test]
+    fn complete_args_asks_for_all_args_if_interactive() {
+        let model = model();
+
+        assert_eq!(
+            complete_args(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "deploy", "--interactive"]),
+                &mut Answers(vec!["web", "prod"])
+            ),
+            to_args(&["mycli", "deploy", "--interactive", "--", "web", "prod"])
+        );
+
+        // Stops when the user gives up
+        assert_eq!(
+            complete_args(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "deploy", "--interactive"]),
+                &mut Answers(vec![])
+            ),
+            to_args(&["mycli", "deploy", "--interactive"])
+        );
+    }
+
+    #[test]
+    fn check_confirmation_refuses_non_interactive_sessions_without_yes() {
+        let model = model();
+        let confirmation = Confirmation::new(None);
+
+        let matches = |args: &[&str]| model.to_cli().get_matches_from(args);
+        let without_yes = matches(&["mycli", "deploy", "web", "prod"]);
+        let with_yes = matches(&["mycli", "deploy", "web", "prod", "--yes"]);
+
+        assert_eq!(
+            check_confirmation(Some(&confirmation), &without_yes, true),
+            Ok(Some(&confirmation))
+        );
+        assert!(check_confirmation(Some(&confirmation), &without_yes, false).is_err());
+        assert_eq!(
+            check_confirmation(Some(&confirmation), &with_yes, false),
+            Ok(None)
+        );
+        assert_eq!(check_confirmation(None, &without_yes, false), Ok(None));
+    }
+
+    #[test]
+    fn choose_command_inserts_chosen_command() {
+        let create = EmbeddedCommand::new("create", Some("Creates a cluster"), vec![], vec![]);
+        let cluster = EmbeddedCommand::new("cluster", NO_DESCRIPTION, vec![], vec![])
+            .with_sub_commands(vec![Box::new(create)]);
+
+        let mut model = model();
+        model.add_command(ScriptCommand::new(
+            "infra".to_owned(),
+            None,
+            PathBuf::from("/tmp/infra.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(cluster)],
+        ));
+
+        let mut items = Vec::<String>::new();
+
+        struct Recorder<'a>(&'a mut Vec<String>);
+
+        impl Prompter for Recorder<'_> {
+            fn prompt(&mut self, _arg: &CommandArg) -> Option<String> {
+                None
+            }
+
+            fn pick(&mut self, items: &[String]) -> Option<usize> {
+                self.0.extend_from_slice(items);
+                None
+            }
+        }
+
+        // Parents that cannot be run are not listed
+        choose_command(
+            &model,
+            &model.to_cli(),
+            to_args(&["mycli"]),
+            &mut Recorder(&mut items),
+        );
+        assert_eq!(
+            items,
+            vec![
+                "deploy".to_owned(),
+                "infra".to_owned(),
+                "infra cluster create  Creates a cluster".to_owned()
+            ]
+        );
+
+        assert_eq!(
+            choose_command(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "--pick"]),
+                &mut Answers(vec!["infra cluster"])
+            ),
+            to_args(&[
+                "mycli",
+                "infra",
+                "cluster",
+                "create",
+                "--pick",
+                "--interactive"
+            ])
+        );
+
+        // A command is only chosen if the command line is empty, or asks for it
+        model
+            .metadata
+            .options
+            .push(CommandOption::new("verbose", None, false, NO_DESCRIPTION));
+
+        assert_eq!(
+            choose_command(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "deploy"]),
+                &mut Answers(vec!["infra"])
+            ),
+            to_args(&["mycli", "deploy"])
+        );
+        assert_eq!(
+            choose_command(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "--verbose"]),
+                &mut Answers(vec!["infra"])
+            ),
+            to_args(&["mycli", "--verbose"])
+        );
+
+        // The sub-commands of the command given are chosen from
+        assert_eq!(
+            choose_command(
+                &model,
+                &model.to_cli(),
+                to_args(&["mycli", "--pick", "infra"]),
+                &mut Answers(vec!["cluster"])
+            ),
+            to_args(&[
+                "mycli",
+                "--pick",
+                "infra",
+                "cluster",
+                "create",
+                "--interactive"
+            ])
+        );
+    }
+
+    #[test]
+    fn validate_checks_arg_type() {
+        let test_dir = tempfile::tempdir().unwrap();
+        let file = test_dir.path().join("file.txt");
+        File::create(&file).unwrap();
+
+        let arg = |arg_type| CommandArg::new("arg", false, false, arg_type, NO_DESCRIPTION);
+
+        let dir = test_dir.path().to_str().unwrap();
+        let file = file.to_str().unwrap();
+
+        assert!(validate(&arg(ArgType::Unknown), "anything").is_ok());
+        assert!(validate(&arg(ArgType::Unknown), " ").is_err());
+        assert!(validate(&arg(ArgType::File), file).is_ok());
+        assert!(validate(&arg(ArgType::File), dir).is_err());
+        assert!(validate(&arg(ArgType::Dir), dir).is_ok());
+        assert!(validate(&arg(ArgType::Dir), file).is_err());
+        assert!(validate(&arg(ArgType::Path), "/no/such/path").is_err());
+    }
+
+    #[test]
+    fn path_completion_completes_common_prefix() {
+        let test_dir = tempfile::tempdir().unwrap();
+        create_dir(test_dir.path().join("scripts")).unwrap();
+        File::create(test_dir.path().join("script1.sh")).unwrap();
+        File::create(test_dir.path().join("script2.sh")).unwrap();
+
+        let dir = format!("{}/", test_dir.path().to_str().unwrap());
+        let completion = PathCompletion { dirs_only: false };
+
+        assert_eq!(
+            completion.get(&format!("{}sc", dir)),
+            Some(format!("{}script", dir))
+        );
+        assert_eq!(completion.get(&format!("{}script", dir)), None);
+        assert_eq!(
+            completion.get(&format!("{}scripts", dir)),
+            Some(format!("{}scripts/", dir))
+        );
+
+        let completion = PathCompletion { dirs_only: true };
+        assert_eq!(
+            completion.get(&format!("{}sc", dir)),
+            Some(format!("{}scripts/", dir))
+        );
+    }
+}
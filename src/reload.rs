@@ -0,0 +1,254 @@
+//! Hot reloading of a model read from a directory, for long-lived processes such as sessions.
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use log::debug;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::builder::{build_script_command, is_cli_metadata_file, HOOKS_DIR};
+use crate::import::is_import_file;
+use crate::model::{build_group, has_no_option_clashes, report_file_error, Command, Model};
+
+/// Watches the source directory of a model, to apply the changes made to its scripts.
+pub struct ModelReloader {
+    source: PathBuf,
+    /// The source directory as watched, which is the path of the changed files in the events.
+    watched: PathBuf,
+    receiver: Receiver<notify::Result<Event>>,
+    // Watching stops when the watcher is dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl ModelReloader {
+    /// Starts watching the source directory of the model; returns `None` if the model was not read
+    /// from a directory.
+    pub fn new(model: &Model) -> Option<Result<ModelReloader, notify::Error>> {
+        let source = model.source.clone()?;
+
+        Some(ModelReloader::watch(source))
+    }
+
+    fn watch(source: PathBuf) -> Result<ModelReloader, notify::Error> {
+        let watched = if source.is_absolute() {
+            source.clone()
+        } else {
+            std::env::current_dir()?.join(&source)
+        };
+
+        let (sender, receiver) = channel();
+
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&watched, RecursiveMode::Recursive)?;
+
+        Ok(ModelReloader {
+            source,
+            watched,
+            receiver,
+            _watcher: watcher,
+        })
+    }
+
+    /// Applies the changes made to the source directory since the last call to the model, without
+    /// waiting for further changes. Returns true if the model was changed.
+    pub fn reload(&self, model: &mut Model) -> bool {
+        let changed: Vec<PathBuf> = self
+            .receiver
+            .try_iter()
+            .filter_map(Result::ok)
+            .filter(|event| !matches!(event.kind, EventKind::Access(_)))
+            .flat_map(|event| event.paths)
+            .filter_map(|path| {
+                path.strip_prefix(&self.watched)
+                    .ok()
+                    .map(|path| self.source.join(path))
+            })
+            .collect();
+
+        !changed.is_empty() && apply_changes(model, &self.source, changed)
+    }
+}
+
+/// Rebuilds the commands read from the entries of the source directory containing the changed
+/// paths: the script for a changed script, and the whole group for a change within a directory.
+/// The metadata file, the hooks directory and the files of other tools apply to several commands,
+/// so changes to them rebuild the entire model, dropping any commands added to it after it was read.
+fn apply_changes(model: &mut Model, source: &Path, changed: Vec<PathBuf>) -> bool {
+    let entries: BTreeSet<PathBuf> = changed
+        .iter()
+        .filter_map(|path| source_entry(source, path))
+        .collect();
+
+    if entries.is_empty() {
+        return false;
+    }
+
+    if entries
+        .iter()
+        .any(|entry| is_cli_metadata_file(entry) || is_hooks_dir(entry) || is_import_file(entry))
+    {
+        debug!("reloading all commands from {}", source.to_string_lossy());
+        reload_all(model, source);
+        return true;
+    }
+
+    for entry in entries {
+        debug!("reloading commands from {}", entry.to_string_lossy());
+
+        model
+            .commands
+            .retain(|command| !is_read_from(command.as_ref(), &entry));
+
+        let command: Option<Box<dyn Command>> = if entry.is_dir() {
+            build_group(&entry).map(|group| Box::new(group) as Box<dyn Command>)
+        } else if entry.is_file() {
+            build_script_command(entry.clone())
+                .unwrap_or_else(|e| report_file_error(&entry, e))
+                .map(|command| Box::new(command) as Box<dyn Command>)
+        } else {
+            // Removed
+            None
+        }
+        .filter(|command| has_no_option_clashes(&model.metadata, command.as_ref(), &entry));
+
+        if let Some(command) = command {
+            // Which of the commands of the same name is kept depends on the whole directory
+            if model.commands.iter().any(|c| c.name() == command.name()) {
+                reload_all(model, source);
+                return true;
+            }
+
+            model.commands.push(command);
+        }
+    }
+
+    // As when the model was read
+    model.commands.sort_by(|a, b| a.name().cmp(b.name()));
+
+    true
+}
+
+/// Replaces the metadata and commands of the model with those read from the source directory,
+/// keeping the name of the cli, which may have been given by the launcher.
+fn reload_all(model: &mut Model, source: &Path) {
+    let reloaded = Model::from(source);
+
+    let name = model
+        .metadata
+        .name
+        .take()
+        .or(reloaded.metadata.name.clone());
+
+    model.metadata = reloaded.metadata;
+    model.metadata.name = name;
+    model.commands = reloaded.commands;
+}
+
+fn is_hooks_dir(entry: &Path) -> bool {
+    entry.file_name().is_some_and(|name| name == HOOKS_DIR)
+}
+
+/// The entry of the source directory containing the given path, if it is in the source directory.
+fn source_entry(source: &Path, path: &Path) -> Option<PathBuf> {
+    match path.strip_prefix(source).ok()?.components().next()? {
+        Component::Normal(name) => Some(source.join(name)),
+        _ => None,
+    }
+}
+
+/// Whether the top-level command was read from the given entry of the source directory: a script
+/// from its file, a group from its directory, which contains its group file if it has one, and is
+/// otherwise named after it.
+fn is_read_from(command: &dyn Command, entry: &Path) -> bool {
+    match command.get_path() {
+        Some(path) => path == entry || path.parent() == Some(entry),
+        None => entry
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy() == command.name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn write_script(path: &Path, description: &str) {
+        fs::write(path, format!("#!/bin/bash\n# @about {}\n", description)).unwrap();
+    }
+
+    fn command_names(model: &Model) -> Vec<&str> {
+        model
+            .commands
+            .iter()
+            .map(|command| command.name())
+            .collect()
+    }
+
+    #[test]
+    fn source_entry_is_the_top_level_entry() {
+        let source = Path::new("/scripts");
+
+        assert_eq!(
+            source_entry(source, Path::new("/scripts/db/_group")),
+            Some(PathBuf::from("/scripts/db"))
+        );
+        assert_eq!(
+            source_entry(source, Path::new("/scripts/deploy.sh")),
+            Some(PathBuf::from("/scripts/deploy.sh"))
+        );
+        assert_eq!(source_entry(source, Path::new("/scripts")), None);
+        assert_eq!(source_entry(source, Path::new("/other/deploy.sh")), None);
+    }
+
+    #[test]
+    fn apply_changes_rebuilds_changed_scripts_and_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path();
+
+        write_script(&source.join("build.sh"), "Builds");
+        write_script(&source.join("deploy.sh"), "Deploys");
+
+        let mut model = Model::from(source);
+        model.metadata.name = Some("mycli".to_owned());
+
+        assert_eq!(command_names(&model), vec!["build", "deploy"]);
+
+        // A changed description, a new group and a removed script
+        write_script(&source.join("build.sh"), "Builds everything");
+        fs::create_dir(source.join("db")).unwrap();
+        write_script(&source.join("db").join("migrate.sh"), "Migrates");
+        fs::remove_file(source.join("deploy.sh")).unwrap();
+
+        assert!(apply_changes(
+            &mut model,
+            source,
+            vec![
+                source.join("build.sh"),
+                source.join("db").join("migrate.sh"),
+                source.join("deploy.sh"),
+            ]
+        ));
+
+        assert_eq!(command_names(&model), vec!["build", "db"]);
+        assert_eq!(model.commands[0].description(), Some("Builds everything"));
+        assert_eq!(model.metadata.name.as_deref(), Some("mycli"));
+
+        // Removing the last script in a group removes the group
+        fs::remove_file(source.join("db").join("migrate.sh")).unwrap();
+
+        assert!(apply_changes(
+            &mut model,
+            source,
+            vec![source.join("db").join("migrate.sh")]
+        ));
+        assert_eq!(command_names(&model), vec!["build"]);
+
+        assert!(!apply_changes(
+            &mut model,
+            source,
+            vec![PathBuf::from("/elsewhere/build.sh")]
+        ));
+    }
+}
@@ -0,0 +1,335 @@
+//! An interactive session in which the commands of the cli are run one after the other, with line
+//! editing, history and completion.
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+use log::debug;
+use rustyline::completion::Completer;
+use rustyline::config::{Behavior, Config};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::exec::{expand_aliases, hoist_dry_run, prepare_execution, Execution};
+use crate::model::{HasSubCommands, Model};
+use crate::prompt::{flag, prompt_missing_args};
+use crate::reload::ModelReloader;
+use crate::transform::ToCliCommand;
+
+/// The name of the global flag starting a session.
+pub const REPL_OPTION: &str = "repl";
+
+/// The words ending a session, unless the cli has a command of that name.
+const EXIT_COMMANDS: [&str; 2] = ["exit", "quit"];
+
+/// Whether the command line asks for a session: it includes `--repl`, but selects no command.
+pub fn repl_requested(cli: &clap::Command, cli_args: &[String]) -> bool {
+    cli.clone()
+        .ignore_errors(true)
+        .try_get_matches_from(cli_args.iter())
+        .is_ok_and(|matches| matches.subcommand().is_none() && flag(&matches, REPL_OPTION))
+}
+
+/// Runs a session, reading command lines from the terminal and running the commands they select
+/// until the user exits. The commands are executed directly, as in executed mode; if stdout is
+/// not the terminal, e.g. as it is to be evaluated, `output_to_stderr` sends their output to
+/// stderr instead. The history is kept in the home directory, in a file named after the cli.
+///
+/// If the model was read from a directory, changes to its scripts are applied to it before each
+/// command line is read, and the cli is rebuilt from it.
+pub fn run_repl(model: &mut Model, mut cli: clap::Command, output_to_stderr: bool) {
+    let name = cli.get_name().to_owned();
+
+    let reloader = match ModelReloader::new(model).transpose() {
+        Ok(reloader) => reloader,
+        Err(e) => {
+            eprintln!("Changes to the scripts will not be picked up: {}", e);
+            None
+        }
+    };
+
+    let config = Config::builder()
+        .behavior(Behavior::PreferTerm)
+        .auto_add_history(true)
+        .build();
+
+    let mut editor = match Editor::<ReplHelper, DefaultHistory>::with_config(config) {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Error in starting the session: {}", e);
+            return;
+        }
+    };
+
+    editor.set_helper(Some(ReplHelper::new(&cli)));
+
+    let history = history_file(&name);
+
+    if let Some(history) = history.as_ref() {
+        // There is no history before the first session
+        let _ = editor.load_history(history);
+    }
+
+    eprintln!("Type a command, `help` for the list of commands, or `exit` to end the session.");
+
+    loop {
+        // For completion
+        reload(reloader.as_ref(), model, &mut cli, &mut editor);
+
+        match editor.readline(&format!("{}> ", name)) {
+            Ok(line) => {
+                // The scripts may have changed while the line was entered
+                reload(reloader.as_ref(), model, &mut cli, &mut editor);
+
+                let words = match shell_words::split(&line) {
+                    Ok(words) => words,
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        continue;
+                    }
+                };
+
+                match words.first() {
+                    None => {}
+                    Some(word)
+                        if EXIT_COMMANDS.contains(&word.as_str())
+                            && model.get_command(word).is_none() =>
+                    {
+                        break
+                    }
+                    Some(_) => run_line(model, &cli, words, output_to_stderr),
+                }
+            }
+            // Ctrl-C abandons the line being edited
+            Err(ReadlineError::Interrupted) => {}
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Error in reading the command: {}", e);
+                break;
+            }
+        }
+    }
+
+    if let Some(history) = history.as_ref() {
+        if let Err(e) = editor.save_history(history) {
+            debug!("Unable to save the history: {}", e);
+        }
+    }
+}
+
+/// Applies the changes to the scripts to the model, if any, rebuilding the cli from it.
+fn reload(
+    reloader: Option<&ModelReloader>,
+    model: &mut Model,
+    cli: &mut clap::Command,
+    editor: &mut Editor<ReplHelper, DefaultHistory>,
+) {
+    if reloader.is_some_and(|reloader| reloader.reload(model)) {
+        *cli = model.to_cli();
+        editor.set_helper(Some(ReplHelper::new(cli)));
+    }
+}
+
+/// Runs the command selected by the words of a command line entered in the session.
+fn run_line(model: &Model, cli: &clap::Command, words: Vec<String>, output_to_stderr: bool) {
+    let cli_args: Vec<String> = std::iter::once(cli.get_name().to_owned())
+        .chain(words)
+        .collect();
+
+    let cli_args = expand_aliases(model, cli, cli_args);
+    let cli_args = hoist_dry_run(cli, cli_args);
+    let cli_args = prompt_missing_args(model, cli, cli_args);
+
+    let arg_matches: ArgMatches = match cli.clone().try_get_matches_from(cli_args.iter()) {
+        Ok(matches) => matches,
+        Err(err) => {
+            // Help and usage messages are errors too
+            print_error(err, output_to_stderr);
+            return;
+        }
+    };
+
+    match prepare_execution(model, &arg_matches) {
+        Ok(Execution::Run(dispatch)) => {
+            // The session goes on whether the command succeeds or not
+            dispatch.run(output_to_stderr);
+        }
+        Ok(Execution::Explained(explanation)) => {
+            if output_to_stderr {
+                eprint!("{}", explanation);
+            } else {
+                print!("{}", explanation);
+                let _ = io::stdout().flush();
+            }
+        }
+        Ok(Execution::Aborted) => eprintln!("Aborted"),
+        Err(err) => print_error(err, output_to_stderr),
+    }
+}
+
+/// Prints a clap error, or help or usage message, without exiting.
+fn print_error(err: clap::Error, output_to_stderr: bool) {
+    if output_to_stderr {
+        eprint!("{}", err.render().ansi());
+    } else {
+        let _ = err.print();
+    }
+}
+
+/// The file the history of the sessions of the cli is kept in.
+fn history_file(name: &str) -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(format!(".{}_history", name)))
+}
+
+/// Completes the words of a command line from the sub-commands and options of the cli.
+struct ReplHelper {
+    cli: clap::Command,
+}
+
+impl ReplHelper {
+    fn new(cli: &clap::Command) -> Self {
+        // Building propagates the global options to the sub-commands
+        let mut cli = cli.clone();
+        cli.build();
+
+        ReplHelper { cli }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line
+            .rfind(char::is_whitespace)
+            .map(|index| index + 1)
+            .unwrap_or(0);
+
+        Ok((
+            start,
+            completions(&self.cli, line[..start].split_whitespace(), &line[start..]),
+        ))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// The names of the sub-commands, or the long options if the prefix starts with a hyphen, of the
+/// command selected by the given words, which start with the prefix.
+fn completions<'a>(
+    cli: &clap::Command,
+    words: impl Iterator<Item = &'a str>,
+    prefix: &str,
+) -> Vec<String> {
+    let command = words.fold(cli, |command, word| {
+        command.find_subcommand(word).unwrap_or(command)
+    });
+
+    let mut candidates: Vec<String> = if prefix.starts_with('-') {
+        command
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .flat_map(|arg| arg.get_long_and_visible_aliases().unwrap_or_default())
+            .map(|long| format!("--{}", long))
+            .collect()
+    } else {
+        command
+            .get_subcommands()
+            .filter(|sub| !sub.is_hide_set())
+            .flat_map(|sub| std::iter::once(sub.get_name()).chain(sub.get_visible_aliases()))
+            .map(str::to_owned)
+            .collect()
+    };
+
+    candidates.retain(|candidate| candidate.starts_with(prefix));
+    candidates.sort();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::model::test::NO_DESCRIPTION;
+    use crate::model::{ArgType, CommandArg, CommandOption, EmbeddedCommand, ScriptCommand};
+    use crate::transform::ToCliCommand;
+
+    use super::*;
+
+    fn model() -> Model {
+        let create = EmbeddedCommand::new(
+            "create",
+            NO_DESCRIPTION,
+            vec![CommandOption::new("region", None, true, NO_DESCRIPTION)],
+            vec![CommandArg::new(
+                "name",
+                false,
+                false,
+                ArgType::Unknown,
+                NO_DESCRIPTION,
+            )],
+        );
+
+        Model::new(vec![Box::new(ScriptCommand::new(
+            "cluster".to_owned(),
+            None,
+            PathBuf::from("/tmp/cluster.sh"),
+            vec![],
+            vec![],
+            vec![Box::new(create)],
+        ))])
+    }
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn repl_requested_without_command() {
+        let cli = model().to_cli();
+
+        assert!(repl_requested(&cli, &to_args(&["mycli", "--repl"])));
+        assert!(!repl_requested(&cli, &to_args(&["mycli"])));
+        assert!(!repl_requested(
+            &cli,
+            &to_args(&["mycli", "--repl", "cluster", "create", "c1"])
+        ));
+    }
+
+    #[test]
+    fn completions_follow_the_command_line() {
+        let helper = ReplHelper::new(&model().to_cli());
+
+        assert_eq!(
+            completions(&helper.cli, "".split_whitespace(), "c"),
+            vec!["cluster"]
+        );
+        assert_eq!(
+            completions(&helper.cli, "cluster".split_whitespace(), ""),
+            vec!["create", "help"]
+        );
+        // Global options are offered for sub-commands too
+        assert_eq!(
+            completions(&helper.cli, "cluster create".split_whitespace(), "--re"),
+            vec!["--region", "--repl"]
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! Running the process of a command in executed mode within its timeout, and running it again
+//! if it fails, as set by its `@timeout` and `@retry` tags.
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{self, Child, ExitStatus};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use std::{io, mem, ptr};
+
+use log::debug;
+
+use crate::exec::RootOptionEnv;
+use crate::model::Command;
+
+/// The exit code of a command that was killed as it did not finish within its timeout, as for
+/// the `timeout` utility.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// How long a command that timed out is given to stop after being asked to, before it is killed.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// How often a command with a timeout is checked for having finished.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The signals that are passed on to a command running in a process group of its own, as they are
+/// meant for the whole job and it no longer receives them from the terminal or the shell.
+const FORWARDED_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// The last of the [FORWARDED_SIGNALS] received, not yet passed on; 0 if none.
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// How a single run of a command ended.
+enum Outcome {
+    /// The command finished with the exit code.
+    Finished(i32),
+    /// The command was killed as it did not finish within the timeout.
+    TimedOut,
+    /// The command finished with the exit code after being passed a signal meant to stop it.
+    Interrupted(i32),
+}
+
+/// Runs the command with the given args and returns its exit code; `None` if the command cannot be
+/// run on its own. A command with a timeout runs in a process group of its own, which is killed
+/// with everything the command started if it does not finish in time. A failing command with a
+/// retry is run again, after waiting, until it succeeds or has been retried as often as allowed;
+/// its exit code is that of the last run. The environment variables are set for each run. If
+/// `output_to_stderr`, the output of the command is sent to stderr, as stdout is to be evaluated.
+pub(crate) fn run_supervised(
+    command: &dyn Command,
+    args: Vec<String>,
+    environment: &RootOptionEnv,
+    output_to_stderr: bool,
+) -> Option<i32> {
+    let retries = command.retry().map_or(0, |retry| retry.count);
+    let mut backoff = command.retry().map(|retry| retry.backoff);
+
+    let mut attempt = 0;
+
+    loop {
+        let mut process = command.process(args.clone())?;
+        environment.apply(&mut process);
+
+        if output_to_stderr {
+            process.stdout(io::stderr());
+        }
+
+        let code = match run_once(process, command.timeout()) {
+            Ok(Outcome::Finished(code)) => code,
+            Ok(Outcome::TimedOut) => {
+                eprintln!(
+                    "The command '{}' timed out after {}",
+                    command.name(),
+                    humantime::format_duration(command.timeout().unwrap_or_default())
+                );
+                TIMEOUT_EXIT_CODE
+            }
+            // Stopped on purpose, so not run again
+            Ok(Outcome::Interrupted(code)) => return Some(code),
+            Err(e) => {
+                eprintln!("Error in executing command : {}", e);
+                return Some(1);
+            }
+        };
+
+        if code == 0 || attempt == retries {
+            return Some(code);
+        }
+
+        attempt += 1;
+
+        let wait = backoff.unwrap_or_default();
+
+        eprintln!(
+            "The command '{}' failed with exit code {}, retrying in {} ({} of {})",
+            command.name(),
+            code,
+            humantime::format_duration(wait),
+            attempt,
+            retries
+        );
+
+        sleep(wait);
+        backoff = backoff.map(|backoff| backoff.saturating_mul(2));
+    }
+}
+
+/// Describes the timeout and retries of the command, for a dry run.
+pub(crate) fn supervision_lines(command: &dyn Command) -> Vec<(&'static str, String)> {
+    let timeout = command
+        .timeout()
+        .map(|timeout| ("timeout", humantime::format_duration(timeout).to_string()));
+
+    let retry = command.retry().map(|retry| {
+        (
+            "retry",
+            format!(
+                "{} times, waiting {} at first",
+                retry.count,
+                humantime::format_duration(retry.backoff)
+            ),
+        )
+    });
+
+    timeout.into_iter().chain(retry).collect()
+}
+
+/// Runs the process once, killing it and the processes it started if it has not finished within
+/// the timeout. If run from the terminal, the process is given the terminal while it runs, so that
+/// it can read from it although it runs in a process group of its own.
+fn run_once(mut process: process::Command, timeout: Option<Duration>) -> io::Result<Outcome> {
+    let Some(timeout) = timeout else {
+        return process
+            .status()
+            .map(|status| Outcome::Finished(exit_code(status)));
+    };
+
+    let terminal_group = foreground_group();
+
+    if terminal_group.is_some() {
+        // SAFETY: the closure only makes async-signal-safe calls, as it runs between fork and exec
+        unsafe {
+            process.pre_exec(|| {
+                give_terminal(libc::getpgrp());
+                Ok(())
+            });
+        }
+    }
+
+    // The process group is named after the process leading it
+    let mut child = process.process_group(0).spawn()?;
+
+    let previous = forward_signals();
+    let outcome = wait_for(&mut child, timeout);
+    restore_signals(previous);
+
+    if let Some(group) = terminal_group {
+        give_terminal(group);
+    }
+
+    outcome
+}
+
+/// Waits for the child leading its own process group to finish, passing on the signals received
+/// in the meantime, and kills the group if it has not finished within the timeout.
+fn wait_for(child: &mut Child, timeout: Duration) -> io::Result<Outcome> {
+    let deadline = Instant::now() + timeout;
+    let mut interrupted = false;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            let code = exit_code(status);
+
+            // Signals from the terminal go straight to the command while it has the terminal
+            let interrupted = interrupted
+                || status
+                    .signal()
+                    .is_some_and(|signal| FORWARDED_SIGNALS.contains(&signal));
+
+            return Ok(if interrupted {
+                Outcome::Interrupted(code)
+            } else {
+                Outcome::Finished(code)
+            });
+        }
+
+        if let Some(signal) = received_signal() {
+            debug!("passing signal {} on to the command", signal);
+            signal_group(child, signal);
+            interrupted = true;
+        }
+
+        if Instant::now() >= deadline {
+            debug!("stopping the command, as it timed out");
+            stop_group(child)?;
+            return Ok(Outcome::TimedOut);
+        }
+
+        sleep(POLL_INTERVAL);
+    }
+}
+
+/// Asks the process group of the child to stop, and kills it if it has not stopped after the
+/// grace period.
+pub(crate) fn stop_group(child: &mut Child) -> io::Result<()> {
+    signal_group(child, libc::SIGTERM);
+
+    let deadline = Instant::now() + KILL_GRACE;
+
+    while Instant::now() < deadline {
+        if child.try_wait()?.is_some() {
+            // Other processes of the group may not have stopped with it
+            signal_group(child, libc::SIGKILL);
+            return Ok(());
+        }
+
+        sleep(POLL_INTERVAL);
+    }
+
+    signal_group(child, libc::SIGKILL);
+    child.wait().map(|_| ())
+}
+
+fn signal_group(child: &Child, signal: libc::c_int) {
+    // SAFETY: kill only sends a signal; a negative pid names the process group the child leads
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), signal);
+    }
+}
+
+/// The process group of this process, if it is the foreground one of the terminal on stdin.
+fn foreground_group() -> Option<libc::pid_t> {
+    // SAFETY: the calls only read the state of the process and of the terminal
+    unsafe {
+        let group = libc::getpgrp();
+
+        (libc::isatty(libc::STDIN_FILENO) == 1 && libc::tcgetpgrp(libc::STDIN_FILENO) == group)
+            .then_some(group)
+    }
+}
+
+/// Makes the process group the foreground one of the terminal on stdin. SIGTTOU, which a process
+/// outside of the foreground group is sent for this, is blocked meanwhile.
+fn give_terminal(group: libc::pid_t) {
+    // SAFETY: the signal sets are initialized before use, and the calls are async-signal-safe
+    unsafe {
+        let mut blocked: libc::sigset_t = mem::zeroed();
+        let mut previous: libc::sigset_t = mem::zeroed();
+
+        libc::sigemptyset(&mut blocked);
+        libc::sigaddset(&mut blocked, libc::SIGTTOU);
+        libc::sigprocmask(libc::SIG_BLOCK, &blocked, &mut previous);
+        libc::tcsetpgrp(libc::STDIN_FILENO, group);
+        libc::sigprocmask(libc::SIG_SETMASK, &previous, ptr::null_mut());
+    }
+}
+
+extern "C" fn record_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Records the [FORWARDED_SIGNALS] instead of handling them as before, and returns the previous
+/// handlers.
+pub(crate) fn forward_signals() -> Vec<libc::sighandler_t> {
+    RECEIVED_SIGNAL.store(0, Ordering::SeqCst);
+
+    FORWARDED_SIGNALS
+        .iter()
+        .map(|signal| {
+            // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+            unsafe { libc::signal(*signal, record_signal as *const () as libc::sighandler_t) }
+        })
+        .collect()
+}
+
+pub(crate) fn restore_signals(previous: Vec<libc::sighandler_t>) {
+    FORWARDED_SIGNALS
+        .iter()
+        .zip(previous)
+        .for_each(|(signal, handler)| {
+            // SAFETY: the handlers were those in place before forward_signals
+            unsafe {
+                libc::signal(*signal, handler);
+            }
+        });
+}
+
+/// The last of the [FORWARDED_SIGNALS] received since it was last asked for, if any.
+pub(crate) fn received_signal() -> Option<libc::c_int> {
+    Some(RECEIVED_SIGNAL.swap(0, Ordering::SeqCst)).filter(|signal| *signal != 0)
+}
+
+/// The exit code of a process, which is that of the shell for one killed by a signal.
+pub(crate) fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or(status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    use crate::model::{Retry, ScriptCommand};
+
+    use super::*;
+
+    fn script(dir: &Path, name: &str, body: &str) -> ScriptCommand {
+        let path = dir.join(name);
+
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        ScriptCommand::new(name.to_owned(), None, path, vec![], vec![], vec![])
+    }
+
+    #[test]
+    fn run_supervised_kills_the_process_group_on_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker: PathBuf = dir.path().join("marker");
+
+        // The background process would create the marker if it were not killed with the script
+        let command = script(
+            dir.path(),
+            "slow.sh",
+            &format!("(sleep 1; touch '{}') &\nsleep 5", marker.to_string_lossy()),
+        )
+        .with_timeout(Some(Duration::from_millis(200)));
+
+        let started = Instant::now();
+
+        assert_eq!(
+            run_supervised(&command, vec![], &RootOptionEnv::default(), false),
+            Some(TIMEOUT_EXIT_CODE)
+        );
+        assert!(started.elapsed() < Duration::from_secs(3));
+
+        sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn run_supervised_retries_until_the_command_succeeds() {
+        let dir = tempfile::tempdir().unwrap();
+        let count = dir.path().join("count");
+
+        // Fails on the first two runs
+        let command = script(
+            dir.path(),
+            "flaky.sh",
+            &format!(
+                "echo run >> '{0}'\n[ $(wc -l < '{0}') -ge 3 ]",
+                count.to_string_lossy()
+            ),
+        );
+
+        let flaky = command.with_retry(Some(Retry::new(3, Some(Duration::from_millis(10)))));
+
+        assert_eq!(
+            run_supervised(&flaky, vec![], &RootOptionEnv::default(), false),
+            Some(0)
+        );
+        assert_eq!(fs::read_to_string(&count).unwrap().lines().count(), 3);
+
+        // Gives up after the last retry
+        fs::remove_file(&count).unwrap();
+        let flaky = flaky.with_retry(Some(Retry::new(1, Some(Duration::from_millis(10)))));
+
+        assert_eq!(
+            run_supervised(&flaky, vec![], &RootOptionEnv::default(), false),
+            Some(1)
+        );
+        assert_eq!(fs::read_to_string(&count).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn run_supervised_does_not_retry_an_interrupted_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let count = dir.path().join("count");
+
+        // As if interrupted from the terminal, which signals the command directly
+        let command = script(
+            dir.path(),
+            "interrupted.sh",
+            &format!("echo run >> '{}'\nkill -INT $$", count.to_string_lossy()),
+        )
+        .with_timeout(Some(Duration::from_secs(5)))
+        .with_retry(Some(Retry::new(3, Some(Duration::from_millis(10)))));
+
+        assert_eq!(
+            run_supervised(&command, vec![], &RootOptionEnv::default(), false),
+            Some(128 + libc::SIGINT)
+        );
+        assert_eq!(fs::read_to_string(&count).unwrap().lines().count(), 1);
+    }
+}
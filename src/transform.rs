@@ -1,9 +1,22 @@
 //! Traits and implementations for transforming the internal model into a clap command
 use clap::builder::StringValueParser;
-use clap::{Arg, ArgAction, ValueHint};
+use clap::{Arg, ArgAction, ArgGroup, ValueHint};
 
+use crate::exec::{DRY_RUN_OPTION, EXPLAIN_ALIAS};
+use crate::history::{HISTORY_OPTION, RERUN_OPTION};
 use crate::model::Command;
-use crate::model::{ArgType, CommandArg, CommandOption, Model};
+use crate::model::{
+    ArgRelation, ArgType, CliMetadata, CommandArg, CommandOption, EnvVar, Model, OptionKind,
+};
+use crate::prompt::{INTERACTIVE_OPTION, PICK_OPTION, YES_OPTION};
+use crate::repl::REPL_OPTION;
+use crate::watch::WATCH_OPTION;
+
+/// The name of the root command, if the metadata does not specify one.
+const DEFAULT_ROOT_NAME: &str = "easy-cli";
+
+/// The version of the root command, if the metadata does not specify one.
+const DEFAULT_ROOT_VERSION: &str = "0.1.0";
 
 /// Convenience type alias to avoid confusion with internal Command
 type CliCommand = clap::Command;
@@ -13,28 +26,199 @@ pub trait ToCliCommand {
     fn to_cli(&self) -> CliCommand;
 }
 
+/// The names of the built-in options of the cli, including their further names, that its own root
+/// options of the same names replace.
+pub fn replaced_built_in_options(metadata: &CliMetadata) -> Vec<&'static str> {
+    let history_options = [HISTORY_OPTION, RERUN_OPTION];
+
+    [
+        INTERACTIVE_OPTION,
+        YES_OPTION,
+        PICK_OPTION,
+        REPL_OPTION,
+        DRY_RUN_OPTION,
+        EXPLAIN_ALIAS,
+        WATCH_OPTION,
+    ]
+    .into_iter()
+    .chain(
+        history_options
+            .into_iter()
+            .filter(|_| metadata.audit_log.is_on()),
+    )
+    .filter(|name| metadata.options.iter().any(|option| option.name == *name))
+    .collect()
+}
+
+/// The names of the options every command of the cli has: its root options, which are global,
+/// including the names turning negatable ones off, and the global built-in options.
+pub fn global_option_names(metadata: &CliMetadata) -> Vec<String> {
+    metadata
+        .options
+        .iter()
+        .flat_map(|option| {
+            let negation = (option.kind == OptionKind::Negatable).then(|| option.negation());
+
+            std::iter::once(option.name.clone()).chain(negation)
+        })
+        .chain(
+            [
+                INTERACTIVE_OPTION,
+                YES_OPTION,
+                PICK_OPTION,
+                REPL_OPTION,
+                DRY_RUN_OPTION,
+                EXPLAIN_ALIAS,
+                WATCH_OPTION,
+            ]
+            .map(str::to_owned),
+        )
+        .collect()
+}
+
 /// Converts an entire Model to a CliCommand
 impl ToCliCommand for Model {
     fn to_cli(&self) -> CliCommand {
-        self.commands.iter().fold(top_level(), |cli, command| {
-            cli.subcommand(command.as_ref().to_cli())
-        })
+        self.commands
+            .iter()
+            .fold(top_level(&self.metadata), |cli, command| {
+                cli.subcommand(command.as_ref().to_cli())
+            })
     }
 }
 
-fn top_level() -> CliCommand {
-    clap::Command::new("easy-cli")
-        .version("0.1.0")
-        .subcommand_required(true)
+fn top_level(metadata: &CliMetadata) -> CliCommand {
+    let mut cli_command = clap::Command::new(
+        metadata
+            .name
+            .clone()
+            .unwrap_or(DEFAULT_ROOT_NAME.to_owned()),
+    )
+    .subcommand_required(true)
+    .version(
+        metadata
+            .version
+            .clone()
+            .unwrap_or(DEFAULT_ROOT_VERSION.to_owned()),
+    );
+
+    if let Some(author) = metadata.author.as_ref() {
+        cli_command = cli_command.author(author);
+    }
+
+    if let Some(text) = metadata.description.as_ref() {
+        cli_command = cli_command.about(text);
+    }
+
+    if let Some(text) = metadata.after_help.as_ref() {
+        cli_command = cli_command.after_help(text);
+    }
+
+    // The root options are global, so they are shown in the help of every command
+    cli_command = metadata
+        .options
+        .iter()
+        .flat_map(|option| option_args(option, true))
+        .fold(cli_command, CliCommand::arg);
+
+    let flags = [
+        (
+            INTERACTIVE_OPTION,
+            None,
+            "Ask for missing required arguments",
+        ),
+        (
+            YES_OPTION,
+            None,
+            "Run commands without asking for confirmation",
+        ),
+        (PICK_OPTION, None, "Choose the command to run from a list"),
+        (
+            REPL_OPTION,
+            None,
+            "Start a session for running commands one after the other",
+        ),
+        (
+            DRY_RUN_OPTION,
+            Some(EXPLAIN_ALIAS),
+            "Show how the command would be run, without running it",
+        ),
+    ];
+
+    let is_defined = |name: &str| metadata.options.iter().any(|option| option.name == name);
+
+    // Unless the cli defines an option of the same name itself
+    if !is_defined(WATCH_OPTION) {
+        cli_command = cli_command.arg(
+            Arg::new(WATCH_OPTION)
+                .long(WATCH_OPTION)
+                .value_name("GLOB")
+                .global(true)
+                .action(ArgAction::Append)
+                .help("Run the command again whenever files matching the glob change"),
+        );
+    }
+
+    // The history is kept unless the cli turns it off
+    let is_audited = metadata.audit_log.is_on();
+
+    if is_audited && !is_defined(HISTORY_OPTION) {
+        cli_command = cli_command.arg(
+            Arg::new(HISTORY_OPTION)
+                .long(HISTORY_OPTION)
+                .value_name("FILTER")
+                .num_args(0..=1)
+                .help("List the commands run, or those containing the filter"),
+        );
+    }
+
+    if is_audited && !is_defined(RERUN_OPTION) {
+        cli_command = cli_command.arg(
+            Arg::new(RERUN_OPTION)
+                .long(RERUN_OPTION)
+                .value_name("ENTRY")
+                .value_parser(clap::value_parser!(usize))
+                .help("Run the command of an entry of the history again"),
+        );
+    }
+
+    flags
+        .into_iter()
+        .filter(|(name, _, _)| !is_defined(name))
+        .fold(cli_command, |cli_command, (name, alias, help)| {
+            cli_command.arg(
+                Arg::new(name)
+                    .long(name)
+                    .visible_aliases(alias.filter(|alias| !is_defined(alias)))
+                    .global(true)
+                    .num_args(0)
+                    .action(ArgAction::SetTrue)
+                    .help(help),
+            )
+        })
 }
 
 impl<C: ?Sized + Command> ToCliCommand for C {
     fn to_cli(&self) -> CliCommand {
-        let mut cli_command = CliCommand::new(self.name().to_owned()).about(
-            self.description()
-                .map(|str| str.to_owned())
-                .unwrap_or(format!("Runs the {} script", self.name())),
-        );
+        let about = self
+            .description()
+            .map(|str| str.to_owned())
+            .unwrap_or(format!("Runs the {} script", self.name()));
+
+        let mut cli_command = CliCommand::new(self.name().to_owned())
+            .about(about.clone())
+            .visible_aliases(self.aliases())
+            .hide(self.hidden())
+            .subcommand_required(self.sub_command_required());
+
+        // The long help extends the description with the details
+        if let Some(details) = self.details() {
+            cli_command = cli_command.long_about(format!("{}\n\n{}", about, details));
+        }
+
+        if !self.examples().is_empty() {
+            cli_command = cli_command.after_long_help(examples_help(self.examples()));
+        }
 
         let make_opts_global = self.has_sub_commands();
 
@@ -42,13 +226,16 @@ impl<C: ?Sized + Command> ToCliCommand for C {
         cli_command = self
             .options()
             .iter()
-            .map(|option| option.to_arg(make_opts_global))
+            .flat_map(|option| option_args(option, make_opts_global))
             .chain(
                 // Then add the Arguments; never global (in fact, a command with subcommands should not have args)
                 self.args().iter().map(|arg| arg.to_arg(false)),
             )
             .fold(cli_command, CliCommand::arg);
 
+        // The relations refer to the options and arguments, so they must be added before
+        cli_command = self.relations().iter().fold(cli_command, add_relation);
+
         // Add the sub_commands
         self.sub_commands()
             .iter()
@@ -59,6 +246,36 @@ impl<C: ?Sized + Command> ToCliCommand for C {
     }
 }
 
+/// Adds a relation between the options and arguments of a command to its clap command, so that
+/// clap checks it when parsing.
+fn add_relation(cli_command: CliCommand, relation: &ArgRelation) -> CliCommand {
+    match relation {
+        ArgRelation::Conflicts(names) => names.iter().fold(cli_command, |cli_command, name| {
+            let others = names.iter().filter(|other| *other != name);
+            cli_command.mut_arg(name, |arg| arg.conflicts_with_all(others))
+        }),
+        ArgRelation::Requires(name, required) => cli_command.mut_arg(name, |arg| {
+            required
+                .iter()
+                .fold(arg, |arg, required| arg.requires(required))
+        }),
+        ArgRelation::Group {
+            name,
+            required,
+            members,
+        } => cli_command.group(ArgGroup::new(name).args(members).required(*required)),
+    }
+}
+
+/// Renders the examples of a command as a section of the long help.
+fn examples_help(examples: &[String]) -> String {
+    examples
+        .iter()
+        .fold("Examples:".to_owned(), |help, example| {
+            format!("{}\n  {}", help, example)
+        })
+}
+
 /// Converts an implementor to a clap Arg
 trait ToArg {
     fn to_arg(&self, global: bool) -> Arg;
@@ -95,9 +312,16 @@ impl ToArg for CommandArg {
             cli_arg = cli_arg.num_args(1);
         }
 
-        cli_arg = cli_arg.value_hint(self.arg_type.to_value_hint());
+        cli_arg = with_heading(
+            cli_arg.value_hint(self.arg_type.to_value_hint()),
+            self.heading.as_ref(),
+        );
+
+        if let Some(value_name) = self.value_name.as_ref() {
+            cli_arg = cli_arg.value_name(value_name);
+        }
 
-        cli_arg
+        with_env(cli_arg, self.env.as_ref())
     }
 }
 
@@ -109,20 +333,70 @@ impl ToArg for CommandOption {
             .long(self.name.to_owned())
             .help(self.description.as_deref().unwrap_or("").to_string());
 
-        if !self.has_param {
-            cli_option = cli_option.num_args(0).action(ArgAction::SetTrue);
-        } else {
-            cli_option = cli_option.value_parser(StringValueParser::default());
+        cli_option = with_heading(cli_option, self.heading.as_ref());
+
+        if let (true, Some(value_name)) = (self.has_param, self.value_name.as_ref()) {
+            cli_option = cli_option.value_name(value_name);
         }
 
-        cli_option
+        cli_option = match (self.has_param, self.kind) {
+            (false, OptionKind::Count) => cli_option.num_args(0).action(ArgAction::Count),
+            (false, OptionKind::Negatable) => cli_option
+                .num_args(0)
+                .action(ArgAction::SetTrue)
+                .overrides_with(self.negation()),
+            (false, _) => cli_option.num_args(0).action(ArgAction::SetTrue),
+            (true, OptionKind::Many) => cli_option
+                .value_parser(StringValueParser::default())
+                .action(ArgAction::Append),
+            (true, _) => cli_option.value_parser(StringValueParser::default()),
+        };
+
+        with_env(cli_option, self.env.as_ref())
+    }
+}
+
+/// Converts an option to the clap Args for it: the option itself and, if it is negatable, the
+/// flag turning it off.
+fn option_args(option: &CommandOption, global: bool) -> Vec<Arg> {
+    let mut args = vec![option.to_arg(global)];
+
+    if option.kind == OptionKind::Negatable {
+        let negation = Arg::new(option.negation())
+            .global(global)
+            .long(option.negation())
+            .help(format!("Turns off --{}", option.name))
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .overrides_with(option.name.to_owned());
+
+        args.push(with_heading(negation, option.heading.as_ref()));
+    }
+
+    args
+}
+
+/// Lists the arg under the heading in help, if any, rather than the default one.
+fn with_heading(cli_arg: Arg, heading: Option<&String>) -> Arg {
+    match heading {
+        Some(heading) => cli_arg.help_heading(heading),
+        None => cli_arg,
+    }
+}
+
+/// Takes the value of the arg from the environment variable, if any, when it is not given. The
+/// variable is shown in help, with its value unless it is secret.
+fn with_env(cli_arg: Arg, env: Option<&EnvVar>) -> Arg {
+    match env {
+        Some(env) => cli_arg.env(&env.name).hide_env_values(env.secret),
+        None => cli_arg,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::model::test::NO_DESCRIPTION;
-    use crate::model::{ArgType, EmbeddedCommand, ScriptCommand};
+    use crate::model::{ArgType, AuditLog, EmbeddedCommand, GroupCommand, ScriptCommand, WithMeta};
 
     use super::*;
 
@@ -199,6 +473,92 @@ mod tests {
         );
     }
 
+    #[test]
+    fn option_kind_sets_action() {
+        let command = script_command(
+            vec![
+                opt("verbose").with_kind(OptionKind::Count),
+                CommandOption::new("tag", None, true, NO_DESCRIPTION).with_kind(OptionKind::Many),
+                opt("color").with_kind(OptionKind::Negatable),
+            ],
+            vec![],
+            vec![],
+        );
+
+        let matches = command
+            .to_cli()
+            .try_get_matches_from(["test", "-vvv", "--tag", "a", "--tag", "b", "--no-color"])
+            .unwrap();
+
+        assert_eq!(matches.get_count("verbose"), 3);
+        assert_eq!(
+            matches
+                .get_many::<String>("tag")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert!(!matches.get_flag("color"));
+        assert!(matches.get_flag("no-color"));
+
+        // The last of the flag and its negation wins
+        let matches = command
+            .to_cli()
+            .try_get_matches_from(["test", "--no-color", "--color"])
+            .unwrap();
+
+        assert!(matches.get_flag("color"));
+        assert!(!matches.get_flag("no-color"));
+    }
+
+    #[test]
+    fn value_name_and_heading_transfer() {
+        let option = CommandOption::new("output", None, true, NO_DESCRIPTION)
+            .with_value_name(Some("FILE".to_string()))
+            .with_heading(Some("Output".to_string()));
+
+        let cli_option = option.to_arg(false);
+
+        assert_eq!(
+            cli_option.get_value_names().unwrap(),
+            [clap::builder::Str::from("FILE")]
+        );
+        assert_eq!(cli_option.get_help_heading(), Some("Output"));
+
+        // Flags have no value to name
+        let flag = opt("force").with_value_name(Some("FILE".to_string()));
+        assert!(flag.to_arg(false).get_value_names().is_none());
+
+        let arg = CommandArg::new("query", false, false, ArgType::Unknown, NO_DESCRIPTION)
+            .with_value_name(Some("SQL".to_string()));
+
+        let cli_arg = arg.to_arg(false);
+        assert_eq!(
+            cli_arg.get_value_names().unwrap(),
+            [clap::builder::Str::from("SQL")]
+        );
+        assert_eq!(cli_arg.get_help_heading(), None);
+    }
+
+    #[test]
+    fn env_is_fallback() {
+        let option = CommandOption::new("token", None, true, NO_DESCRIPTION)
+            .with_env(Some(EnvVar::new("EASY_CLI_TEST_TOKEN", true)));
+
+        let cli_option = option.to_arg(false);
+
+        assert_eq!(
+            cli_option.get_env(),
+            Some(std::ffi::OsStr::new("EASY_CLI_TEST_TOKEN"))
+        );
+        assert!(cli_option.is_hide_env_values_set());
+
+        let arg = CommandArg::new("file", false, false, ArgType::File, NO_DESCRIPTION)
+            .with_env(Some(EnvVar::new("EASY_CLI_TEST_FILE", false)));
+
+        assert!(!arg.to_arg(false).is_hide_env_values_set());
+    }
+
     #[test]
     fn type_is_value_hint() {
         let arg = CommandArg::new("TestArg", false, true, ArgType::Dir, Some("My description"));
@@ -216,6 +576,53 @@ mod tests {
         assert_eq!(command.get_version(), Some("0.1.0"));
     }
 
+    #[test]
+    fn from_applies_cli_metadata() {
+        let model = Model::new(vec![]).with_metadata(CliMetadata {
+            name: Some("mycli".to_string()),
+            version: Some("1.2.3".to_string()),
+            author: Some("Jane Doe".to_string()),
+            description: Some("My tool".to_string()),
+            after_help: Some("See the wiki".to_string()),
+            options: vec![opt("verbose")],
+            ..CliMetadata::default()
+        });
+
+        let command: CliCommand = model.to_cli();
+
+        assert_eq!(command.get_name(), "mycli");
+        assert_eq!(command.get_version(), Some("1.2.3"));
+        assert_eq!(command.get_author(), Some("Jane Doe"));
+        assert_eq!(command.get_about().unwrap().to_string(), "My tool");
+        assert_eq!(
+            command.get_after_help().unwrap().to_string(),
+            "See the wiki"
+        );
+
+        // The root options are global
+        let verbose = command.get_arguments().next().unwrap();
+        assert_eq!(verbose.get_id(), "verbose");
+        assert!(verbose.is_global_set());
+    }
+
+    #[test]
+    fn replaced_built_in_options_names_root_options_of_built_in_names() {
+        let mut metadata = CliMetadata {
+            options: vec![opt("yes"), opt("explain"), opt("history"), opt("verbose")],
+            ..CliMetadata::default()
+        };
+
+        assert_eq!(
+            replaced_built_in_options(&metadata),
+            ["yes", "explain", "history"]
+        );
+
+        // The history options are not built in if the cli is not audited
+        metadata.audit_log = AuditLog::Off;
+
+        assert_eq!(replaced_built_in_options(&metadata), ["yes", "explain"]);
+    }
+
     #[test]
     fn from_creates_easy_cli_command_with_subcommands() {
         let command = ScriptCommand::new(
@@ -346,6 +753,33 @@ mod tests {
         assert_eq!(subs[1].get_name(), "sub2");
     }
 
+    #[test]
+    fn to_cli_adds_details_to_long_help() {
+        let command = script_command(vec![], vec![], vec![])
+            .with_details(Some("More about test".to_string()));
+
+        let cli_command: CliCommand = command.to_cli();
+
+        assert_eq!(cli_command.get_about().unwrap().to_string(), "echo test");
+        assert_eq!(
+            cli_command.get_long_about().unwrap().to_string(),
+            "echo test\n\nMore about test"
+        );
+    }
+
+    #[test]
+    fn to_cli_adds_examples_after_long_help() {
+        let command = script_command(vec![], vec![], vec![])
+            .with_examples(vec!["test foo".to_string(), "test bar".to_string()]);
+
+        let cli_command: CliCommand = command.to_cli();
+
+        assert_eq!(
+            cli_command.get_after_long_help().unwrap().to_string(),
+            "Examples:\n  test foo\n  test bar"
+        );
+    }
+
     #[test]
     fn to_cli_makes_super_options_global() {
         let command = ScriptCommand::new(
@@ -385,4 +819,59 @@ mod tests {
         assert!(!args[0].is_global_set());
         assert!(!args[1].is_global_set());
     }
+
+    #[test]
+    fn to_cli_checks_relations() {
+        let to_names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+        let mut file = arg("file");
+        file.optional = true;
+
+        let command = script_command(
+            vec![opt("json"), opt("yaml"), opt("user"), opt("password")],
+            vec![file],
+            vec![],
+        )
+        .with_relations(vec![
+            ArgRelation::Conflicts(to_names(&["json", "yaml"])),
+            ArgRelation::Requires("user".to_string(), to_names(&["password"])),
+            ArgRelation::Group {
+                name: "input".to_string(),
+                required: true,
+                members: to_names(&["file", "user"]),
+            },
+        ]);
+
+        let parse = |args: &[&str]| {
+            command
+                .to_cli()
+                .try_get_matches_from(std::iter::once("cmd").chain(args.iter().copied()))
+        };
+
+        assert!(parse(&["--json", "in.txt"]).is_ok());
+        assert!(parse(&["--user", "--password"]).is_ok());
+        assert!(parse(&["--json", "--yaml", "in.txt"]).is_err());
+        assert!(parse(&["--user"]).is_err());
+        assert!(parse(&["--json"]).is_err());
+        assert!(parse(&["--user", "--password", "in.txt"]).is_err());
+    }
+
+    #[test]
+    fn to_cli_requires_sub_command_of_group() {
+        let command = GroupCommand::new(
+            "group",
+            Some("A group"),
+            vec![opt("foo")],
+            vec![Box::new(embedded_command(1, vec![], vec![]))],
+        )
+        .with_aliases(vec!["g".to_string()])
+        .with_hidden(true);
+
+        let cli_command: CliCommand = command.to_cli();
+
+        assert!(cli_command.is_subcommand_required_set());
+        assert!(cli_command.is_hide_set());
+        assert_eq!(cli_command.get_visible_aliases().collect::<Vec<_>>(), ["g"]);
+        assert!(cli_command.get_arguments().next().unwrap().is_global_set());
+    }
 }
@@ -4,8 +4,13 @@ use regex::Regex;
 /// Strips the file type suffix -  that is, everything after the last '.' - from the given file name.
 pub(crate) fn strip_file_suffix(name: &str) -> String {
     lazy_static! {
-        static ref FILE_SUFFIX: Regex = Regex::new(".[^.]*$").unwrap();
+        static ref FILE_SUFFIX: Regex = Regex::new(r"\.[^.]*$").unwrap();
     }
 
-    FILE_SUFFIX.replace(&name, "").to_string()
+    FILE_SUFFIX.replace(name, "").to_string()
+}
+
+/// Quotes the given string for use as a single word in a shell script.
+pub(crate) fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
 }
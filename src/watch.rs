@@ -0,0 +1,355 @@
+//! Watch mode, in which the selected command is run again whenever files matching a glob change.
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use clap::ArgMatches;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::debug;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::exec::{prepare_execution, Dispatch, Execution, RootOptionEnv};
+use crate::model::{Command, Model};
+use crate::supervise::{
+    forward_signals, received_signal, restore_signals, stop_group, POLL_INTERVAL,
+};
+
+/// The name of the global option giving the globs of the files to watch.
+pub const WATCH_OPTION: &str = "watch";
+
+/// How long the files must be left unchanged before the command is run again, so that a burst of
+/// changes, e.g. by a save or checkout, runs it only once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The globs given with `--watch`; none if the cli defines an option of that name itself.
+pub fn watch_globs(model: &Model, matches: &ArgMatches) -> Vec<String> {
+    if model
+        .metadata
+        .options
+        .iter()
+        .any(|option| option.name == WATCH_OPTION)
+    {
+        return vec![];
+    }
+
+    matches
+        .get_many::<String>(WATCH_OPTION)
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// Runs the command selected by the matches, as in executed mode, and runs it again whenever files
+/// below the current directory that match one of the globs change, until interrupted. Each run is
+/// in a process group of its own, which is stopped with everything the run started when the files
+/// change before it has finished, or when watching is interrupted. Runs do not read from the
+/// terminal, as it stays with the watch. If `output_to_stderr`, the output of the command is sent
+/// to stderr, as stdout is to be evaluated.
+pub fn run_watched(
+    model: &Model,
+    arg_matches: &ArgMatches,
+    globs: &[String],
+    output_to_stderr: bool,
+) {
+    let (command, args, environment) = match prepare_execution(model, arg_matches) {
+        // The hooks are not run, nor the runs logged, as runs may be stopped at any time
+        Ok(Execution::Run(dispatch)) => {
+            report_left_out(&dispatch, output_to_stderr);
+            (dispatch.command, dispatch.args, dispatch.environment)
+        }
+        Ok(Execution::Explained(explanation)) => {
+            eprint!("{}", explanation);
+            return;
+        }
+        Ok(Execution::Aborted) => {
+            eprintln!("Aborted");
+            return;
+        }
+        Err(err) => {
+            eprint!("{}", err.render().ansi());
+            return;
+        }
+    };
+
+    let globs = match glob_set(globs) {
+        Ok(globs) => globs,
+        Err(e) => {
+            eprintln!("Invalid glob: {}", e);
+            return;
+        }
+    };
+
+    let root = match std::env::current_dir() {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("Error in reading the current directory: {}", e);
+            return;
+        }
+    };
+
+    let (sender, receiver) = channel();
+
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error in watching files: {}", e);
+            return;
+        }
+    };
+
+    if let Err(e) = watcher.watch(&root, RecursiveMode::Recursive) {
+        eprintln!("Error in watching {}: {}", root.to_string_lossy(), e);
+        return;
+    }
+
+    // Interrupting the watch stops the run as well, which is in a process group of its own
+    let signals = forward_signals();
+
+    let mut child = spawn(command, &args, &environment, output_to_stderr);
+
+    while let Some(changed) = next_change(&receiver, &root, &globs) {
+        stop_run(child.as_mut());
+
+        eprintln!("{} changed, running again", changed.to_string_lossy());
+
+        child = spawn(command, &args, &environment, output_to_stderr);
+    }
+
+    stop_run(child.as_mut());
+    restore_signals(signals);
+}
+
+/// Reports what watched runs leave out that the command would have otherwise: they are run as
+/// plain processes, as they may be stopped at any time, and executed even in evaluated mode.
+fn report_left_out(dispatch: &Dispatch, evaluated: bool) {
+    let left_out = left_out(dispatch);
+
+    if !left_out.is_empty() {
+        eprintln!(
+            "Watched runs of '{}' leave out its {}",
+            dispatch.command.name(),
+            left_out.join(", ")
+        );
+    }
+
+    if evaluated {
+        eprintln!(
+            "Watched runs of '{}' are executed, not evaluated by the shell",
+            dispatch.command.name()
+        );
+    }
+}
+
+/// What the dispatched command has that watched runs leave out.
+fn left_out(dispatch: &Dispatch) -> Vec<&'static str> {
+    let command = dispatch.command;
+
+    [
+        (!dispatch.hooks.is_empty(), "hooks"),
+        (command.timeout().is_some(), "timeout"),
+        (command.retry().is_some(), "retries"),
+        (dispatch.entry.is_some(), "history entries"),
+    ]
+    .into_iter()
+    .filter_map(|(has, name)| has.then_some(name))
+    .collect()
+}
+
+/// Waits for a file matching the globs to change and for the changes to settle, and returns the
+/// file; `None` once a signal to stop is received or the files are no longer watched.
+fn next_change(
+    receiver: &Receiver<notify::Result<Event>>,
+    root: &Path,
+    globs: &GlobSet,
+) -> Option<PathBuf> {
+    let mut changed = None;
+
+    loop {
+        if received_signal().is_some() {
+            return None;
+        }
+
+        // Once a file has changed, wait for the changes to settle
+        let wait = if changed.is_some() {
+            DEBOUNCE
+        } else {
+            POLL_INTERVAL
+        };
+
+        // Each message is an event, or an error in watching
+        match receiver.recv_timeout(wait) {
+            Ok(event) if changed.is_none() => {
+                changed = event
+                    .ok()
+                    .and_then(|event| changed_file(&event, root, globs));
+            }
+            Ok(_) => {}
+            Err(RecvTimeoutError::Timeout) if changed.is_some() => return changed,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Stops the process group of the run, if it is still running.
+fn stop_run(child: Option<&mut Child>) {
+    if let Some(running) = child {
+        if matches!(running.try_wait(), Ok(None)) {
+            debug!("stopping the run");
+            let _ = stop_group(running);
+        }
+    }
+}
+
+/// Starts the command with the given args and environment variables, reporting a failure to start
+/// it.
+fn spawn(
+    command: &dyn Command,
+    args: &[String],
+    environment: &RootOptionEnv,
+    output_to_stderr: bool,
+) -> Option<Child> {
+    let Some(mut process) = command.process(args.to_vec()) else {
+        eprintln!("The command '{}' cannot be run", command.name());
+        return None;
+    };
+
+    environment.apply(&mut process);
+
+    if output_to_stderr {
+        process.stdout(io::stderr());
+    }
+
+    // The process group is named after the process leading it
+    process
+        .stdin(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map_err(|e| eprintln!("Error in executing command : {}", e))
+        .ok()
+}
+
+fn glob_set(globs: &[String]) -> Result<GlobSet, globset::Error> {
+    globs
+        .iter()
+        .try_fold(GlobSetBuilder::new(), |mut builder, glob| {
+            builder.add(Glob::new(glob)?);
+            Ok(builder)
+        })?
+        .build()
+}
+
+/// The first file changed by the event that matches the globs, relative to the watched directory.
+/// Files that were only read are not changed.
+fn changed_file(event: &Event, root: &Path, globs: &GlobSet) -> Option<PathBuf> {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return None;
+    }
+
+    event
+        .paths
+        .iter()
+        .map(|path| path.strip_prefix(root).unwrap_or(path).to_path_buf())
+        .find(|path| globs.is_match(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, CreateKind};
+
+    use crate::model::{CliMetadata, Hook, Hooks, ScriptCommand, WithMeta};
+    use crate::transform::ToCliCommand;
+
+    use super::*;
+
+    #[test]
+    fn left_out_names_what_watched_runs_skip() {
+        let script = PathBuf::from("/tmp/build.sh");
+        let build = ScriptCommand::new(
+            "build".to_owned(),
+            None,
+            script.clone(),
+            vec![],
+            vec![],
+            vec![],
+        )
+        .with_timeout(Some(Duration::from_secs(30)))
+        .with_hooks(Hooks::new(
+            Some(Hook::new(script, Some("login".to_owned()))),
+            None,
+        ));
+
+        let plain = ScriptCommand::new(
+            "plain".to_owned(),
+            None,
+            PathBuf::from("/tmp/plain.sh"),
+            vec![],
+            vec![],
+            vec![],
+        );
+
+        let model = Model::new(vec![Box::new(build), Box::new(plain)]).with_metadata(CliMetadata {
+            name: Some("mycli".to_owned()),
+            ..CliMetadata::default()
+        });
+
+        let left_out_of = |name: &str| {
+            let matches = model.to_cli().get_matches_from(["mycli", name]);
+
+            match prepare_execution(&model, &matches) {
+                Ok(Execution::Run(dispatch)) => left_out(&dispatch),
+                _ => panic!("The command is not run"),
+            }
+        };
+
+        assert_eq!(
+            left_out_of("build"),
+            ["hooks", "timeout", "history entries"]
+        );
+        assert_eq!(left_out_of("plain"), ["history entries"]);
+    }
+
+    fn event(kind: EventKind, path: &str) -> Event {
+        Event::new(kind).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn changed_file_matches_globs_relative_to_root() {
+        let globs = glob_set(&["*.rs".to_owned(), "Cargo.toml".to_owned()]).unwrap();
+        let root = Path::new("/work");
+
+        assert_eq!(
+            changed_file(
+                &event(EventKind::Create(CreateKind::File), "/work/src/main.rs"),
+                root,
+                &globs
+            ),
+            Some(PathBuf::from("src/main.rs"))
+        );
+        assert_eq!(
+            changed_file(&event(EventKind::Any, "/work/Cargo.toml"), root, &globs),
+            Some(PathBuf::from("Cargo.toml"))
+        );
+        assert_eq!(
+            changed_file(&event(EventKind::Any, "/work/README.md"), root, &globs),
+            None
+        );
+        assert_eq!(
+            changed_file(
+                &event(EventKind::Access(AccessKind::Any), "/work/src/main.rs"),
+                root,
+                &globs
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn glob_set_rejects_invalid_globs() {
+        assert!(glob_set(&["src/[".to_owned()]).is_err());
+    }
+}